@@ -0,0 +1,2 @@
+ALTER TABLE oncall_syncs DROP COLUMN oncall_provider;
+ALTER TABLE notified_slack_channel DROP COLUMN oncall_provider;
@@ -0,0 +1,3 @@
+ALTER TABLE oncall_syncs ADD COLUMN oncall_provider VARCHAR NOT NULL DEFAULT 'opsgenie';
+
+ALTER TABLE notified_slack_channel ADD COLUMN oncall_provider VARCHAR NOT NULL DEFAULT 'opsgenie';
@@ -0,0 +1,3 @@
+ALTER TABLE oncall_syncs ADD COLUMN chat_provider VARCHAR NOT NULL DEFAULT 'slack';
+
+ALTER TABLE notified_slack_channel ADD COLUMN chat_provider VARCHAR NOT NULL DEFAULT 'slack';
@@ -0,0 +1,19 @@
+CREATE TABLE audit_events (
+  id INTEGER PRIMARY KEY AUTOINCREMENT NOT NULL,
+  created_at BIGINT NOT NULL,
+  actor VARCHAR NOT NULL,
+  action VARCHAR NOT NULL,
+  outcome VARCHAR NOT NULL,
+  error TEXT,
+  oncall_sync_id INTEGER,
+  notification_id INTEGER,
+  oncall_provider VARCHAR,
+  oncall_id VARCHAR,
+  chat_provider VARCHAR,
+  user_group_id VARCHAR,
+  slack_channel_id VARCHAR,
+  previous_value TEXT,
+  new_value TEXT
+);
+
+CREATE INDEX audit_events_created_at ON audit_events (created_at);
@@ -0,0 +1,11 @@
+CREATE TABLE shifts (
+  id INTEGER PRIMARY KEY AUTOINCREMENT NOT NULL,
+  oncall_provider VARCHAR NOT NULL,
+  oncall_id VARCHAR NOT NULL,
+  user_id VARCHAR NOT NULL,
+  started_at BIGINT NOT NULL,
+  ended_at BIGINT
+);
+
+CREATE INDEX shifts_oncall ON shifts (oncall_provider, oncall_id);
+CREATE INDEX shifts_started_at ON shifts (started_at);
@@ -0,0 +1,2 @@
+ALTER TABLE oncall_syncs DROP COLUMN dry_run;
+ALTER TABLE notified_slack_channel DROP COLUMN dry_run;
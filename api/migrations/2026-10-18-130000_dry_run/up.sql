@@ -0,0 +1,3 @@
+ALTER TABLE oncall_syncs ADD COLUMN dry_run BOOLEAN NOT NULL DEFAULT 0;
+
+ALTER TABLE notified_slack_channel ADD COLUMN dry_run BOOLEAN NOT NULL DEFAULT 0;
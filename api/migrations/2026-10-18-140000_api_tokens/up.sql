@@ -0,0 +1,7 @@
+CREATE TABLE api_tokens (
+  id INTEGER PRIMARY KEY AUTOINCREMENT NOT NULL,
+  name VARCHAR UNIQUE NOT NULL,
+  token_hash VARCHAR UNIQUE NOT NULL,
+  role VARCHAR NOT NULL,
+  created_at BIGINT NOT NULL
+);
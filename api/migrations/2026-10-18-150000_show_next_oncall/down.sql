@@ -0,0 +1 @@
+ALTER TABLE notified_slack_channel DROP COLUMN show_next_oncall;
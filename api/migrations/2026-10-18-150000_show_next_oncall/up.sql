@@ -0,0 +1 @@
+ALTER TABLE notified_slack_channel ADD COLUMN show_next_oncall BOOLEAN NOT NULL DEFAULT 0;
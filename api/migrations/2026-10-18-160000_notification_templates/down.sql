@@ -0,0 +1,3 @@
+ALTER TABLE notified_slack_channel DROP COLUMN out_of_hours_template;
+ALTER TABLE notified_slack_channel DROP COLUMN message_template;
+ALTER TABLE notified_slack_channel DROP COLUMN topic_template;
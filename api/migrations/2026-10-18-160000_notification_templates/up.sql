@@ -0,0 +1,3 @@
+ALTER TABLE notified_slack_channel ADD COLUMN topic_template TEXT;
+ALTER TABLE notified_slack_channel ADD COLUMN message_template TEXT;
+ALTER TABLE notified_slack_channel ADD COLUMN out_of_hours_template TEXT;
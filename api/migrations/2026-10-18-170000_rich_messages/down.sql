@@ -0,0 +1 @@
+ALTER TABLE notified_slack_channel DROP COLUMN rich_messages;
@@ -0,0 +1 @@
+ALTER TABLE notified_slack_channel ADD COLUMN rich_messages BOOLEAN NOT NULL DEFAULT 0;
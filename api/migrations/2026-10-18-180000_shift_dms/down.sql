@@ -0,0 +1 @@
+ALTER TABLE user_mapping DROP COLUMN shift_dms_opt_out;
@@ -0,0 +1 @@
+ALTER TABLE user_mapping ADD COLUMN shift_dms_opt_out BOOLEAN NOT NULL DEFAULT 0;
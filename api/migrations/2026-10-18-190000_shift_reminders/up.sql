@@ -0,0 +1,14 @@
+CREATE TABLE shift_reminders (
+  id INTEGER PRIMARY KEY AUTOINCREMENT NOT NULL,
+  oncall_provider VARCHAR NOT NULL,
+  oncall_id VARCHAR NOT NULL,
+  chat_provider VARCHAR NOT NULL,
+  user_id VARCHAR NOT NULL,
+  shift_start BIGINT NOT NULL,
+  is_override BOOLEAN NOT NULL DEFAULT 0,
+  remind_at BIGINT NOT NULL,
+  sent_at BIGINT,
+  UNIQUE (oncall_provider, oncall_id, chat_provider, user_id, shift_start, remind_at)
+);
+
+CREATE INDEX shift_reminders_remind_at ON shift_reminders (remind_at);
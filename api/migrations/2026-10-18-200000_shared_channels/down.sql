@@ -0,0 +1,27 @@
+-- Only the oldest notification of each channel survives going back to one schedule per channel
+CREATE TABLE notified_slack_channel_old (
+  id INTEGER PRIMARY KEY AUTOINCREMENT NOT NULL,
+  oncall_id VARCHAR NOT NULL,
+  slack_channel_id VARCHAR UNIQUE NOT NULL,
+  oncall_provider VARCHAR NOT NULL DEFAULT 'opsgenie',
+  chat_provider VARCHAR NOT NULL DEFAULT 'slack',
+  dry_run BOOLEAN NOT NULL DEFAULT 0,
+  show_next_oncall BOOLEAN NOT NULL DEFAULT 0,
+  topic_template TEXT,
+  message_template TEXT,
+  out_of_hours_template TEXT,
+  rich_messages BOOLEAN NOT NULL DEFAULT 0
+);
+
+INSERT INTO notified_slack_channel_old (
+  id, oncall_id, slack_channel_id, oncall_provider, chat_provider, dry_run, show_next_oncall,
+  topic_template, message_template, out_of_hours_template, rich_messages
+)
+SELECT
+  id, oncall_id, slack_channel_id, oncall_provider, chat_provider, dry_run, show_next_oncall,
+  topic_template, message_template, out_of_hours_template, rich_messages
+FROM notified_slack_channel
+WHERE id IN (SELECT MIN(id) FROM notified_slack_channel GROUP BY slack_channel_id);
+
+DROP TABLE notified_slack_channel;
+ALTER TABLE notified_slack_channel_old RENAME TO notified_slack_channel;
@@ -0,0 +1,29 @@
+-- SQLite can't drop the UNIQUE constraint on slack_channel_id, so the table is rebuilt. A channel
+-- can now be notified about several schedules, but only once about each.
+CREATE TABLE notified_slack_channel_new (
+  id INTEGER PRIMARY KEY AUTOINCREMENT NOT NULL,
+  oncall_id VARCHAR NOT NULL,
+  slack_channel_id VARCHAR NOT NULL,
+  oncall_provider VARCHAR NOT NULL DEFAULT 'opsgenie',
+  chat_provider VARCHAR NOT NULL DEFAULT 'slack',
+  dry_run BOOLEAN NOT NULL DEFAULT 0,
+  show_next_oncall BOOLEAN NOT NULL DEFAULT 0,
+  topic_template TEXT,
+  message_template TEXT,
+  out_of_hours_template TEXT,
+  rich_messages BOOLEAN NOT NULL DEFAULT 0,
+  UNIQUE (oncall_provider, oncall_id, chat_provider, slack_channel_id)
+);
+
+INSERT INTO notified_slack_channel_new (
+  id, oncall_id, slack_channel_id, oncall_provider, chat_provider, dry_run, show_next_oncall,
+  topic_template, message_template, out_of_hours_template, rich_messages
+)
+SELECT
+  id, oncall_id, slack_channel_id, oncall_provider, chat_provider, dry_run, show_next_oncall,
+  topic_template, message_template, out_of_hours_template, rich_messages
+FROM notified_slack_channel;
+
+DROP TABLE notified_slack_channel;
+ALTER TABLE notified_slack_channel_new RENAME TO notified_slack_channel;
+CREATE INDEX notified_slack_channel_slack_channel_id ON notified_slack_channel (slack_channel_id);
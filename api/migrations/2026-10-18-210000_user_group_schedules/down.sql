@@ -0,0 +1,16 @@
+-- Only the oldest sync of each user group survives going back to one schedule per group
+CREATE TABLE oncall_syncs_old (
+  id INTEGER PRIMARY KEY AUTOINCREMENT NOT NULL,
+  oncall_id VARCHAR NOT NULL,
+  user_group_id VARCHAR UNIQUE NOT NULL,
+  oncall_provider VARCHAR NOT NULL DEFAULT 'opsgenie',
+  chat_provider VARCHAR NOT NULL DEFAULT 'slack',
+  dry_run BOOLEAN NOT NULL DEFAULT 0
+);
+
+INSERT INTO oncall_syncs_old (id, oncall_id, user_group_id, oncall_provider, chat_provider, dry_run)
+SELECT id, oncall_id, user_group_id, oncall_provider, chat_provider, dry_run FROM oncall_syncs
+WHERE id IN (SELECT MIN(id) FROM oncall_syncs GROUP BY user_group_id);
+
+DROP TABLE oncall_syncs;
+ALTER TABLE oncall_syncs_old RENAME TO oncall_syncs;
@@ -0,0 +1,18 @@
+-- SQLite can't drop the UNIQUE constraint on user_group_id, so the table is rebuilt. A user group
+-- can now be synced with several schedules, but only once with each.
+CREATE TABLE oncall_syncs_new (
+  id INTEGER PRIMARY KEY AUTOINCREMENT NOT NULL,
+  oncall_id VARCHAR NOT NULL,
+  user_group_id VARCHAR NOT NULL,
+  oncall_provider VARCHAR NOT NULL DEFAULT 'opsgenie',
+  chat_provider VARCHAR NOT NULL DEFAULT 'slack',
+  dry_run BOOLEAN NOT NULL DEFAULT 0,
+  UNIQUE (oncall_provider, oncall_id, chat_provider, user_group_id)
+);
+
+INSERT INTO oncall_syncs_new (id, oncall_id, user_group_id, oncall_provider, chat_provider, dry_run)
+SELECT id, oncall_id, user_group_id, oncall_provider, chat_provider, dry_run FROM oncall_syncs;
+
+DROP TABLE oncall_syncs;
+ALTER TABLE oncall_syncs_new RENAME TO oncall_syncs;
+CREATE INDEX oncall_syncs_user_group_id ON oncall_syncs (user_group_id);
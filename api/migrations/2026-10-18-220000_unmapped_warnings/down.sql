@@ -0,0 +1 @@
+ALTER TABLE shifts DROP COLUMN unmapped_warned_at;
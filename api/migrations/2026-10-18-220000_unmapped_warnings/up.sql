@@ -0,0 +1 @@
+ALTER TABLE shifts ADD COLUMN unmapped_warned_at BIGINT;
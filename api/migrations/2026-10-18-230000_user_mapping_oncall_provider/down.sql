@@ -0,0 +1,15 @@
+-- Only the oldest mapping of each user ID survives going back to OpsGenie-only mappings
+CREATE TABLE user_mapping_old (
+  id INTEGER PRIMARY KEY AUTOINCREMENT NOT NULL,
+  opsgenie_id VARCHAR UNIQUE NOT NULL,
+  slack_id VARCHAR UNIQUE NOT NULL,
+  shift_dms_opt_out BOOLEAN NOT NULL DEFAULT 0
+);
+
+INSERT INTO user_mapping_old (id, opsgenie_id, slack_id, shift_dms_opt_out)
+SELECT id, opsgenie_id, slack_id, shift_dms_opt_out
+FROM user_mapping
+WHERE id IN (SELECT MIN(id) FROM user_mapping GROUP BY opsgenie_id);
+
+DROP TABLE user_mapping;
+ALTER TABLE user_mapping_old RENAME TO user_mapping;
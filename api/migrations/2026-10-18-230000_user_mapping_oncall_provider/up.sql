@@ -0,0 +1,17 @@
+-- SQLite can't drop the UNIQUE constraint on opsgenie_id, so the table is rebuilt. User IDs are
+-- only unique within a provider.
+CREATE TABLE user_mapping_new (
+  id INTEGER PRIMARY KEY AUTOINCREMENT NOT NULL,
+  opsgenie_id VARCHAR NOT NULL,
+  slack_id VARCHAR UNIQUE NOT NULL,
+  shift_dms_opt_out BOOLEAN NOT NULL DEFAULT 0,
+  oncall_provider VARCHAR NOT NULL DEFAULT 'opsgenie',
+  UNIQUE (oncall_provider, opsgenie_id)
+);
+
+INSERT INTO user_mapping_new (id, opsgenie_id, slack_id, shift_dms_opt_out)
+SELECT id, opsgenie_id, slack_id, shift_dms_opt_out
+FROM user_mapping;
+
+DROP TABLE user_mapping;
+ALTER TABLE user_mapping_new RENAME TO user_mapping;
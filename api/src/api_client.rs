@@ -0,0 +1,206 @@
+use log::warn;
+use rand::Rng;
+use reqwest::{header::RETRY_AFTER, Client, RequestBuilder, Response, StatusCode};
+use std::{collections::HashMap, time::Duration};
+use tokio::{
+    sync::Mutex,
+    time::{sleep, Instant},
+};
+
+/// Attempts per request, including the first one.
+const MAX_ATTEMPTS: u32 = 5;
+const INITIAL_BACKOFF: Duration = Duration::from_millis(500);
+const MAX_BACKOFF: Duration = Duration::from_secs(30);
+
+/// How many requests can be made per `period`. Up to `requests` can be made at once.
+#[derive(Debug, Clone, Copy)]
+pub struct RateLimit {
+    pub requests: u32,
+    pub period: Duration,
+}
+
+impl RateLimit {
+    pub const fn per_minute(requests: u32) -> RateLimit {
+        RateLimit {
+            requests,
+            period: Duration::from_secs(60),
+        }
+    }
+}
+
+struct BucketState {
+    tokens: f64,
+    updated_at: Instant,
+    /// Set when the API told us to back off with a `Retry-After`
+    paused_until: Option<Instant>,
+}
+
+/// Token bucket limiting the requests made to one group of endpoints.
+struct TokenBucket {
+    capacity: f64,
+    tokens_per_sec: f64,
+    state: Mutex<BucketState>,
+}
+
+impl TokenBucket {
+    fn new(limit: RateLimit) -> TokenBucket {
+        let capacity = limit.requests.max(1) as f64;
+        TokenBucket {
+            capacity,
+            tokens_per_sec: capacity / limit.period.as_secs_f64(),
+            state: Mutex::new(BucketState {
+                tokens: capacity,
+                updated_at: Instant::now(),
+                paused_until: None,
+            }),
+        }
+    }
+
+    /// Waits until a request can be made.
+    async fn acquire(&self) {
+        loop {
+            let wait = {
+                let mut state = self.state.lock().await;
+                let now = Instant::now();
+                match state.paused_until {
+                    Some(paused_until) if paused_until > now => paused_until - now,
+                    _ => {
+                        state.paused_until = None;
+                        state.tokens = (state.tokens
+                            + (now - state.updated_at).as_secs_f64() * self.tokens_per_sec)
+                            .min(self.capacity);
+                        state.updated_at = now;
+                        if state.tokens >= 1.0 {
+                            state.tokens -= 1.0;
+                            return;
+                        }
+                        Duration::from_secs_f64((1.0 - state.tokens) / self.tokens_per_sec)
+                    }
+                }
+            };
+            sleep(wait).await;
+        }
+    }
+
+    /// Holds back every request for `duration`, and drains the bucket so they don't all go out at
+    /// once afterwards.
+    async fn pause(&self, duration: Duration) {
+        let mut state = self.state.lock().await;
+        let paused_until = Instant::now() + duration;
+        if state
+            .paused_until
+            .is_none_or(|current| current < paused_until)
+        {
+            state.paused_until = Some(paused_until);
+        }
+        state.tokens = 0.0;
+    }
+}
+
+/// HTTP client shared by every call to one API, so connections are reused. Requests are grouped
+/// into buckets (e.g. one per Slack method), each with its own rate limit. Rate limited requests
+/// are retried after the `Retry-After` the API gives us, and idempotent requests are also retried
+/// with exponential backoff on server errors and dropped connections.
+pub struct ApiClient {
+    name: &'static str,
+    client: Client,
+    buckets: HashMap<&'static str, TokenBucket>,
+    default_bucket: TokenBucket,
+}
+
+impl ApiClient {
+    /// `limits` gives the rate limit of each bucket. Requests to any other bucket share
+    /// `default_limit`.
+    pub fn new(
+        name: &'static str,
+        default_limit: RateLimit,
+        limits: &[(&'static str, RateLimit)],
+    ) -> ApiClient {
+        ApiClient {
+            name,
+            client: Client::new(),
+            buckets: limits
+                .iter()
+                .map(|(bucket, limit)| (*bucket, TokenBucket::new(*limit)))
+                .collect(),
+            default_bucket: TokenBucket::new(default_limit),
+        }
+    }
+
+    /// The underlying client, to build requests for [`ApiClient::send`].
+    pub fn http(&self) -> &Client {
+        &self.client
+    }
+
+    /// Sends the request once `bucket` allows it, retrying as described on [`ApiClient`]. Only set
+    /// `idempotent` if sending the request twice is harmless. Returns the last response if all
+    /// attempts failed.
+    pub async fn send(
+        &self,
+        bucket: &str,
+        idempotent: bool,
+        request: RequestBuilder,
+    ) -> reqwest::Result<Response> {
+        let token_bucket = self.buckets.get(bucket).unwrap_or(&self.default_bucket);
+        let mut attempt = 1;
+        loop {
+            token_bucket.acquire().await;
+            // Only streaming bodies can't be cloned, and we never send those
+            let attempt_request = match request.try_clone() {
+                Some(attempt_request) => attempt_request,
+                None => return request.send().await,
+            };
+            let result = attempt_request.send().await;
+
+            let retry_in = match &result {
+                Ok(response) if response.status() == StatusCode::TOO_MANY_REQUESTS => {
+                    let retry_in = retry_after(response).unwrap_or_else(|| backoff(attempt));
+                    token_bucket.pause(retry_in).await;
+                    Some(retry_in)
+                }
+                Ok(response) if idempotent && response.status().is_server_error() => {
+                    Some(backoff(attempt))
+                }
+                Err(e) if idempotent && (e.is_connect() || e.is_timeout()) => {
+                    Some(backoff(attempt))
+                }
+                _ => None,
+            };
+            match retry_in {
+                Some(retry_in) if attempt < MAX_ATTEMPTS => {
+                    warn!(
+                        "{} request to {} failed ({}), retrying in {:?}",
+                        self.name,
+                        bucket,
+                        match &result {
+                            Ok(response) => format!("HTTP {}", response.status()),
+                            Err(e) => format!("{}", e),
+                        },
+                        retry_in
+                    );
+                    sleep(retry_in).await;
+                    attempt += 1;
+                }
+                _ => return result,
+            }
+        }
+    }
+}
+
+fn retry_after(response: &Response) -> Option<Duration> {
+    response
+        .headers()
+        .get(RETRY_AFTER)
+        .and_then(|retry_after| retry_after.to_str().ok())
+        .and_then(|retry_after| retry_after.trim().parse().ok())
+        .map(Duration::from_secs)
+}
+
+/// Exponential backoff with up to 50% of jitter, so retries from concurrent workers spread out.
+fn backoff(attempt: u32) -> Duration {
+    let backoff = INITIAL_BACKOFF
+        .saturating_mul(2u32.saturating_pow(attempt - 1))
+        .min(MAX_BACKOFF);
+    let jitter_ms = rand::thread_rng().gen_range(0..=backoff.as_millis() as u64 / 2);
+    backoff + Duration::from_millis(jitter_ms)
+}
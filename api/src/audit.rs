@@ -0,0 +1,64 @@
+use crate::{db, models::NewAuditEvent};
+use actix_web::web;
+use log::warn;
+use std::{
+    fmt::Display,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+/// Actor recorded for changes made by the background workers.
+pub const SYSTEM_ACTOR: &str = "system";
+
+pub const OUTCOME_SUCCESS: &str = "success";
+pub const OUTCOME_FAILURE: &str = "failure";
+
+impl NewAuditEvent {
+    /// A successful `action` by `actor`, happening now.
+    pub fn new(actor: &str, action: &str) -> NewAuditEvent {
+        NewAuditEvent {
+            created_at: now(),
+            actor: actor.into(),
+            action: action.into(),
+            outcome: OUTCOME_SUCCESS.into(),
+            ..Default::default()
+        }
+    }
+
+    /// Sets the outcome from the result of the audited call.
+    pub fn with_result<T, E: Display>(mut self, result: &Result<T, E>) -> NewAuditEvent {
+        if let Err(e) = result {
+            self.outcome = OUTCOME_FAILURE.into();
+            self.error = Some(format!("{}", e));
+        }
+        self
+    }
+}
+
+/// Writes the event to the DB. Failing to audit should never fail the change itself, so errors
+/// are only logged.
+pub async fn record(event: NewAuditEvent) {
+    let action = event.action.clone();
+    match web::block(move || {
+        let conn = db::connection();
+        db::add_audit_event(&conn, &event)
+    })
+    .await
+    {
+        Err(e) => warn!("Error recording audit event {}: {:?}", action, e),
+        Ok(Err(e)) => warn!("Error recording audit event {}: {}", action, e),
+        Ok(Ok(())) => {}
+    }
+}
+
+/// Formats a list of users for the `previous_value`/`new_value` columns.
+pub fn format_users(users: &[String]) -> String {
+    users.join(",")
+}
+
+/// Current unix time in seconds, as stored in `created_at`.
+pub fn now() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .expect("system time is before the unix epoch")
+        .as_secs() as i64
+}
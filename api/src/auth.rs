@@ -0,0 +1,306 @@
+use crate::{db, ErrorResponse};
+use actix_web::{
+    body::EitherBody,
+    dev::{forward_ready, Service, ServiceRequest, ServiceResponse, Transform},
+    http::Method,
+    web, Error, HttpMessage, HttpResponse,
+};
+use diesel::{
+    backend::Backend,
+    deserialize::{self, FromSql},
+    serialize::{self, Output, ToSql},
+    sql_types::Text,
+    sqlite::Sqlite,
+};
+use futures::future::{ready, LocalBoxFuture, Ready};
+use log::{info, warn};
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::{env, fmt, io::Write, rc::Rc, str::FromStr};
+use subtle::ConstantTimeEq;
+
+/// Name recorded as the actor for requests made with `ADMIN_API_TOKEN`.
+const BOOTSTRAP_ADMIN_NAME: &str = "admin";
+/// Endpoints called by Slack and OpsGenie. They check their own signatures instead of API tokens.
+const EXTERNAL_PATH_PREFIXES: [&str; 2] = ["/slack/", "/webhooks/"];
+/// Endpoints managing the tokens themselves.
+const ADMIN_PATH_PREFIX: &str = "/api_tokens/";
+
+#[derive(thiserror::Error, Debug)]
+#[error("unknown role \"{0}\"")]
+pub struct UnknownRole(String);
+
+/// What an API token is allowed to do. Each role can do everything the previous ones can.
+#[derive(
+    Serialize,
+    Deserialize,
+    Debug,
+    Clone,
+    Copy,
+    PartialEq,
+    Eq,
+    PartialOrd,
+    Ord,
+    AsExpression,
+    FromSqlRow,
+)]
+#[serde(rename_all = "lowercase")]
+#[sql_type = "Text"]
+pub enum Role {
+    /// Read-only access
+    Viewer,
+    /// Can add and remove syncs, notifications and user mappings
+    Editor,
+    /// Can also manage API tokens
+    Admin,
+}
+
+impl Role {
+    pub const ALL: [Role; 3] = [Role::Viewer, Role::Editor, Role::Admin];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Role::Viewer => "viewer",
+            Role::Editor => "editor",
+            Role::Admin => "admin",
+        }
+    }
+
+    /// The role needed to call the endpoint at `path` with `method`, or None if the endpoint
+    /// doesn't take API tokens.
+    fn required_for(method: &Method, path: &str) -> Option<Role> {
+        if EXTERNAL_PATH_PREFIXES
+            .iter()
+            .any(|prefix| path.starts_with(prefix))
+        {
+            None
+        } else if path.starts_with(ADMIN_PATH_PREFIX) {
+            Some(Role::Admin)
+        } else if method == Method::GET || method == Method::HEAD {
+            Some(Role::Viewer)
+        } else {
+            Some(Role::Editor)
+        }
+    }
+}
+
+impl fmt::Display for Role {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for Role {
+    type Err = UnknownRole;
+
+    fn from_str(s: &str) -> Result<Role, UnknownRole> {
+        Role::ALL
+            .into_iter()
+            .find(|role| role.as_str() == s)
+            .ok_or_else(|| UnknownRole(s.into()))
+    }
+}
+
+impl ToSql<Text, Sqlite> for Role {
+    fn to_sql<W: Write>(&self, out: &mut Output<W, Sqlite>) -> serialize::Result {
+        ToSql::<Text, Sqlite>::to_sql(self.as_str(), out)
+    }
+}
+
+impl FromSql<Text, Sqlite> for Role {
+    fn from_sql(bytes: Option<&<Sqlite as Backend>::RawValue>) -> deserialize::Result<Self> {
+        let value = <String as FromSql<Text, Sqlite>>::from_sql(bytes)?;
+        Ok(value.parse()?)
+    }
+}
+
+/// Who made the request. Added to the request extensions by [`Authentication`], so handlers can
+/// take it as `web::ReqData<Identity>`.
+#[derive(Debug, Clone)]
+pub struct Identity {
+    pub name: String,
+    pub role: Role,
+}
+
+/// Returns a new random token. Only its hash is stored.
+pub fn generate_token() -> String {
+    let mut bytes = [0u8; 32];
+    rand::thread_rng().fill_bytes(&mut bytes);
+    hex::encode(bytes)
+}
+
+pub fn hash_token(token: &str) -> String {
+    hex::encode(Sha256::digest(token.as_bytes()))
+}
+
+/// Warns at startup if there's no way to get an admin token.
+pub fn check_config() {
+    if env::var("ADMIN_API_TOKEN").is_err() {
+        warn!("ADMIN_API_TOKEN is not set, only tokens already in the DB can use the API");
+    }
+}
+
+/// Looks up who the bearer token belongs to. `ADMIN_API_TOKEN` is always an admin, so the first
+/// tokens can be created.
+async fn identify(token: String) -> Result<Option<Identity>, String> {
+    if let Ok(admin_token) = env::var("ADMIN_API_TOKEN") {
+        if !admin_token.is_empty() && bool::from(admin_token.as_bytes().ct_eq(token.as_bytes())) {
+            return Ok(Some(Identity {
+                name: BOOTSTRAP_ADMIN_NAME.into(),
+                role: Role::Admin,
+            }));
+        }
+    }
+
+    let token_hash = hash_token(&token);
+    match web::block(move || {
+        let conn = db::connection();
+        db::get_api_token_by_hash(&conn, &token_hash)
+    })
+    .await
+    {
+        Err(blocking_error) => Err(format!("{:?}", blocking_error)),
+        Ok(Err(db_error)) => Err(format!("{}", db_error)),
+        Ok(Ok(token)) => Ok(token.map(|token| Identity {
+            name: token.name,
+            role: token.role,
+        })),
+    }
+}
+
+fn bearer_token(req: &ServiceRequest) -> Option<String> {
+    req.headers()
+        .get("Authorization")
+        .and_then(|header| header.to_str().ok())
+        .and_then(|header| header.strip_prefix("Bearer "))
+        .map(|token| token.trim().to_string())
+}
+
+/// Middleware requiring an API token with a role allowed to call the requested endpoint.
+pub struct Authentication;
+
+impl<S, B> Transform<S, ServiceRequest> for Authentication
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<EitherBody<B>>;
+    type Error = Error;
+    type Transform = AuthenticationMiddleware<S>;
+    type InitError = ();
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ready(Ok(AuthenticationMiddleware {
+            service: Rc::new(service),
+        }))
+    }
+}
+
+pub struct AuthenticationMiddleware<S> {
+    service: Rc<S>,
+}
+
+impl<S, B> Service<ServiceRequest> for AuthenticationMiddleware<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<EitherBody<B>>;
+    type Error = Error;
+    type Future = LocalBoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    forward_ready!(service);
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        let service = self.service.clone();
+        Box::pin(async move {
+            let required_role = match Role::required_for(req.method(), req.path()) {
+                Some(role) => role,
+                None => return Ok(service.call(req).await?.map_into_left_body()),
+            };
+
+            let identity = match bearer_token(&req) {
+                Some(token) => identify(token).await,
+                None => Ok(None),
+            };
+            let response = match identity {
+                Err(e) => HttpResponse::InternalServerError().json(ErrorResponse { error: e }),
+                Ok(None) => HttpResponse::Unauthorized().json(ErrorResponse {
+                    error: "a valid API token is required".into(),
+                }),
+                Ok(Some(identity)) if identity.role < required_role => HttpResponse::Forbidden()
+                    .json(ErrorResponse {
+                        error: format!("this endpoint requires the {} role", required_role),
+                    }),
+                Ok(Some(identity)) => {
+                    if required_role > Role::Viewer {
+                        info!("{} {} by {}", req.method(), req.path(), identity.name);
+                    }
+                    req.extensions_mut().insert(identity);
+                    return Ok(service.call(req).await?.map_into_left_body());
+                }
+            };
+            Ok(req.into_response(response).map_into_right_body())
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn slack_and_webhook_endpoints_do_not_take_tokens() {
+        for path in ["/slack/events", "/slack/commands", "/webhooks/opsgenie"] {
+            assert_eq!(Role::required_for(&Method::POST, path), None, "{}", path);
+            assert_eq!(Role::required_for(&Method::GET, path), None, "{}", path);
+        }
+    }
+
+    #[test]
+    fn token_endpoints_require_admin() {
+        assert_eq!(
+            Role::required_for(&Method::GET, "/api_tokens/list"),
+            Some(Role::Admin)
+        );
+        assert_eq!(
+            Role::required_for(&Method::POST, "/api_tokens/add"),
+            Some(Role::Admin)
+        );
+        assert_eq!(
+            Role::required_for(&Method::POST, "/api_tokens/remove"),
+            Some(Role::Admin)
+        );
+    }
+
+    #[test]
+    fn reads_require_viewer_and_writes_require_editor() {
+        assert_eq!(
+            Role::required_for(&Method::GET, "/list_syncs"),
+            Some(Role::Viewer)
+        );
+        assert_eq!(
+            Role::required_for(&Method::HEAD, "/list_syncs"),
+            Some(Role::Viewer)
+        );
+        assert_eq!(
+            Role::required_for(&Method::POST, "/add_sync"),
+            Some(Role::Editor)
+        );
+        assert_eq!(
+            Role::required_for(&Method::DELETE, "/add_sync"),
+            Some(Role::Editor)
+        );
+        // Only the exact prefixes are exempt
+        assert_eq!(
+            Role::required_for(&Method::POST, "/slack_channels"),
+            Some(Role::Editor)
+        );
+        assert_eq!(
+            Role::required_for(&Method::GET, "/webhooks"),
+            Some(Role::Viewer)
+        );
+    }
+}
@@ -0,0 +1,203 @@
+//! Maps oncall users to the chat users registered with the same email address, so that new hires
+//! get mapped without an admin going through `/add_user_map`. Runs on demand through
+//! `/user_mappings/auto` and, when `AUTO_MAP_USERS_INTERVAL_SECS` is set, from the scheduler.
+
+use crate::{
+    audit,
+    chat_provider::{self, ChatProviderKind},
+    db, format_user_mapping,
+    models::NewAuditEvent,
+    oncall_provider::{self, OncallProviderKind},
+    ErrorResponse,
+};
+use actix_web::HttpResponse;
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+
+#[derive(thiserror::Error, Debug)]
+pub enum Error {
+    #[error("error listing {0} users: {1}")]
+    ListUsers(OncallProviderKind, oncall_provider::Error),
+    #[error("error looking up {0} user by email: {1}")]
+    FindUser(ChatProviderKind, chat_provider::Error),
+    #[error(transparent)]
+    Db(#[from] db::Error),
+    #[error("error running DB query: {0}")]
+    Blocking(#[from] tokio::task::JoinError),
+}
+
+impl From<Error> for HttpResponse {
+    fn from(error: Error) -> HttpResponse {
+        match error {
+            Error::Db(e) => e.into(),
+            Error::FindUser(_, e) => e.into(),
+            Error::ListUsers(_, _) => HttpResponse::BadGateway().json(ErrorResponse {
+                error: format!("{}", error),
+            }),
+            Error::Blocking(_) => HttpResponse::InternalServerError().json(ErrorResponse {
+                error: format!("{}", error),
+            }),
+        }
+    }
+}
+
+pub type Result<T = (), E = Error> = std::result::Result<T, E>;
+
+/// An oncall user and the chat user registered with the same email.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Match {
+    pub oncall_provider: OncallProviderKind,
+    pub opsgenie_id: String,
+    pub chat_provider: ChatProviderKind,
+    pub slack_id: String,
+    pub email: String,
+}
+
+/// A match that wasn't mapped because the chat user already belongs to another oncall user.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Conflict {
+    #[serde(flatten)]
+    pub found: Match,
+    /// Oncall user the chat user is mapped to
+    pub mapped_to: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct Report {
+    pub dry_run: bool,
+    /// Mappings created, or the ones that would be in a dry run
+    pub created: Vec<Match>,
+    pub conflicts: Vec<Conflict>,
+    /// Oncall users without a mapping whose email isn't known to any chat provider
+    pub unmatched: Vec<oncall_provider::User>,
+    /// What went wrong for the providers and users that were skipped. They're retried next run.
+    pub errors: Vec<String>,
+}
+
+/// Looks up the unmapped users of every configured oncall provider by email and maps them to the
+/// chat users found. In a dry run nothing is written, the report says what would have been.
+/// Failures listing or mapping users only skip those users, and are listed in the report.
+pub async fn run(actor: &str, dry_run: bool) -> Result<Report> {
+    let mappings = tokio::task::spawn_blocking(|| {
+        let conn = db::connection();
+        db::list_user_mappings(&conn)
+    })
+    .await??;
+    let mapped_oncall_users: HashSet<(OncallProviderKind, String)> = mappings
+        .iter()
+        .map(|mapping| (mapping.oncall_provider, mapping.opsgenie_id.clone()))
+        .collect();
+    // Chat users can only be mapped once, so later matches for the same user are conflicts too
+    let mut mapped_chat_users: HashMap<String, String> = mappings
+        .into_iter()
+        .map(|mapping| (mapping.slack_id, mapping.opsgenie_id))
+        .collect();
+
+    let mut report = Report {
+        dry_run,
+        ..Default::default()
+    };
+    for oncall_provider in OncallProviderKind::configured() {
+        let users = match oncall_provider.provider().list_users().await {
+            Ok(users) => users,
+            Err(e) => {
+                report.skip(Error::ListUsers(oncall_provider, e).to_string());
+                continue;
+            }
+        };
+        for user in users {
+            if mapped_oncall_users.contains(&(oncall_provider, user.id.clone())) {
+                continue;
+            }
+            let chat_user = match find_chat_user(&user.username).await {
+                Ok(Some(chat_user)) => chat_user,
+                Ok(None) => {
+                    report.unmatched.push(user);
+                    continue;
+                }
+                Err(e) => {
+                    report.skip(format!("{} for {}", e, user.username));
+                    continue;
+                }
+            };
+
+            let found = Match {
+                oncall_provider,
+                opsgenie_id: user.id.clone(),
+                chat_provider: chat_user.provider,
+                slack_id: chat_user.id.clone(),
+                email: user.username.clone(),
+            };
+            if let Some(mapped_to) = mapped_chat_users.get(&chat_user.id) {
+                warn!(
+                    "Not mapping {} user {} to {} user {} with the same email, who is already mapped to {}",
+                    oncall_provider, user.id, chat_user.provider, chat_user.id, mapped_to
+                );
+                report.conflicts.push(Conflict {
+                    found,
+                    mapped_to: mapped_to.clone(),
+                });
+                continue;
+            }
+
+            if !dry_run {
+                if let Err(e) = create(actor, &found).await {
+                    report.skip(format!(
+                        "error mapping {} user {} to {} user {}: {}",
+                        oncall_provider, user.id, chat_user.provider, chat_user.id, e
+                    ));
+                    continue;
+                }
+            }
+            mapped_chat_users.insert(chat_user.id, user.id);
+            report.created.push(found);
+        }
+    }
+    Ok(report)
+}
+
+impl Report {
+    fn skip(&mut self, error: String) {
+        warn!("Skipped while mapping users by email: {}", error);
+        self.errors.push(error);
+    }
+}
+
+/// Returns the first chat user registered with the email, trying each configured provider in turn.
+async fn find_chat_user(email: &str) -> Result<Option<chat_provider::User>> {
+    if !email.contains('@') {
+        return Ok(None);
+    }
+    for kind in ChatProviderKind::configured() {
+        match kind.provider().find_user_by_email(email).await {
+            Ok(Some(user)) if !user.is_bot => return Ok(Some(user)),
+            Ok(_) => {}
+            Err(e) => return Err(Error::FindUser(kind, e)),
+        }
+    }
+    Ok(None)
+}
+
+async fn create(actor: &str, found: &Match) -> Result {
+    let opsgenie_id = found.opsgenie_id.clone();
+    let slack_id = found.slack_id.clone();
+    let oncall_provider = found.oncall_provider;
+    let user_mapping = tokio::task::spawn_blocking(move || {
+        let conn = db::connection();
+        db::add_user_mapping(&conn, oncall_provider, &opsgenie_id, &slack_id)
+    })
+    .await??;
+    info!(
+        "Mapped {} user {} to {} user {} by email",
+        found.oncall_provider, found.opsgenie_id, found.chat_provider, found.slack_id
+    );
+    audit::record(NewAuditEvent {
+        oncall_provider: Some(found.oncall_provider),
+        chat_provider: Some(found.chat_provider),
+        new_value: Some(format_user_mapping(&user_mapping)),
+        ..NewAuditEvent::new(actor, "auto_map_user")
+    })
+    .await;
+    Ok(())
+}
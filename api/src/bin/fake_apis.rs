@@ -0,0 +1,25 @@
+use log::{info, Level};
+use oncall_bot::fake_apis::FakeApis;
+use std::env;
+
+/// Runs the fake Slack and OpsGenie APIs on their own, to try the bot locally without real
+/// workspaces. Point `SLACK_API_URL` and `OPSGENIE_API_URL` at the logged URLs.
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
+    simple_logger::init_with_level(Level::Info).unwrap();
+
+    let fake_apis = FakeApis::start_on((
+        "127.0.0.1",
+        env::var("FAKE_APIS_PORT")
+            .unwrap_or("8090".into())
+            .parse()
+            .expect("unable to parse provided port to a number"),
+    ))?;
+    info!("SLACK_API_URL={}", fake_apis.slack_url());
+    info!("OPSGENIE_API_URL={}", fake_apis.opsgenie_url());
+
+    tokio::signal::ctrl_c().await?;
+    fake_apis.stop().await;
+
+    Ok(())
+}
@@ -68,23 +68,11 @@ impl<K: Clone + Hash + PartialEq + Eq, V: Clone, Error: std::error::Error> Cache
         }
     }
 
-    pub async fn update(&self, k: K, v: V) {
-        let mut data_lg = self.data.write().await;
-        match data_lg.get_mut(&k) {
-            Some(old_val) => {
-                *old_val = v;
-            }
-            None => {
-                data_lg.insert(k, v);
-            }
-        }
-    }
-
     async fn check_needs_update(&self) -> bool {
         let now = Instant::now();
         let last_update = {
             let lg = self.last_update.read().await;
-            (*lg).clone()
+            *lg
         };
 
         match last_update {
@@ -0,0 +1,269 @@
+use crate::{mattermost, oncall_provider::OncallProviderKind, slack, ErrorResponse};
+use actix_web::HttpResponse;
+use diesel::{
+    backend::Backend,
+    deserialize::{self, FromSql},
+    serialize::{self, Output, ToSql},
+    sql_types::Text,
+    sqlite::Sqlite,
+};
+use futures::future::BoxFuture;
+use serde::{Deserialize, Serialize};
+use std::{fmt, io::Write, str::FromStr};
+use time::{macros::format_description, OffsetDateTime};
+
+#[derive(thiserror::Error, Debug)]
+pub enum Error {
+    #[error("slack error: {0}")]
+    Slack(#[from] slack::Error),
+    #[error("mattermost error: {0}")]
+    Mattermost(#[from] mattermost::Error),
+}
+
+impl Error {
+    /// Returns true if the chat service told us the requested resource does not exist.
+    pub fn is_not_found(&self) -> bool {
+        match self {
+            Error::Slack(slack::Error::HttpErrorCode(code))
+            | Error::Mattermost(mattermost::Error::HttpErrorCode(code)) => {
+                *code == reqwest::StatusCode::NOT_FOUND
+            }
+            Error::Slack(e) => e.is_not_found(),
+            _ => false,
+        }
+    }
+
+    /// Error code returned by the chat service's API (e.g. Slack's `not_in_channel`), if any.
+    pub fn code(&self) -> Option<&str> {
+        match self {
+            Error::Slack(e) => e.code(),
+            Error::Mattermost(_) => None,
+        }
+    }
+}
+
+impl From<Error> for HttpResponse {
+    fn from(error: Error) -> HttpResponse {
+        let mut response = if error.is_not_found() {
+            HttpResponse::NotFound()
+        } else if let Error::Slack(slack::Error::RateLimited) = error {
+            HttpResponse::ServiceUnavailable()
+        } else {
+            HttpResponse::BadGateway()
+        };
+        response.json(ErrorResponse {
+            error: format!("{}", error),
+        })
+    }
+}
+
+pub type Result<T = (), E = Error> = std::result::Result<T, E>;
+
+#[derive(thiserror::Error, Debug)]
+#[error("unknown chat provider \"{0}\"")]
+pub struct UnknownProvider(String);
+
+/// Identifies which chat workspace a sync or notification lives in. This is what gets stored in
+/// the `chat_provider` column of the DB.
+#[derive(
+    Serialize,
+    Deserialize,
+    Debug,
+    Clone,
+    Copy,
+    Default,
+    PartialEq,
+    Eq,
+    Hash,
+    AsExpression,
+    FromSqlRow,
+)]
+#[serde(rename_all = "lowercase")]
+#[sql_type = "Text"]
+pub enum ChatProviderKind {
+    #[default]
+    Slack,
+    Mattermost,
+}
+
+impl ChatProviderKind {
+    pub const ALL: [ChatProviderKind; 2] = [ChatProviderKind::Slack, ChatProviderKind::Mattermost];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ChatProviderKind::Slack => "slack",
+            ChatProviderKind::Mattermost => "mattermost",
+        }
+    }
+
+    pub fn provider(&self) -> &'static dyn ChatProvider {
+        match self {
+            ChatProviderKind::Slack => &slack::Slack,
+            ChatProviderKind::Mattermost => &mattermost::Mattermost,
+        }
+    }
+
+    /// A provider is configured when its credentials are present in the environment.
+    pub fn is_configured(&self) -> bool {
+        self.provider().is_configured()
+    }
+
+    pub fn configured() -> Vec<ChatProviderKind> {
+        ChatProviderKind::ALL
+            .into_iter()
+            .filter(|kind| kind.is_configured())
+            .collect()
+    }
+}
+
+impl fmt::Display for ChatProviderKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for ChatProviderKind {
+    type Err = UnknownProvider;
+
+    fn from_str(s: &str) -> Result<ChatProviderKind, UnknownProvider> {
+        ChatProviderKind::ALL
+            .into_iter()
+            .find(|kind| kind.as_str() == s)
+            .ok_or_else(|| UnknownProvider(s.into()))
+    }
+}
+
+impl ToSql<Text, Sqlite> for ChatProviderKind {
+    fn to_sql<W: Write>(&self, out: &mut Output<W, Sqlite>) -> serialize::Result {
+        ToSql::<Text, Sqlite>::to_sql(self.as_str(), out)
+    }
+}
+
+impl FromSql<Text, Sqlite> for ChatProviderKind {
+    fn from_sql(bytes: Option<&<Sqlite as Backend>::RawValue>) -> deserialize::Result<Self> {
+        let value = <String as FromSql<Text, Sqlite>>::from_sql(bytes)?;
+        Ok(value.parse()?)
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct UserGroup {
+    pub id: String,
+    pub name: String,
+    pub handle: String,
+    pub provider: ChatProviderKind,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct User {
+    pub id: String,
+    pub name: String,
+    pub real_name: Option<String>,
+    pub is_bot: bool,
+    pub provider: ChatProviderKind,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ChannelTopic {
+    pub value: String,
+    pub creator: String,
+    pub last_set: u64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Channel {
+    pub id: String,
+    pub name: String,
+    pub topic: ChannelTopic,
+    pub provider: ChatProviderKind,
+}
+
+/// A handoff announcement. Providers that support it render this with the oncalls' names and
+/// avatars, the schedule and buttons to page or take over. Others just post `text`.
+#[derive(Debug, Clone)]
+pub struct Announcement<'a> {
+    pub text: &'a str,
+    pub oncall_provider: OncallProviderKind,
+    pub oncall_id: &'a str,
+    pub schedule_name: Option<&'a str>,
+    /// Chat user IDs of the current oncalls
+    pub user_ids: &'a [String],
+    /// Unix timestamp of the end of the shift
+    pub until: Option<i64>,
+}
+
+/// Common interface over the chat workspaces the bot can drive (Slack, Mattermost, ...). User
+/// group syncs and channel notifiers only talk to the workspace through this trait.
+pub trait ChatProvider: Send + Sync {
+    fn kind(&self) -> ChatProviderKind;
+
+    fn is_configured(&self) -> bool;
+
+    fn list_user_groups(&self) -> BoxFuture<'_, Result<Vec<UserGroup>>>;
+
+    fn get_user_group<'a>(&'a self, id: &'a str) -> BoxFuture<'a, Result<UserGroup>>;
+
+    /// Returns the IDs of the users currently in the user group.
+    fn get_user_group_members<'a>(&'a self, id: &'a str) -> BoxFuture<'a, Result<Vec<String>>>;
+
+    /// Replaces the members of the user group with exactly `users`.
+    fn set_user_group<'a>(&'a self, id: &'a str, users: &'a [String]) -> BoxFuture<'a, Result>;
+
+    fn list_users(&self) -> BoxFuture<'_, Result<Vec<User>>>;
+
+    fn get_user<'a>(&'a self, id: &'a str) -> BoxFuture<'a, Result<User>>;
+
+    /// Returns the user whose account is registered with the given email address, if any.
+    fn find_user_by_email<'a>(&'a self, email: &'a str) -> BoxFuture<'a, Result<Option<User>>>;
+
+    fn list_channels(&self) -> BoxFuture<'_, Result<Vec<Channel>>>;
+
+    fn get_channel<'a>(&'a self, id: &'a str) -> BoxFuture<'a, Result<Channel>>;
+
+    fn set_channel_topic<'a>(
+        &'a self,
+        channel_id: &'a str,
+        topic: &'a str,
+    ) -> BoxFuture<'a, Result>;
+
+    fn post_message<'a>(&'a self, channel_id: &'a str, message: &'a str) -> BoxFuture<'a, Result>;
+
+    /// Sends a message to the user privately, from the bot.
+    fn post_direct_message<'a>(
+        &'a self,
+        user_id: &'a str,
+        message: &'a str,
+    ) -> BoxFuture<'a, Result>;
+
+    fn post_announcement<'a>(
+        &'a self,
+        channel_id: &'a str,
+        announcement: &'a Announcement<'a>,
+    ) -> BoxFuture<'a, Result> {
+        self.post_message(channel_id, announcement.text)
+    }
+
+    /// Returns the markup that mentions (and notifies) the given user in a message or topic.
+    fn format_mention<'a>(&'a self, user_id: &'a str) -> BoxFuture<'a, Result<String>>;
+
+    /// Returns the markup that mentions (and notifies) every member of the given user group.
+    fn format_user_group_mention<'a>(
+        &'a self,
+        user_group_id: &'a str,
+    ) -> BoxFuture<'a, Result<String>>;
+
+    /// Returns the markup showing a Unix timestamp to readers, in their own time zone where the
+    /// service supports it.
+    fn format_time(&self, timestamp: i64) -> String {
+        format_utc(timestamp)
+    }
+}
+
+/// Formats a Unix timestamp as e.g. `2022-04-02 19:28 UTC`.
+pub fn format_utc(timestamp: i64) -> String {
+    let format = format_description!("[year]-[month]-[day] [hour]:[minute] UTC");
+    OffsetDateTime::from_unix_timestamp(timestamp)
+        .ok()
+        .and_then(|time| time.format(&format).ok())
+        .unwrap_or_else(|| timestamp.to_string())
+}
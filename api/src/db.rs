@@ -1,16 +1,25 @@
 use crate::{
+    auth::Role,
+    chat_provider::ChatProviderKind,
     models::{
-        NewNotifiedSlackChannel, NewOncallSync, NewUserMapping, NotifiedSlackChannel, OncallSync,
-        UserMapping,
+        ApiToken, AuditEvent, NewApiToken, NewAuditEvent, NewNotifiedSlackChannel, NewOncallSync,
+        NewShift, NewShiftReminder, NewUserMapping, NotifiedSlackChannel, OncallSync, Shift,
+        ShiftReminder, UserMapping,
     },
-    schema::{notified_slack_channel, oncall_syncs, user_mapping},
+    oncall_provider::OncallProviderKind,
+    schema::{audit_events, notified_slack_channel, oncall_syncs, user_mapping},
+    template::Templates,
     ErrorResponse,
 };
 use actix_web::HttpResponse;
-use diesel::{prelude::*, result::Error as DieselError, sqlite::SqliteConnection};
-use std::env;
+use diesel::{
+    connection::SimpleConnection, prelude::*, result::Error as DieselError,
+    sqlite::SqliteConnection,
+};
+use std::{collections::HashSet, env};
 
 #[derive(thiserror::Error, Debug)]
+#[allow(clippy::enum_variant_names)]
 pub enum Error {
     #[error("error making a query")]
     QueryError(#[from] DieselError),
@@ -24,14 +33,21 @@ pub enum Error {
         opsgenie_id: String,
         slack_id: String,
     },
-    #[error("channel with ID {0} is already being notified")]
-    ChannelAlreadyNotified(String),
+    #[error("channel with ID {slack_channel_id} is already notified for oncall {oncall_id}")]
+    ChannelAlreadyNotified {
+        slack_channel_id: String,
+        oncall_id: String,
+    },
     #[error("Oncall sync with ID {0} does not exist")]
     OncallSyncDoesNotExist(i32),
     #[error("user mapping with ID {0} does not exist")]
     UserMappingDoesNotExist(i32),
     #[error("channel notification ID {0} does not exist")]
     ChannelNotificationDoesNotExist(i32),
+    #[error("API token named {0} already exists")]
+    ApiTokenAlreadyExists(String),
+    #[error("API token with ID {0} does not exist")]
+    ApiTokenDoesNotExist(i32),
 }
 
 impl From<Error> for HttpResponse {
@@ -50,18 +66,32 @@ impl From<Error> for HttpResponse {
             }
             | Error::UserMappingDoesNotExist(_)
             | Error::OncallSyncDoesNotExist(_)
-            | Error::ChannelAlreadyNotified(_)
-            | Error::ChannelNotificationDoesNotExist(_) => {
-                HttpResponse::BadRequest().json(ErrorResponse {
-                    error: format!("{}", error),
-                })
+            | Error::ChannelAlreadyNotified {
+                slack_channel_id: _,
+                oncall_id: _,
             }
+            | Error::ChannelNotificationDoesNotExist(_)
+            | Error::ApiTokenAlreadyExists(_)
+            | Error::ApiTokenDoesNotExist(_) => HttpResponse::BadRequest().json(ErrorResponse {
+                error: format!("{}", error),
+            }),
         }
     }
 }
 
 pub type Result<T = (), E = Error> = std::result::Result<T, E>;
 
+const BUSY_TIMEOUT_MS: u32 = 5000;
+
+/// Filters for [`list_audit_events`]. Unset filters match everything.
+#[derive(Debug, Default)]
+pub struct AuditEventFilter<'a> {
+    pub oncall_id: Option<&'a str>,
+    pub slack_channel_id: Option<&'a str>,
+    pub since: Option<i64>,
+    pub until: Option<i64>,
+}
+
 no_arg_sql_function!(
     last_insert_rowid,
     diesel::sql_types::Integer,
@@ -70,25 +100,35 @@ no_arg_sql_function!(
 
 pub fn connection() -> SqliteConnection {
     let database_url = env::var("DATABASE_URL").expect("DATABASE_URL must be set");
-    SqliteConnection::establish(&database_url)
-        .expect(&format!("Error connecting to {}", database_url))
+    let conn = SqliteConnection::establish(&database_url)
+        .unwrap_or_else(|_| panic!("Error connecting to {}", database_url));
+    // Handlers, the scheduler and the audit log all write concurrently, so wait for the lock
+    // instead of failing with "database is locked"
+    conn.batch_execute(&format!("PRAGMA busy_timeout = {};", BUSY_TIMEOUT_MS))
+        .expect("Error setting busy timeout");
+    conn
 }
 
 pub fn add_sync<'a>(
     conn: &SqliteConnection,
+    oncall_provider_q: OncallProviderKind,
     oncall_id_q: &'a str,
+    chat_provider_q: ChatProviderKind,
     user_group_id_q: &'a str,
+    dry_run_q: bool,
 ) -> Result<OncallSync> {
     conn.transaction(|| {
         // If sync already exists, error out
         {
             use crate::schema::oncall_syncs::dsl::*;
-            if let Some(_) = oncall_syncs
+            if !oncall_syncs
+                .filter(oncall_provider.eq(oncall_provider_q))
                 .filter(oncall_id.eq(oncall_id_q))
+                .filter(chat_provider.eq(chat_provider_q))
                 .filter(user_group_id.eq(user_group_id_q))
                 .limit(1)
                 .load::<OncallSync>(conn)?
-                .first()
+                .is_empty()
             {
                 return Err(Error::OncallSyncAlreadyExists {
                     oncall_id: oncall_id_q.into(),
@@ -100,6 +140,9 @@ pub fn add_sync<'a>(
         let new_oncall_sync = NewOncallSync {
             oncall_id: oncall_id_q,
             user_group_id: user_group_id_q,
+            oncall_provider: oncall_provider_q,
+            chat_provider: chat_provider_q,
+            dry_run: dry_run_q,
         };
 
         // Insert and get ID
@@ -122,6 +165,45 @@ pub fn add_sync<'a>(
     })
 }
 
+/// Syncs the user group with every one of the schedules, or none of them if any already is.
+pub fn add_syncs(
+    conn: &SqliteConnection,
+    schedules: &[(OncallProviderKind, String)],
+    chat_provider_q: ChatProviderKind,
+    user_group_id_q: &str,
+    dry_run_q: bool,
+) -> Result<Vec<OncallSync>> {
+    conn.transaction(|| {
+        schedules
+            .iter()
+            .map(|(oncall_provider_q, oncall_id_q)| {
+                add_sync(
+                    conn,
+                    *oncall_provider_q,
+                    oncall_id_q,
+                    chat_provider_q,
+                    user_group_id_q,
+                    dry_run_q,
+                )
+            })
+            .collect()
+    })
+}
+
+/// Every sync of the user group, oldest first.
+pub fn get_user_group_syncs(
+    conn: &SqliteConnection,
+    chat_provider_q: ChatProviderKind,
+    user_group_id_q: &str,
+) -> Result<Vec<OncallSync>> {
+    use crate::schema::oncall_syncs::dsl::*;
+    Ok(oncall_syncs
+        .filter(chat_provider.eq(chat_provider_q))
+        .filter(user_group_id.eq(user_group_id_q))
+        .order(id.asc())
+        .load::<OncallSync>(conn)?)
+}
+
 pub fn remove_sync(conn: &SqliteConnection, id_q: i32) -> Result<OncallSync> {
     use crate::schema::oncall_syncs::dsl::*;
 
@@ -138,6 +220,19 @@ pub fn remove_sync(conn: &SqliteConnection, id_q: i32) -> Result<OncallSync> {
     Ok(removed_sync)
 }
 
+pub fn set_sync_dry_run(conn: &SqliteConnection, id_q: i32, dry_run_q: bool) -> Result<OncallSync> {
+    use crate::schema::oncall_syncs::dsl::*;
+    conn.transaction(|| {
+        let updated = diesel::update(oncall_syncs.filter(id.eq(id_q)))
+            .set(dry_run.eq(dry_run_q))
+            .execute(conn)?;
+        if updated == 0 {
+            return Err(Error::OncallSyncDoesNotExist(id_q));
+        }
+        Ok(oncall_syncs.filter(id.eq(id_q)).first::<OncallSync>(conn)?)
+    })
+}
+
 pub fn get_syncs(conn: &SqliteConnection, oncall_id_q: &str) -> Result<Vec<OncallSync>> {
     use crate::schema::oncall_syncs::dsl::*;
     Ok(oncall_syncs
@@ -147,6 +242,7 @@ pub fn get_syncs(conn: &SqliteConnection, oncall_id_q: &str) -> Result<Vec<Oncal
 
 pub fn add_user_mapping<'a>(
     conn: &SqliteConnection,
+    oncall_provider_q: OncallProviderKind,
     opsgenie_id_q: &'a str,
     slack_id_q: &'a str,
 ) -> Result<UserMapping> {
@@ -154,12 +250,13 @@ pub fn add_user_mapping<'a>(
         // Ensure user mapping doesn't already exist
         {
             use crate::schema::user_mapping::dsl::*;
-            if let Some(_) = user_mapping
+            if !user_mapping
+                .filter(oncall_provider.eq(oncall_provider_q))
                 .filter(opsgenie_id.eq(opsgenie_id_q))
                 .filter(slack_id.eq(slack_id_q))
                 .limit(1)
                 .load::<UserMapping>(conn)?
-                .first()
+                .is_empty()
             {
                 // If sync already exists, error out
                 return Err(Error::UserMappingAlreadyExists {
@@ -172,6 +269,7 @@ pub fn add_user_mapping<'a>(
         let new_user_mapping = NewUserMapping {
             opsgenie_id: opsgenie_id_q,
             slack_id: slack_id_q,
+            oncall_provider: oncall_provider_q,
         };
 
         // Insert and get ID
@@ -193,7 +291,7 @@ pub fn add_user_mapping<'a>(
     })
 }
 
-pub fn remove_user_mapping<'a>(conn: &SqliteConnection, id_q: i32) -> Result<UserMapping> {
+pub fn remove_user_mapping(conn: &SqliteConnection, id_q: i32) -> Result<UserMapping> {
     use crate::schema::user_mapping::dsl::*;
 
     let removed_user_mapping = user_mapping
@@ -208,6 +306,25 @@ pub fn remove_user_mapping<'a>(conn: &SqliteConnection, id_q: i32) -> Result<Use
     Ok(removed_user_mapping)
 }
 
+pub fn set_user_mapping_shift_dms_opt_out(
+    conn: &SqliteConnection,
+    id_q: i32,
+    opt_out_q: bool,
+) -> Result<UserMapping> {
+    use crate::schema::user_mapping::dsl::*;
+    conn.transaction(|| {
+        let updated = diesel::update(user_mapping.filter(id.eq(id_q)))
+            .set(shift_dms_opt_out.eq(opt_out_q))
+            .execute(conn)?;
+        if updated == 0 {
+            return Err(Error::UserMappingDoesNotExist(id_q));
+        }
+        Ok(user_mapping
+            .filter(id.eq(id_q))
+            .first::<UserMapping>(conn)?)
+    })
+}
+
 pub fn list_user_mappings(conn: &SqliteConnection) -> Result<Vec<UserMapping>> {
     use crate::schema::user_mapping::dsl::*;
     Ok(user_mapping.load::<UserMapping>(conn)?)
@@ -222,19 +339,21 @@ pub fn get_slack_user_mapping(
         .filter(slack_id.eq(slack_id_q))
         .load::<UserMapping>(conn)?
         .first()
-        .map(|um| um.clone()))
+        .cloned())
 }
 
 pub fn get_opsgenie_user_mapping(
     conn: &SqliteConnection,
+    oncall_provider_q: OncallProviderKind,
     opsgenie_id_q: &str,
 ) -> Result<Option<UserMapping>> {
     use crate::schema::user_mapping::dsl::*;
     Ok(user_mapping
+        .filter(oncall_provider.eq(oncall_provider_q))
         .filter(opsgenie_id.eq(opsgenie_id_q))
         .load::<UserMapping>(conn)?
         .first()
-        .map(|um| um.clone()))
+        .cloned())
 }
 
 pub fn list_oncall_syncs(conn: &SqliteConnection) -> Result<Vec<OncallSync>> {
@@ -257,41 +376,62 @@ pub fn get_channels_notified_for_oncall(
         .load::<NotifiedSlackChannel>(conn)?)
 }
 
-pub fn get_oncall_notified_in_channel(
+/// Every schedule notified in the channel, oldest notification first. That's the order the
+/// schedules are shown in the channel's topic.
+pub fn get_oncalls_notified_in_channel(
     conn: &SqliteConnection,
     channel_id: &str,
-) -> Result<Option<NotifiedSlackChannel>> {
+) -> Result<Vec<NotifiedSlackChannel>> {
     use crate::schema::notified_slack_channel::dsl::*;
     Ok(notified_slack_channel
-        .limit(1)
         .filter(slack_channel_id.eq(channel_id))
-        .load::<NotifiedSlackChannel>(conn)?
-        .first()
-        .cloned())
+        .order(id.asc())
+        .load::<NotifiedSlackChannel>(conn)?)
 }
 
+#[allow(clippy::too_many_arguments)]
 pub fn add_channel_oncall_notification(
     conn: &SqliteConnection,
+    chat_provider_q: ChatProviderKind,
     slack_channel_id_q: &str,
+    oncall_provider_q: OncallProviderKind,
     oncall_id_q: &str,
+    dry_run_q: bool,
+    show_next_oncall_q: bool,
+    templates_q: &Templates,
+    rich_messages_q: bool,
 ) -> Result<NotifiedSlackChannel> {
     conn.transaction(|| {
-        // First, confirm the channel's not already been mapped
+        // First, confirm the channel's not already notified about the schedule
         {
             use crate::schema::notified_slack_channel::dsl::*;
-            if let Some(_) = notified_slack_channel
+            if !notified_slack_channel
                 .limit(1)
                 .filter(slack_channel_id.eq(slack_channel_id_q))
+                .filter(chat_provider.eq(chat_provider_q))
+                .filter(oncall_provider.eq(oncall_provider_q))
+                .filter(oncall_id.eq(oncall_id_q))
                 .load::<NotifiedSlackChannel>(conn)?
-                .first()
+                .is_empty()
             {
-                return Err(Error::ChannelAlreadyNotified(slack_channel_id_q.into()));
+                return Err(Error::ChannelAlreadyNotified {
+                    slack_channel_id: slack_channel_id_q.into(),
+                    oncall_id: oncall_id_q.into(),
+                });
             }
         }
 
         let new_notified_slack_channel = NewNotifiedSlackChannel {
             slack_channel_id: slack_channel_id_q,
             oncall_id: oncall_id_q,
+            oncall_provider: oncall_provider_q,
+            chat_provider: chat_provider_q,
+            dry_run: dry_run_q,
+            show_next_oncall: show_next_oncall_q,
+            topic_template: templates_q.topic_template.as_deref(),
+            message_template: templates_q.message_template.as_deref(),
+            out_of_hours_template: templates_q.out_of_hours_template.as_deref(),
+            rich_messages: rich_messages_q,
         };
 
         // Insert and get ID
@@ -314,6 +454,63 @@ pub fn add_channel_oncall_notification(
     })
 }
 
+pub fn set_notification_dry_run(
+    conn: &SqliteConnection,
+    id_q: i32,
+    dry_run_q: bool,
+) -> Result<NotifiedSlackChannel> {
+    use crate::schema::notified_slack_channel::dsl::*;
+    conn.transaction(|| {
+        let updated = diesel::update(notified_slack_channel.filter(id.eq(id_q)))
+            .set(dry_run.eq(dry_run_q))
+            .execute(conn)?;
+        if updated == 0 {
+            return Err(Error::ChannelNotificationDoesNotExist(id_q));
+        }
+        Ok(notified_slack_channel
+            .filter(id.eq(id_q))
+            .first::<NotifiedSlackChannel>(conn)?)
+    })
+}
+
+pub fn set_notification_show_next_oncall(
+    conn: &SqliteConnection,
+    id_q: i32,
+    show_next_oncall_q: bool,
+) -> Result<NotifiedSlackChannel> {
+    use crate::schema::notified_slack_channel::dsl::*;
+    conn.transaction(|| {
+        let updated = diesel::update(notified_slack_channel.filter(id.eq(id_q)))
+            .set(show_next_oncall.eq(show_next_oncall_q))
+            .execute(conn)?;
+        if updated == 0 {
+            return Err(Error::ChannelNotificationDoesNotExist(id_q));
+        }
+        Ok(notified_slack_channel
+            .filter(id.eq(id_q))
+            .first::<NotifiedSlackChannel>(conn)?)
+    })
+}
+
+pub fn set_notification_rich_messages(
+    conn: &SqliteConnection,
+    id_q: i32,
+    rich_messages_q: bool,
+) -> Result<NotifiedSlackChannel> {
+    use crate::schema::notified_slack_channel::dsl::*;
+    conn.transaction(|| {
+        let updated = diesel::update(notified_slack_channel.filter(id.eq(id_q)))
+            .set(rich_messages.eq(rich_messages_q))
+            .execute(conn)?;
+        if updated == 0 {
+            return Err(Error::ChannelNotificationDoesNotExist(id_q));
+        }
+        Ok(notified_slack_channel
+            .filter(id.eq(id_q))
+            .first::<NotifiedSlackChannel>(conn)?)
+    })
+}
+
 pub fn remove_channel_oncall_notification(
     conn: &SqliteConnection,
     id_q: i32,
@@ -339,3 +536,308 @@ pub fn remove_channel_oncall_notification(
         Ok(deleted_notification)
     })
 }
+
+pub fn add_audit_event(conn: &SqliteConnection, event: &NewAuditEvent) -> Result {
+    diesel::insert_into(audit_events::table)
+        .values(event)
+        .execute(conn)?;
+    Ok(())
+}
+
+/// Lists audit events matching the filter, newest first.
+pub fn list_audit_events(
+    conn: &SqliteConnection,
+    filter: &AuditEventFilter,
+    offset_q: i64,
+    limit_q: i64,
+) -> Result<Vec<AuditEvent>> {
+    use crate::schema::audit_events::dsl::*;
+    let mut query = audit_events.into_boxed();
+    if let Some(oncall_id_q) = filter.oncall_id {
+        query = query.filter(oncall_id.eq(oncall_id_q));
+    }
+    if let Some(slack_channel_id_q) = filter.slack_channel_id {
+        query = query.filter(slack_channel_id.eq(slack_channel_id_q));
+    }
+    if let Some(since) = filter.since {
+        query = query.filter(created_at.ge(since));
+    }
+    if let Some(until) = filter.until {
+        query = query.filter(created_at.lt(until));
+    }
+
+    Ok(query
+        .order(id.desc())
+        .offset(offset_q)
+        .limit(limit_q)
+        .load::<AuditEvent>(conn)?)
+}
+
+/// Shifts opened and closed by [`update_shifts`].
+#[derive(Debug, Clone, Default)]
+pub struct ShiftChanges {
+    /// Users that came on call
+    pub started: Vec<String>,
+    pub ended: Vec<Shift>,
+    /// Set when no shift had been recorded for the schedule before, so the ones opened aren't
+    /// handoffs but just the first time the schedule was seen
+    pub first_seen: bool,
+}
+
+impl ShiftChanges {
+    pub fn is_empty(&self) -> bool {
+        self.started.is_empty() && self.ended.is_empty()
+    }
+}
+
+/// Brings the open shifts of a schedule in line with the users currently on call: shifts of users
+/// no longer on call get closed and new shifts are opened for users that just came on call.
+pub fn update_shifts(
+    conn: &SqliteConnection,
+    oncall_provider_q: OncallProviderKind,
+    oncall_id_q: &str,
+    user_ids: &[String],
+    now: i64,
+) -> Result<ShiftChanges> {
+    use crate::schema::shifts::dsl::*;
+    conn.transaction(|| {
+        let open_shifts = shifts
+            .filter(oncall_provider.eq(oncall_provider_q))
+            .filter(oncall_id.eq(oncall_id_q))
+            .filter(ended_at.is_null())
+            .load::<Shift>(conn)?;
+        let mut changes = ShiftChanges {
+            first_seen: open_shifts.is_empty()
+                && shifts
+                    .filter(oncall_provider.eq(oncall_provider_q))
+                    .filter(oncall_id.eq(oncall_id_q))
+                    .select(id)
+                    .first::<i32>(conn)
+                    .optional()?
+                    .is_none(),
+            ..ShiftChanges::default()
+        };
+
+        for shift in open_shifts.iter() {
+            if !user_ids.contains(&shift.user_id) {
+                diesel::update(shifts.filter(id.eq(shift.id)))
+                    .set(ended_at.eq(Some(now)))
+                    .execute(conn)?;
+                changes.ended.push(Shift {
+                    ended_at: Some(now),
+                    ..shift.clone()
+                });
+            }
+        }
+        for user_id_q in user_ids {
+            if !open_shifts.iter().any(|shift| &shift.user_id == user_id_q) {
+                diesel::insert_into(shifts)
+                    .values(&NewShift {
+                        oncall_provider: oncall_provider_q,
+                        oncall_id: oncall_id_q,
+                        user_id: user_id_q,
+                        started_at: now,
+                    })
+                    .execute(conn)?;
+                changes.started.push(user_id_q.clone());
+            }
+        }
+        Ok(changes)
+    })
+}
+
+/// Lists the shifts going on right now of users without a user mapping, optionally only for one
+/// schedule.
+pub fn list_unmapped_shifts(
+    conn: &SqliteConnection,
+    schedule: Option<(OncallProviderKind, &str)>,
+) -> Result<Vec<Shift>> {
+    // User IDs are only unique within a provider, so shifts are matched to mappings on both
+    let mapped_users: HashSet<(OncallProviderKind, String)> = {
+        use crate::schema::user_mapping::dsl::*;
+        user_mapping
+            .select((oncall_provider, opsgenie_id))
+            .load::<(OncallProviderKind, String)>(conn)?
+            .into_iter()
+            .collect()
+    };
+    use crate::schema::shifts::dsl::*;
+    let mut query = shifts.filter(ended_at.is_null()).into_boxed();
+    if let Some((oncall_provider_q, oncall_id_q)) = schedule {
+        query = query
+            .filter(oncall_provider.eq(oncall_provider_q))
+            .filter(oncall_id.eq(oncall_id_q));
+    }
+    Ok(query
+        .order(started_at.asc())
+        .load::<Shift>(conn)?
+        .into_iter()
+        .filter(|shift| !mapped_users.contains(&(shift.oncall_provider, shift.user_id.clone())))
+        .collect())
+}
+
+pub fn set_unmapped_warned(conn: &SqliteConnection, shift_ids: &[i32], now: i64) -> Result {
+    use crate::schema::shifts::dsl::*;
+    diesel::update(shifts.filter(id.eq_any(shift_ids)))
+        .set(unmapped_warned_at.eq(Some(now)))
+        .execute(conn)?;
+    Ok(())
+}
+
+/// Lists the shifts overlapping the `[since, until)` range, optionally only for one provider or
+/// schedule.
+pub fn list_shifts(
+    conn: &SqliteConnection,
+    oncall_provider_q: Option<OncallProviderKind>,
+    oncall_id_q: Option<&str>,
+    since: i64,
+    until: i64,
+) -> Result<Vec<Shift>> {
+    use crate::schema::shifts::dsl::*;
+    let mut query = shifts
+        .filter(started_at.lt(until))
+        .filter(ended_at.is_null().or(ended_at.gt(since)))
+        .into_boxed();
+    if let Some(oncall_provider_q) = oncall_provider_q {
+        query = query.filter(oncall_provider.eq(oncall_provider_q));
+    }
+    if let Some(oncall_id_q) = oncall_id_q {
+        query = query.filter(oncall_id.eq(oncall_id_q));
+    }
+    Ok(query.order(started_at.asc()).load::<Shift>(conn)?)
+}
+
+/// Brings the queued reminders of a schedule in line with its upcoming shifts. `planned` are
+/// the reminders for every shift starting after `now`, whether or not they're due yet. Reminders
+/// that aren't planned anymore are dropped unless they were sent, and only reminders that aren't
+/// due yet are queued, so late plans don't send stale reminders. Returns how many were queued.
+pub fn plan_shift_reminders(
+    conn: &SqliteConnection,
+    oncall_provider_q: OncallProviderKind,
+    oncall_id_q: &str,
+    planned: &[NewShiftReminder],
+    now: i64,
+) -> Result<usize> {
+    use crate::schema::shift_reminders::dsl::*;
+    conn.transaction(|| {
+        let schedule_reminders = shift_reminders
+            .filter(oncall_provider.eq(oncall_provider_q))
+            .filter(oncall_id.eq(oncall_id_q));
+        // Reminders of shifts that started are only kept to know they were sent
+        diesel::delete(schedule_reminders.filter(shift_start.le(now))).execute(conn)?;
+
+        let queued = schedule_reminders.load::<ShiftReminder>(conn)?;
+        let same = |queued: &ShiftReminder, planned: &NewShiftReminder| {
+            queued.chat_provider == planned.chat_provider
+                && queued.user_id == planned.user_id
+                && queued.shift_start == planned.shift_start
+                && queued.remind_at == planned.remind_at
+        };
+        for reminder in queued.iter() {
+            if reminder.sent_at.is_none() && !planned.iter().any(|p| same(reminder, p)) {
+                diesel::delete(shift_reminders.filter(id.eq(reminder.id))).execute(conn)?;
+            }
+        }
+        let new_reminders: Vec<&NewShiftReminder> = planned
+            .iter()
+            .filter(|p| p.remind_at > now && !queued.iter().any(|reminder| same(reminder, p)))
+            .collect();
+        for reminder in new_reminders.iter() {
+            diesel::insert_into(shift_reminders)
+                .values(*reminder)
+                .execute(conn)?;
+        }
+        Ok(new_reminders.len())
+    })
+}
+
+/// Marks every reminder that's due as sent and returns them, so that they're only picked up once.
+pub fn claim_due_shift_reminders(conn: &SqliteConnection, now: i64) -> Result<Vec<ShiftReminder>> {
+    use crate::schema::shift_reminders::dsl::*;
+    conn.transaction(|| {
+        let due = shift_reminders
+            .filter(sent_at.is_null())
+            .filter(remind_at.le(now))
+            .order(remind_at.asc())
+            .load::<ShiftReminder>(conn)?;
+        let due_ids: Vec<i32> = due.iter().map(|reminder| reminder.id).collect();
+        diesel::update(shift_reminders.filter(id.eq_any(&due_ids)))
+            .set(sent_at.eq(Some(now)))
+            .execute(conn)?;
+        Ok(due)
+    })
+}
+
+/// Puts a claimed reminder back in the queue, e.g. because it couldn't be sent.
+pub fn release_shift_reminder(conn: &SqliteConnection, id_q: i32) -> Result {
+    use crate::schema::shift_reminders::dsl::*;
+    diesel::update(shift_reminders.filter(id.eq(id_q)))
+        .set(sent_at.eq(None::<i64>))
+        .execute(conn)?;
+    Ok(())
+}
+
+pub fn add_api_token(
+    conn: &SqliteConnection,
+    name_q: &str,
+    token_hash_q: &str,
+    role_q: Role,
+    created_at_q: i64,
+) -> Result<ApiToken> {
+    use crate::schema::api_tokens::dsl::*;
+    conn.transaction(|| {
+        if !api_tokens
+            .filter(name.eq(name_q))
+            .limit(1)
+            .load::<ApiToken>(conn)?
+            .is_empty()
+        {
+            return Err(Error::ApiTokenAlreadyExists(name_q.into()));
+        }
+
+        diesel::insert_into(api_tokens)
+            .values(&NewApiToken {
+                name: name_q,
+                token_hash: token_hash_q,
+                role: role_q,
+                created_at: created_at_q,
+            })
+            .execute(conn)?;
+
+        let generated_id: i32 = diesel::select(last_insert_rowid).first(conn).unwrap();
+        Ok(api_tokens
+            .filter(id.eq(generated_id))
+            .first::<ApiToken>(conn)?)
+    })
+}
+
+pub fn remove_api_token(conn: &SqliteConnection, id_q: i32) -> Result<ApiToken> {
+    use crate::schema::api_tokens::dsl::*;
+
+    let removed_token = api_tokens
+        .filter(id.eq(id_q))
+        .load::<ApiToken>(conn)?
+        .first()
+        .ok_or_else(|| Error::ApiTokenDoesNotExist(id_q))?
+        .clone();
+
+    diesel::delete(api_tokens.filter(id.eq(id_q))).execute(conn)?;
+
+    Ok(removed_token)
+}
+
+pub fn list_api_tokens(conn: &SqliteConnection) -> Result<Vec<ApiToken>> {
+    use crate::schema::api_tokens::dsl::*;
+    Ok(api_tokens.order(name.asc()).load::<ApiToken>(conn)?)
+}
+
+pub fn get_api_token_by_hash(
+    conn: &SqliteConnection,
+    token_hash_q: &str,
+) -> Result<Option<ApiToken>> {
+    use crate::schema::api_tokens::dsl::*;
+    Ok(api_tokens
+        .filter(token_hash.eq(token_hash_q))
+        .first::<ApiToken>(conn)
+        .optional()?)
+}
@@ -0,0 +1,678 @@
+use actix_web::{dev::ServerHandle, http::Method, web, App, HttpRequest, HttpResponse, HttpServer};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex, MutexGuard},
+};
+use time::{format_description::well_known::Rfc3339, OffsetDateTime};
+
+/// A request received by [`FakeApis`].
+#[derive(Debug, Clone)]
+pub struct RecordedCall {
+    pub method: String,
+    /// Path relative to the fake API's base URL, e.g. `/chat.postMessage` or `/v2/alerts`
+    pub path: String,
+    pub query: String,
+    pub body: String,
+}
+
+#[derive(Debug, Clone)]
+pub struct PostedMessage {
+    pub channel: String,
+    pub text: String,
+    pub thread_ts: Option<String>,
+    /// Block Kit layout, for messages that have one
+    pub blocks: Option<Value>,
+}
+
+#[derive(Debug, Clone)]
+struct SlackUser {
+    id: String,
+    name: String,
+    real_name: String,
+    email: String,
+    avatar_url: Option<String>,
+}
+
+#[derive(Debug, Clone)]
+struct SlackUserGroup {
+    id: String,
+    name: String,
+    handle: String,
+    members: Vec<String>,
+}
+
+#[derive(Debug, Clone)]
+struct SlackChannel {
+    id: String,
+    name: String,
+    topic: String,
+}
+
+#[derive(Debug, Clone)]
+struct OpsgenieUser {
+    id: String,
+    username: String,
+    full_name: String,
+}
+
+#[derive(Debug, Clone, Default)]
+struct OpsgenieSchedule {
+    id: String,
+    name: String,
+    /// Participants as returned by the on-calls API
+    oncalls: Vec<Value>,
+    next_oncalls: Vec<Value>,
+    /// Unix timestamp the current shift ends and the next one starts at
+    handoff_at: Option<i64>,
+    /// Periods with a recipient, shown in a rotation of their own in the timeline
+    periods: Vec<Value>,
+}
+
+#[derive(Debug, Clone)]
+struct OpsgenieTeam {
+    id: String,
+    name: String,
+    members: Vec<String>,
+}
+
+#[derive(Default)]
+struct FakeData {
+    slack_calls: Vec<RecordedCall>,
+    opsgenie_calls: Vec<RecordedCall>,
+    /// Slack methods that should fail with the given error code
+    slack_failures: HashMap<String, String>,
+    slack_users: Vec<SlackUser>,
+    user_groups: Vec<SlackUserGroup>,
+    channels: Vec<SlackChannel>,
+    messages: Vec<PostedMessage>,
+    opsgenie_users: Vec<OpsgenieUser>,
+    schedules: Vec<OpsgenieSchedule>,
+    teams: Vec<OpsgenieTeam>,
+    alerts: Vec<Value>,
+    /// Schedule ID and body of every override created
+    overrides: Vec<(String, Value)>,
+}
+
+#[derive(Deserialize)]
+struct UserGroupUpdate {
+    usergroup: String,
+    users: Vec<String>,
+}
+
+#[derive(Deserialize)]
+struct SetTopic {
+    channel: String,
+    topic: String,
+}
+
+#[derive(Deserialize)]
+struct PostMessage {
+    channel: String,
+    text: String,
+    thread_ts: Option<String>,
+    blocks: Option<Value>,
+}
+
+/// In-memory stand-in for the Slack Web API and the OpsGenie REST API, for integration tests and
+/// local development. Point `SLACK_API_URL` at [`FakeApis::slack_url`] and `OPSGENIE_API_URL` at
+/// [`FakeApis::opsgenie_url`]. Every request is recorded, and the changes made through the API
+/// (user group members, topics, messages, alerts) can be inspected.
+pub struct FakeApis {
+    slack_url: String,
+    opsgenie_url: String,
+    data: Arc<Mutex<FakeData>>,
+    handle: ServerHandle,
+}
+
+impl FakeApis {
+    /// Starts the server on a random local port. Must be called from within a tokio runtime.
+    pub fn start() -> std::io::Result<FakeApis> {
+        FakeApis::start_on(("127.0.0.1", 0))
+    }
+
+    pub fn start_on(address: (&str, u16)) -> std::io::Result<FakeApis> {
+        let data = Arc::new(Mutex::new(FakeData::default()));
+        let data_clone = data.clone();
+        let server = HttpServer::new(move || {
+            App::new()
+                .app_data(web::Data::from(data_clone.clone()))
+                .default_service(web::to(handle))
+        })
+        .workers(1)
+        .bind(address)?;
+        let base_url = format!("http://{}", server.addrs()[0]);
+        let server = server.run();
+        let handle = server.handle();
+        tokio::spawn(server);
+
+        Ok(FakeApis {
+            slack_url: format!("{}/slack", base_url),
+            opsgenie_url: format!("{}/opsgenie", base_url),
+            data,
+            handle,
+        })
+    }
+
+    pub fn slack_url(&self) -> &str {
+        &self.slack_url
+    }
+
+    pub fn opsgenie_url(&self) -> &str {
+        &self.opsgenie_url
+    }
+
+    pub async fn stop(&self) {
+        self.handle.stop(true).await;
+    }
+
+    fn data(&self) -> MutexGuard<'_, FakeData> {
+        self.data.lock().expect("fake API data lock poisoned")
+    }
+
+    //
+    // Slack
+    //
+
+    /// Adds a user with the email `<name>@example.com`.
+    pub fn add_slack_user(&self, id: &str, name: &str, real_name: &str) {
+        self.data().slack_users.push(SlackUser {
+            id: id.into(),
+            name: name.into(),
+            real_name: real_name.into(),
+            email: format!("{}@example.com", name),
+            avatar_url: None,
+        });
+    }
+
+    pub fn set_slack_email(&self, id: &str, email: &str) {
+        if let Some(user) = self
+            .data()
+            .slack_users
+            .iter_mut()
+            .find(|user| user.id == id)
+        {
+            user.email = email.into();
+        }
+    }
+
+    pub fn set_slack_avatar(&self, id: &str, url: &str) {
+        if let Some(user) = self
+            .data()
+            .slack_users
+            .iter_mut()
+            .find(|user| user.id == id)
+        {
+            user.avatar_url = Some(url.into());
+        }
+    }
+
+    pub fn add_user_group(&self, id: &str, name: &str, handle: &str, members: &[&str]) {
+        self.data().user_groups.push(SlackUserGroup {
+            id: id.into(),
+            name: name.into(),
+            handle: handle.into(),
+            members: members.iter().map(|member| member.to_string()).collect(),
+        });
+    }
+
+    pub fn add_channel(&self, id: &str, name: &str, topic: &str) {
+        self.data().channels.push(SlackChannel {
+            id: id.into(),
+            name: name.into(),
+            topic: topic.into(),
+        });
+    }
+
+    /// Makes every call to the Slack `method` answer `ok: false` with `error`.
+    pub fn fail_slack_method(&self, method: &str, error: &str) {
+        self.data()
+            .slack_failures
+            .insert(method.into(), error.into());
+    }
+
+    pub fn user_group_members(&self, id: &str) -> Option<Vec<String>> {
+        self.data()
+            .user_groups
+            .iter()
+            .find(|user_group| user_group.id == id)
+            .map(|user_group| user_group.members.clone())
+    }
+
+    pub fn channel_topic(&self, id: &str) -> Option<String> {
+        self.data()
+            .channels
+            .iter()
+            .find(|channel| channel.id == id)
+            .map(|channel| channel.topic.clone())
+    }
+
+    pub fn messages(&self) -> Vec<PostedMessage> {
+        self.data().messages.clone()
+    }
+
+    pub fn slack_calls(&self) -> Vec<RecordedCall> {
+        self.data().slack_calls.clone()
+    }
+
+    //
+    // OpsGenie
+    //
+
+    pub fn add_opsgenie_user(&self, id: &str, username: &str, full_name: &str) {
+        self.data().opsgenie_users.push(OpsgenieUser {
+            id: id.into(),
+            username: username.into(),
+            full_name: full_name.into(),
+        });
+    }
+
+    pub fn add_schedule(&self, id: &str, name: &str) {
+        self.data().schedules.push(OpsgenieSchedule {
+            id: id.into(),
+            name: name.into(),
+            ..Default::default()
+        });
+    }
+
+    /// Teams are only expanded to their members by the on-calls API when they're listed with
+    /// their `participants`, otherwise clients have to look them up.
+    pub fn add_team(&self, id: &str, name: &str, member_ids: &[&str]) {
+        self.data().teams.push(OpsgenieTeam {
+            id: id.into(),
+            name: name.into(),
+            members: member_ids.iter().map(|id| id.to_string()).collect(),
+        });
+    }
+
+    /// Sets who is currently on call for the schedule, by OpsGenie user ID.
+    pub fn set_oncalls(&self, schedule_id: &str, user_ids: &[&str]) {
+        self.set_oncall_participants(schedule_id, user_participants(user_ids));
+    }
+
+    /// Sets the raw participants currently on call, e.g. to nest users in teams and escalations.
+    pub fn set_oncall_participants(&self, schedule_id: &str, participants: Vec<Value>) {
+        if let Some(schedule) = self.schedule_mut(&mut self.data(), schedule_id) {
+            schedule.oncalls = participants;
+        }
+    }
+
+    pub fn set_next_oncalls(&self, schedule_id: &str, user_ids: &[&str]) {
+        self.set_next_oncall_participants(schedule_id, user_participants(user_ids));
+    }
+
+    pub fn set_next_oncall_participants(&self, schedule_id: &str, participants: Vec<Value>) {
+        if let Some(schedule) = self.schedule_mut(&mut self.data(), schedule_id) {
+            schedule.next_oncalls = participants;
+        }
+    }
+
+    /// Sets when the next shift starts, as shown by the schedule's timeline.
+    pub fn set_handoff(&self, schedule_id: &str, handoff_at: i64) {
+        if let Some(schedule) = self.schedule_mut(&mut self.data(), schedule_id) {
+            schedule.handoff_at = Some(handoff_at);
+        }
+    }
+
+    /// Adds a period where the user is on call to the schedule's timeline.
+    pub fn add_timeline_period(
+        &self,
+        schedule_id: &str,
+        user_id: &str,
+        start: i64,
+        end: i64,
+        is_override: bool,
+    ) {
+        if let Some(schedule) = self.schedule_mut(&mut self.data(), schedule_id) {
+            schedule.periods.push(json!({
+                "startDate": rfc3339(start),
+                "endDate": rfc3339(end),
+                "type": if is_override { "override" } else { "default" },
+                "recipient": {"id": user_id, "type": "user"},
+            }));
+        }
+    }
+
+    /// Bodies of the overrides created for the schedule so far.
+    pub fn overrides(&self, schedule_id: &str) -> Vec<Value> {
+        self.data()
+            .overrides
+            .iter()
+            .filter(|(id, _)| id == schedule_id)
+            .map(|(_, body)| body.clone())
+            .collect()
+    }
+
+    /// Bodies of the alerts created so far.
+    pub fn alerts(&self) -> Vec<Value> {
+        self.data().alerts.clone()
+    }
+
+    pub fn opsgenie_calls(&self) -> Vec<RecordedCall> {
+        self.data().opsgenie_calls.clone()
+    }
+
+    fn schedule_mut<'a>(
+        &self,
+        data: &'a mut FakeData,
+        schedule_id: &str,
+    ) -> Option<&'a mut OpsgenieSchedule> {
+        data.schedules
+            .iter_mut()
+            .find(|schedule| schedule.id == schedule_id)
+    }
+}
+
+const WEEK: i64 = 7 * 24 * 60 * 60;
+
+fn rfc3339(timestamp: i64) -> String {
+    OffsetDateTime::from_unix_timestamp(timestamp)
+        .ok()
+        .and_then(|time| time.format(&Rfc3339).ok())
+        .unwrap_or_default()
+}
+
+fn user_participants(user_ids: &[&str]) -> Vec<Value> {
+    user_ids
+        .iter()
+        .map(|id| json!({"id": id, "type": "user"}))
+        .collect()
+}
+
+async fn handle(
+    req: HttpRequest,
+    body: web::Bytes,
+    data: web::Data<Mutex<FakeData>>,
+) -> HttpResponse {
+    let mut data = data.lock().expect("fake API data lock poisoned");
+    let body = String::from_utf8_lossy(&body).to_string();
+    let query = web::Query::<HashMap<String, String>>::from_query(req.query_string())
+        .map(|query| query.into_inner())
+        .unwrap_or_default();
+
+    if let Some(path) = req.path().strip_prefix("/slack") {
+        data.slack_calls.push(RecordedCall {
+            method: req.method().to_string(),
+            path: path.into(),
+            query: req.query_string().into(),
+            body: body.clone(),
+        });
+        let method = path.trim_start_matches('/');
+        let response = match data.slack_failures.get(method) {
+            Some(error) => Err(error.clone()),
+            None => slack_method(&mut data, method, &query, &body),
+        };
+        HttpResponse::Ok().json(match response {
+            Ok(mut response) => {
+                response["ok"] = json!(true);
+                response
+            }
+            Err(error) => json!({"ok": false, "error": error}),
+        })
+    } else if let Some(path) = req.path().strip_prefix("/opsgenie") {
+        data.opsgenie_calls.push(RecordedCall {
+            method: req.method().to_string(),
+            path: path.into(),
+            query: req.query_string().into(),
+            body: body.clone(),
+        });
+        opsgenie_endpoint(&mut data, req.method(), path, &body)
+    } else {
+        HttpResponse::NotFound().finish()
+    }
+}
+
+fn slack_method(
+    data: &mut FakeData,
+    method: &str,
+    query: &HashMap<String, String>,
+    body: &str,
+) -> Result<Value, String> {
+    let param = |name: &str| query.get(name).cloned().unwrap_or_default();
+    let user_group_json = |user_group: &SlackUserGroup| json!({"id": user_group.id, "name": user_group.name, "handle": user_group.handle});
+    let user_json = |user: &SlackUser| {
+        json!({
+            "id": user.id,
+            "name": user.name,
+            "real_name": user.real_name,
+            "is_bot": false,
+            "profile": {"image_48": user.avatar_url, "email": user.email},
+        })
+    };
+    let channel_json = |channel: &SlackChannel| {
+        json!({
+            "id": channel.id,
+            "name": channel.name,
+            "topic": {"value": channel.topic, "creator": "", "last_set": 0},
+        })
+    };
+
+    match method {
+        "usergroups.list" => Ok(json!({
+            "usergroups": data.user_groups.iter().map(user_group_json).collect::<Vec<_>>(),
+        })),
+        "usergroups.users.list" => {
+            let id = param("usergroup");
+            match data
+                .user_groups
+                .iter()
+                .find(|user_group| user_group.id == id)
+            {
+                Some(user_group) => Ok(json!({"users": user_group.members})),
+                None => Err("no_such_subteam".into()),
+            }
+        }
+        "usergroups.users.update" => {
+            let update = serde_json::from_str::<UserGroupUpdate>(body)
+                .map_err(|_| "invalid_arguments".to_string())?;
+            match data
+                .user_groups
+                .iter_mut()
+                .find(|user_group| user_group.id == update.usergroup)
+            {
+                Some(user_group) => {
+                    user_group.members = update.users;
+                    Ok(json!({"usergroup": user_group_json(user_group)}))
+                }
+                None => Err("no_such_subteam".into()),
+            }
+        }
+        "users.list" => Ok(json!({
+            "members": data.slack_users.iter().map(user_json).collect::<Vec<_>>(),
+        })),
+        "users.info" => {
+            let id = param("user");
+            match data.slack_users.iter().find(|user| user.id == id) {
+                Some(user) => Ok(json!({"user": user_json(user)})),
+                None => Err("user_not_found".into()),
+            }
+        }
+        "users.lookupByEmail" => {
+            let email = param("email");
+            match data
+                .slack_users
+                .iter()
+                .find(|user| user.email.eq_ignore_ascii_case(&email))
+            {
+                Some(user) => Ok(json!({"user": user_json(user)})),
+                None => Err("users_not_found".into()),
+            }
+        }
+        "conversations.list" => Ok(json!({
+            "channels": data.channels.iter().map(channel_json).collect::<Vec<_>>(),
+            "response_metadata": {"next_cursor": ""},
+        })),
+        "conversations.info" => {
+            let id = param("channel");
+            match data.channels.iter().find(|channel| channel.id == id) {
+                Some(channel) => Ok(json!({"channel": channel_json(channel)})),
+                None => Err("channel_not_found".into()),
+            }
+        }
+        "conversations.setTopic" => {
+            let set_topic = serde_json::from_str::<SetTopic>(body)
+                .map_err(|_| "invalid_arguments".to_string())?;
+            match data
+                .channels
+                .iter_mut()
+                .find(|channel| channel.id == set_topic.channel)
+            {
+                Some(channel) => {
+                    channel.topic = set_topic.topic;
+                    Ok(json!({"channel": channel_json(channel)}))
+                }
+                None => Err("channel_not_found".into()),
+            }
+        }
+        "chat.postMessage" => {
+            let message = serde_json::from_str::<PostMessage>(body)
+                .map_err(|_| "invalid_arguments".to_string())?;
+            // Posting to a user ID sends them a DM
+            if !data
+                .channels
+                .iter()
+                .any(|channel| channel.id == message.channel)
+                && !data
+                    .slack_users
+                    .iter()
+                    .any(|user| user.id == message.channel)
+            {
+                return Err("channel_not_found".into());
+            }
+            let ts = format!("{}.000000", data.messages.len() + 1);
+            let channel = message.channel.clone();
+            data.messages.push(PostedMessage {
+                channel: message.channel,
+                text: message.text,
+                thread_ts: message.thread_ts,
+                blocks: message.blocks,
+            });
+            Ok(json!({"channel": channel, "ts": ts}))
+        }
+        _ => Err("unknown_method".into()),
+    }
+}
+
+fn opsgenie_endpoint(data: &mut FakeData, method: &Method, path: &str, body: &str) -> HttpResponse {
+    let segments: Vec<&str> = path.trim_matches('/').split('/').collect();
+    let not_found = || HttpResponse::NotFound().json(json!({"message": "Not found"}));
+    let find_schedule = |id: &str| data.schedules.iter().find(|schedule| schedule.id == id);
+
+    match (method.as_str(), &segments[..]) {
+        ("GET", ["v2", "schedules"]) => HttpResponse::Ok().json(json!({
+            "data": data
+                .schedules
+                .iter()
+                .map(|schedule| json!({"id": schedule.id, "name": schedule.name}))
+                .collect::<Vec<_>>(),
+        })),
+        ("GET", ["v2", "schedules", id]) => match find_schedule(id) {
+            Some(schedule) => HttpResponse::Ok()
+                .json(json!({"data": {"id": schedule.id, "name": schedule.name}})),
+            None => not_found(),
+        },
+        ("GET", ["v2", "schedules", id, "on-calls"]) => match find_schedule(id) {
+            Some(schedule) => HttpResponse::Ok().json(json!({
+                "data": {"onCallParticipants": schedule.oncalls},
+            })),
+            None => not_found(),
+        },
+        ("GET", ["v2", "schedules", id, "next-on-calls"]) => match find_schedule(id) {
+            Some(schedule) => HttpResponse::Ok().json(json!({
+                "data": {"nextOnCallParticipants": schedule.next_oncalls},
+            })),
+            None => not_found(),
+        },
+        ("GET", ["v2", "schedules", id, "timeline"]) => match find_schedule(id) {
+            Some(schedule) => {
+                let periods = match schedule.handoff_at {
+                    Some(handoff_at) => vec![
+                        json!({
+                            "startDate": rfc3339(handoff_at - WEEK),
+                            "endDate": rfc3339(handoff_at),
+                        }),
+                        json!({
+                            "startDate": rfc3339(handoff_at),
+                            "endDate": rfc3339(handoff_at + WEEK),
+                        }),
+                    ],
+                    None => vec![],
+                };
+                HttpResponse::Ok().json(json!({
+                    "data": {"finalTimeline": {"rotations": [
+                        {"periods": periods},
+                        {"periods": schedule.periods},
+                    ]}},
+                }))
+            }
+            None => not_found(),
+        },
+        // Overrides take effect straight away and replace whoever is on call
+        ("POST", ["v2", "schedules", id, "overrides"]) => {
+            let body = serde_json::from_str::<Value>(body).unwrap_or(Value::Null);
+            let user_id = body["user"]["id"].as_str().unwrap_or_default().to_string();
+            let schedule_id = id.to_string();
+            match data
+                .schedules
+                .iter_mut()
+                .find(|schedule| schedule.id == schedule_id)
+            {
+                Some(schedule) => {
+                    schedule.oncalls = user_participants(&[&user_id]);
+                    data.overrides.push((schedule_id, body));
+                    HttpResponse::Created().json(json!({"data": {"alias": format!("override-{}", data.overrides.len())}}))
+                }
+                None => not_found(),
+            }
+        }
+        ("GET", ["v2", "users"]) => HttpResponse::Ok().json(json!({
+            "data": data
+                .opsgenie_users
+                .iter()
+                .map(|user| json!({"id": user.id, "username": user.username, "fullName": user.full_name}))
+                .collect::<Vec<_>>(),
+        })),
+        ("GET", ["v2", "users", id]) => match data.opsgenie_users.iter().find(|user| user.id == *id) {
+            Some(user) => HttpResponse::Ok().json(json!({
+                "data": {"id": user.id, "username": user.username, "fullName": user.full_name},
+            })),
+            None => not_found(),
+        },
+        ("GET", ["v2", "teams", id]) => match data.teams.iter().find(|team| team.id == *id) {
+            Some(team) => HttpResponse::Ok().json(json!({
+                "data": {
+                    "id": team.id,
+                    "name": team.name,
+                    "members": team
+                        .members
+                        .iter()
+                        .map(|id| json!({"user": {"id": id}, "role": "user"}))
+                        .collect::<Vec<_>>(),
+                },
+            })),
+            None => not_found(),
+        },
+        ("POST", ["v2", "alerts"]) => {
+            data.alerts
+                .push(serde_json::from_str(body).unwrap_or(Value::Null));
+            let request_id = format!("request-{}", data.alerts.len());
+            HttpResponse::Accepted().json(json!({"requestId": request_id}))
+        }
+        // Alerts are never closed, so every alert counts as open whatever the query
+        ("GET", ["v2", "alerts", "count"]) => {
+            HttpResponse::Ok().json(json!({"data": {"count": data.alerts.len()}}))
+        }
+        ("GET", ["v2", "alerts", "requests", request_id]) => {
+            match request_id
+                .strip_prefix("request-")
+                .and_then(|n| n.parse::<usize>().ok())
+                .filter(|n| *n >= 1 && *n <= data.alerts.len())
+            {
+                Some(n) => HttpResponse::Ok().json(json!({"data": {"alertId": format!("alert-{}", n)}})),
+                None => not_found(),
+            }
+        }
+        _ => not_found(),
+    }
+}
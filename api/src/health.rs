@@ -0,0 +1,163 @@
+use crate::{
+    audit, chat_provider,
+    chat_provider::ChatProviderKind,
+    oncall_provider::{self, OncallProviderKind},
+    scheduler::{ChannelTarget, ScheduleKey, UserGroupTarget},
+};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use tokio::sync::Mutex;
+
+/// Why the last reconcile of a sync or notification failed.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Failure {
+    pub error: String,
+    /// Error code returned by the chat service, e.g. `not_in_channel` or `missing_scope`
+    pub error_code: Option<String>,
+}
+
+impl From<&chat_provider::Error> for Failure {
+    fn from(error: &chat_provider::Error) -> Failure {
+        Failure {
+            error: format!("{}", error),
+            error_code: error.code().map(String::from),
+        }
+    }
+}
+
+impl From<&oncall_provider::Error> for Failure {
+    fn from(error: &oncall_provider::Error) -> Failure {
+        Failure {
+            error: format!("{}", error),
+            error_code: None,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct WorkerStatus {
+    pub last_run_at: i64,
+    /// None if it hasn't succeeded since the bot started
+    pub last_success_at: Option<i64>,
+    /// None if the last run succeeded
+    pub failure: Option<Failure>,
+}
+
+impl WorkerStatus {
+    fn next(previous: Option<&WorkerStatus>, failure: Option<Failure>) -> WorkerStatus {
+        let now = audit::now();
+        WorkerStatus {
+            last_run_at: now,
+            last_success_at: match failure {
+                None => Some(now),
+                Some(_) => previous.and_then(|status| status.last_success_at),
+            },
+            failure,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct UserGroupHealth {
+    pub oncall_sync_id: i32,
+    pub oncall_provider: OncallProviderKind,
+    pub oncall_id: String,
+    pub chat_provider: ChatProviderKind,
+    pub user_group_id: String,
+    #[serde(flatten)]
+    pub status: WorkerStatus,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ChannelHealth {
+    pub notification_id: i32,
+    pub oncall_provider: OncallProviderKind,
+    pub oncall_id: String,
+    pub chat_provider: ChatProviderKind,
+    pub slack_channel_id: String,
+    #[serde(flatten)]
+    pub status: WorkerStatus,
+}
+
+/// Outcome of the latest reconcile of every sync and notification, keyed by their DB ID.
+pub struct Health {
+    user_groups: Mutex<HashMap<i32, UserGroupHealth>>,
+    channels: Mutex<HashMap<i32, ChannelHealth>>,
+}
+
+impl Health {
+    pub fn new() -> Health {
+        Health {
+            user_groups: Mutex::new(HashMap::new()),
+            channels: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub async fn record_user_group(
+        &self,
+        schedule: &ScheduleKey,
+        target: &UserGroupTarget,
+        failure: Option<Failure>,
+    ) {
+        let mut user_groups = self.user_groups.lock().await;
+        let status = WorkerStatus::next(
+            user_groups
+                .get(&target.oncall_sync_id)
+                .map(|health| &health.status),
+            failure,
+        );
+        user_groups.insert(
+            target.oncall_sync_id,
+            UserGroupHealth {
+                oncall_sync_id: target.oncall_sync_id,
+                oncall_provider: schedule.oncall_provider,
+                oncall_id: schedule.oncall_id.clone(),
+                chat_provider: target.chat_provider,
+                user_group_id: target.user_group_id.clone(),
+                status,
+            },
+        );
+    }
+
+    pub async fn record_channel(
+        &self,
+        schedule: &ScheduleKey,
+        target: &ChannelTarget,
+        failure: Option<Failure>,
+    ) {
+        let mut channels = self.channels.lock().await;
+        let status = WorkerStatus::next(
+            channels
+                .get(&target.notification_id)
+                .map(|health| &health.status),
+            failure,
+        );
+        channels.insert(
+            target.notification_id,
+            ChannelHealth {
+                notification_id: target.notification_id,
+                oncall_provider: schedule.oncall_provider,
+                oncall_id: schedule.oncall_id.clone(),
+                chat_provider: target.chat_provider,
+                slack_channel_id: target.slack_channel_id.clone(),
+                status,
+            },
+        );
+    }
+
+    pub async fn remove_user_group(&self, oncall_sync_id: i32) {
+        self.user_groups.lock().await.remove(&oncall_sync_id);
+    }
+
+    pub async fn remove_channel(&self, notification_id: i32) {
+        self.channels.lock().await.remove(&notification_id);
+    }
+
+    pub async fn user_groups(&self) -> Vec<UserGroupHealth> {
+        self.user_groups.lock().await.values().cloned().collect()
+    }
+
+    pub async fn channels(&self) -> Vec<ChannelHealth> {
+        self.channels.lock().await.values().cloned().collect()
+    }
+}
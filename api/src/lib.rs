@@ -0,0 +1,2521 @@
+// Diesel 1.x's derives and `table!` expand to impls nested inside consts, which newer compilers
+// warn about.
+#![allow(non_local_definitions)]
+
+#[macro_use]
+extern crate diesel;
+
+use crate::{
+    cache::Cache,
+    chat_provider::{ChatProvider, ChatProviderKind},
+    models::NewAuditEvent,
+    oncall_provider::OncallProviderKind,
+    scheduler::{ChannelTarget, ScheduleKey, Scheduler, SchedulerConfig, UserGroupTarget},
+    slack_events::SeenEvents,
+    template::{Placeholder, Templates},
+};
+use actix_web::{
+    body::{BoxBody, EitherBody},
+    dev::{ServiceFactory, ServiceRequest, ServiceResponse},
+    get, post, web, App, HttpRequest, HttpResponse, Responder, Result,
+};
+use futures::future::BoxFuture;
+use futures_util::future::join_all;
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+use tokio::join;
+
+mod api_client;
+mod audit;
+mod auth;
+mod auto_mapping;
+mod cache;
+mod chat_provider;
+mod db;
+pub mod fake_apis;
+mod health;
+mod mattermost;
+mod models;
+mod notifier;
+mod oncall_provider;
+mod opsgenie;
+mod pagerduty;
+mod preview;
+mod reminders;
+mod reports;
+mod scheduler;
+mod schema;
+mod shift_dms;
+mod slack;
+mod slack_events;
+mod slack_interactivity;
+mod slash_command;
+mod template;
+mod unmapped;
+mod user_group_sync;
+
+const DEFAULT_AUDIT_PAGE_SIZE: i64 = 50;
+const MAX_AUDIT_PAGE_SIZE: i64 = 500;
+const DEFAULT_SHIFT_REPORT_DAYS: i64 = 30;
+
+#[derive(Serialize, Deserialize, Debug)]
+struct OncallSync {
+    id: i32,
+    oncall_provider: OncallProviderKind,
+    oncall_id: String,
+    oncall_name: String,
+    chat_provider: ChatProviderKind,
+    user_group_id: String,
+    user_group_name: String,
+    user_group_handle: String,
+    dry_run: bool,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct UserMapping {
+    id: i32,
+    opsgenie_user_id: String,
+    slack_user_id: String,
+    shift_dms_opt_out: bool,
+    oncall_provider: OncallProviderKind,
+}
+
+impl From<models::UserMapping> for UserMapping {
+    fn from(user_mapping: models::UserMapping) -> UserMapping {
+        UserMapping {
+            id: user_mapping.id,
+            opsgenie_user_id: user_mapping.opsgenie_id,
+            slack_user_id: user_mapping.slack_id,
+            shift_dms_opt_out: user_mapping.shift_dms_opt_out,
+            oncall_provider: user_mapping.oncall_provider,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct Notification {
+    pub id: i32,
+    pub oncall_provider: OncallProviderKind,
+    pub oncall_id: String,
+    pub oncall_name: String,
+    pub chat_provider: ChatProviderKind,
+    pub slack_channel_id: String,
+    pub slack_channel_name: String,
+    pub dry_run: bool,
+    pub show_next_oncall: bool,
+    #[serde(flatten)]
+    pub templates: Templates,
+    pub rich_messages: bool,
+}
+
+/// A user group and the schedules whose oncalls it's synced with.
+#[derive(Serialize, Deserialize, Debug)]
+struct SyncedUserGroup {
+    chat_provider: ChatProviderKind,
+    user_group_id: String,
+    user_group_name: String,
+    user_group_handle: String,
+    /// One per schedule, the group's members are everyone on call for any of them
+    syncs: Vec<OncallSync>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct AddSyncRequest {
+    oncall_provider: Option<OncallProviderKind>,
+    /// Schedules to add to the user group's
+    oncall_ids: Vec<String>,
+    chat_provider: Option<ChatProviderKind>,
+    user_group_id: String,
+    #[serde(default)]
+    dry_run: bool,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct SetSyncDryRunRequest {
+    oncall_sync_id: i32,
+    dry_run: bool,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct RemoveSyncRequest {
+    oncall_sync_id: i32,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct SyncedWithRequest {
+    oncall_provider: Option<OncallProviderKind>,
+    oncall_id: String,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct GetSlackUserMappingRequest {
+    slack_user_id: String,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct SyncedWithResponse {
+    syncs: Vec<OncallSync>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct ListSyncsResponse {
+    user_groups: Vec<SyncedUserGroup>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct ListOncallsResponse {
+    oncalls: Vec<oncall_provider::Oncall>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct ListUserGroupsResponse {
+    user_groups: Vec<chat_provider::UserGroup>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct ListSlackUsersResponse {
+    users: Vec<chat_provider::User>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct ListSlackChannelsResponse {
+    channels: Vec<chat_provider::Channel>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct ListOpsgenieUsersResponse {
+    users: Vec<oncall_provider::User>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct AddUserMapRequest {
+    slack_id: String,
+    opsgenie_id: String,
+    chat_provider: Option<ChatProviderKind>,
+    oncall_provider: Option<OncallProviderKind>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct RemoveUserMapRequest {
+    user_mapping_id: i32,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct SetUserMapShiftDmsOptOutRequest {
+    user_mapping_id: i32,
+    shift_dms_opt_out: bool,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct AutoMapUsersRequest {
+    /// Only report the mappings that would be created
+    #[serde(default)]
+    dry_run: bool,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct GetNotificationForSlackChannelRequest {
+    slack_channel_id: String,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct GetNotificationForOncallRequest {
+    oncall_id: String,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct AddNotificationRequest {
+    oncall_provider: Option<OncallProviderKind>,
+    oncall_id: String,
+    chat_provider: Option<ChatProviderKind>,
+    slack_channel_id: String,
+    #[serde(default)]
+    dry_run: bool,
+    /// Also show who is next and when they take over
+    #[serde(default)]
+    show_next_oncall: bool,
+    /// Templates for the topic and announcements, the defaults when left out
+    #[serde(flatten)]
+    templates: Templates,
+    /// Post announcements with buttons, on chat providers that support it
+    #[serde(default)]
+    rich_messages: bool,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct PreviewTemplatesRequest {
+    oncall_provider: Option<OncallProviderKind>,
+    oncall_id: String,
+    chat_provider: Option<ChatProviderKind>,
+    #[serde(flatten)]
+    templates: Templates,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct PreviewTemplatesResponse {
+    topic: String,
+    message: String,
+    out_of_hours_message: String,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct SetNotificationDryRunRequest {
+    notification_id: i32,
+    dry_run: bool,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct SetNotificationShowNextOncallRequest {
+    notification_id: i32,
+    show_next_oncall: bool,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct SetNotificationRichMessagesRequest {
+    notification_id: i32,
+    rich_messages: bool,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct RemoveNotificationRequest {
+    notification_id: i32,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct ListUserMappingsResponse {
+    user_mappings: Vec<UserMapping>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct GetSlackUserMappingResponse {
+    user_mapping: Option<UserMapping>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct ListNotificationsResponse {
+    notifications: Vec<Notification>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct GetNotificationForSlackChannelResponse {
+    notifications: Vec<Notification>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct GetNotificationForOncallResponse {
+    notifications: Vec<Notification>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct AddNotificationResponse {
+    notification: Notification,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct RemoveNotificationResponse {
+    notification: Notification,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct OpsgenieWebhookResponse {
+    oncall_id: Option<String>,
+    syncs: usize,
+    notifications: usize,
+    triggered: bool,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct ListAuditEventsRequest {
+    oncall_id: Option<String>,
+    slack_channel_id: Option<String>,
+    /// Unix timestamps in seconds. `since` is inclusive and `until` is exclusive.
+    since: Option<i64>,
+    until: Option<i64>,
+    page: Option<i64>,
+    page_size: Option<i64>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct ListAuditEventsResponse {
+    events: Vec<models::AuditEvent>,
+    page: i64,
+    page_size: i64,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct AddApiTokenRequest {
+    name: String,
+    role: auth::Role,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct AddApiTokenResponse {
+    #[serde(flatten)]
+    api_token: models::ApiToken,
+    /// The only time the token is returned, it can't be recovered afterwards
+    token: String,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct RemoveApiTokenRequest {
+    api_token_id: i32,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct ListApiTokensResponse {
+    api_tokens: Vec<models::ApiToken>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct WhoamiResponse {
+    name: String,
+    role: auth::Role,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct ShiftReportRequest {
+    /// Only report on schedules of this provider
+    oncall_provider: Option<OncallProviderKind>,
+    oncall_id: Option<String>,
+    /// Unix timestamps in seconds. Defaults to the last `DEFAULT_SHIFT_REPORT_DAYS` days.
+    since: Option<i64>,
+    until: Option<i64>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct ShiftReportEntry {
+    #[serde(flatten)]
+    summary: reports::ShiftSummary,
+    oncall_name: Option<String>,
+    user_name: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct ShiftReportResponse {
+    since: i64,
+    until: i64,
+    entries: Vec<ShiftReportEntry>,
+}
+
+/// Someone on call right now who has no user mapping.
+#[derive(Serialize, Deserialize, Debug)]
+struct UnmappedOncall {
+    oncall_provider: OncallProviderKind,
+    oncall_id: String,
+    oncall_name: Option<String>,
+    user_id: String,
+    user_name: Option<String>,
+    /// Unix timestamp of the start of their shift
+    since: i64,
+    /// Unix timestamp of when the admin channel was warned, if it was
+    warned_at: Option<i64>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct ListUnmappedOncallsResponse {
+    unmapped_oncalls: Vec<UnmappedOncall>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct PreviewRequest {
+    oncall_sync_id: Option<i32>,
+    notification_id: Option<i32>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct PreviewResponse {
+    user_groups: Vec<preview::UserGroupPreview>,
+    channels: Vec<preview::ChannelPreview>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct HealthResponse {
+    /// False if the last reconcile of any sync or notification failed
+    healthy: bool,
+    user_groups: Vec<health::UserGroupHealth>,
+    channels: Vec<health::ChannelHealth>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct SlackChallengeResponse {
+    challenge: String,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct ErrorResponse {
+    error: String,
+}
+
+fn format_user_mapping(user_mapping: &models::UserMapping) -> String {
+    format!("{}={}", user_mapping.opsgenie_id, user_mapping.slack_id)
+}
+
+fn format_api_token(api_token: &models::ApiToken) -> String {
+    format!("{} ({})", api_token.name, api_token.role)
+}
+
+//
+// Request Verification
+//
+
+fn verify_slack_request(http_req: &HttpRequest, body: &[u8]) -> bool {
+    let header = |name| {
+        http_req
+            .headers()
+            .get(name)
+            .and_then(|value| value.to_str().ok())
+            .unwrap_or_default()
+    };
+    slack::verify_request_signature(
+        header("X-Slack-Request-Timestamp"),
+        header("X-Slack-Signature"),
+        body,
+    )
+}
+
+//
+// Cache Functions
+//
+
+async fn slack_users_update() -> chat_provider::Result<HashMap<String, chat_provider::User>> {
+    let mut users = HashMap::new();
+    for kind in ChatProviderKind::configured() {
+        users.extend(
+            kind.provider()
+                .list_users()
+                .await?
+                .into_iter()
+                .map(|user| (user.id.clone(), user)),
+        );
+    }
+    Ok(users)
+}
+
+/// Oncalls last listed from each provider, so that one provider failing doesn't drop the oncalls
+/// of the others from the cache.
+type LastOncalls = Arc<Mutex<HashMap<OncallProviderKind, Vec<oncall_provider::Oncall>>>>;
+
+async fn oncall_update(
+    last_oncalls: LastOncalls,
+) -> oncall_provider::Result<HashMap<String, oncall_provider::Oncall>> {
+    let mut oncalls = HashMap::new();
+    let mut error = None;
+    for kind in OncallProviderKind::configured() {
+        let listed = match kind.provider().list_oncalls().await {
+            Ok(listed) => {
+                last_oncalls.lock().unwrap().insert(kind, listed.clone());
+                listed
+            }
+            Err(e) => match last_oncalls.lock().unwrap().get(&kind) {
+                Some(listed) => {
+                    warn!(
+                        "Error listing {} oncalls, keeping the last ones: {}",
+                        kind, e
+                    );
+                    listed.clone()
+                }
+                None => {
+                    warn!("Error listing {} oncalls: {}", kind, e);
+                    error = Some(e);
+                    continue;
+                }
+            },
+        };
+        oncalls.extend(listed.into_iter().map(|oncall| (oncall.id.clone(), oncall)));
+    }
+    match error {
+        Some(e) if oncalls.is_empty() => Err(e),
+        _ => Ok(oncalls),
+    }
+}
+
+async fn slack_channel_update() -> chat_provider::Result<HashMap<String, chat_provider::Channel>> {
+    let mut channels = HashMap::new();
+    for kind in ChatProviderKind::configured() {
+        channels.extend(
+            kind.provider()
+                .list_channels()
+                .await?
+                .into_iter()
+                .map(|channel| (channel.id.clone(), channel)),
+        );
+    }
+    Ok(channels)
+}
+
+//
+// Helper functions
+//
+
+/// Full names of the users of the given oncall providers, by user ID. Providers that can't be
+/// reached are left out.
+async fn user_names(
+    providers: impl Iterator<Item = OncallProviderKind>,
+) -> HashMap<String, String> {
+    let mut providers: Vec<OncallProviderKind> = providers.collect();
+    providers.sort_by_key(|provider| provider.as_str());
+    providers.dedup();
+    join_all(
+        providers
+            .into_iter()
+            .map(|provider| provider.provider().list_users()),
+    )
+    .await
+    .into_iter()
+    .filter_map(|users| users.ok())
+    .flatten()
+    .map(|user| (user.id, user.full_name))
+    .collect()
+}
+
+async fn db_notification_to_response(
+    notification: models::NotifiedSlackChannel,
+    data: &Arc<AppState>,
+) -> anyhow::Result<Notification> {
+    let slack_channel = data
+        .slack_channel_cache
+        .get(&notification.slack_channel_id)
+        .await?
+        .ok_or_else(|| anyhow::anyhow!("slack channel not found"))?;
+    let oncall = data
+        .oncall_cache
+        .get(&notification.oncall_id)
+        .await?
+        .ok_or_else(|| anyhow::anyhow!("oncall not found"))?;
+    let templates = notification.templates();
+    Ok(Notification {
+        id: notification.id,
+        oncall_provider: notification.oncall_provider,
+        oncall_id: notification.oncall_id,
+        oncall_name: oncall.name.clone(),
+        chat_provider: notification.chat_provider,
+        slack_channel_id: notification.slack_channel_id,
+        slack_channel_name: slack_channel.name.clone(),
+        dry_run: notification.dry_run,
+        show_next_oncall: notification.show_next_oncall,
+        templates,
+        rich_messages: notification.rich_messages,
+    })
+}
+
+/// Works out which provider owns an oncall when the client didn't specify one, by looking the ID
+/// up among the schedules of every configured provider. Falls back to the default provider.
+async fn resolve_oncall_provider(
+    oncall_id: &str,
+    requested: Option<OncallProviderKind>,
+    data: &Arc<AppState>,
+) -> OncallProviderKind {
+    if let Some(kind) = requested {
+        return kind;
+    }
+    match data.oncall_cache.get(&oncall_id.to_string()).await {
+        Ok(Some(oncall)) => oncall.provider,
+        Ok(None) => OncallProviderKind::default(),
+        Err(e) => {
+            warn!("Error fetching oncalls while resolving provider: {}", e);
+            OncallProviderKind::default()
+        }
+    }
+}
+
+/// Works out which chat provider a channel belongs to when the client didn't specify one, using
+/// the channel cache. Falls back to the default provider.
+async fn resolve_chat_provider_for_channel(
+    slack_channel_id: &str,
+    requested: Option<ChatProviderKind>,
+    data: &Arc<AppState>,
+) -> ChatProviderKind {
+    if let Some(kind) = requested {
+        return kind;
+    }
+    match data
+        .slack_channel_cache
+        .get(&slack_channel_id.to_string())
+        .await
+    {
+        Ok(Some(channel)) => channel.provider,
+        Ok(None) => ChatProviderKind::default(),
+        Err(e) => {
+            warn!("Error fetching channels while resolving provider: {}", e);
+            ChatProviderKind::default()
+        }
+    }
+}
+
+/// Runs `lookup` against the requested chat provider, or against every configured one if the
+/// client didn't specify, and returns the first hit. Not-found errors are skipped over so that
+/// `Ok(None)` means no provider knows about the resource.
+async fn find_in_chat_providers<T, F>(
+    requested: Option<ChatProviderKind>,
+    lookup: F,
+) -> chat_provider::Result<Option<T>>
+where
+    F: Fn(&'static dyn ChatProvider) -> BoxFuture<'static, chat_provider::Result<T>>,
+{
+    let providers = match requested {
+        Some(kind) => vec![kind],
+        None => ChatProviderKind::configured(),
+    };
+    for kind in providers {
+        match lookup(kind.provider()).await {
+            Ok(value) => return Ok(Some(value)),
+            Err(e) if e.is_not_found() => {}
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(None)
+}
+
+//
+// App State
+//
+
+pub struct AppState {
+    scheduler: Scheduler,
+    seen_slack_events: SeenEvents,
+    slack_user_cache: Cache<String, chat_provider::User, chat_provider::Error>, // Key is the user ID
+    oncall_cache: Cache<String, oncall_provider::Oncall, oncall_provider::Error>, // Key is the oncall ID
+    slack_channel_cache: Cache<String, chat_provider::Channel, chat_provider::Error>, // Key is the slack channel ID
+}
+
+impl AppState {
+    /// Loads every sync and notification from the DB and starts the scheduler for them.
+    pub async fn new() -> anyhow::Result<AppState> {
+        auth::check_config();
+        let (syncs, notifiers) = join!(
+            web::block(move || {
+                let conn = db::connection();
+                db::list_oncall_syncs(&conn)
+            }),
+            web::block(move || {
+                let conn = db::connection();
+                db::list_notified_slack_channels(&conn)
+            })
+        );
+        let syncs = syncs??;
+        let notifiers = notifiers??;
+
+        let scheduler = Scheduler::new(SchedulerConfig::from_env());
+        for sync in syncs {
+            scheduler
+                .add_user_group(
+                    ScheduleKey {
+                        oncall_provider: sync.oncall_provider,
+                        oncall_id: sync.oncall_id,
+                    },
+                    UserGroupTarget {
+                        oncall_sync_id: sync.id,
+                        chat_provider: sync.chat_provider,
+                        user_group_id: sync.user_group_id,
+                        dry_run: sync.dry_run,
+                    },
+                )
+                .await;
+        }
+        for notifier in notifiers {
+            let templates = notifier.templates();
+            scheduler
+                .add_channel(
+                    ScheduleKey {
+                        oncall_provider: notifier.oncall_provider,
+                        oncall_id: notifier.oncall_id,
+                    },
+                    ChannelTarget {
+                        notification_id: notifier.id,
+                        chat_provider: notifier.chat_provider,
+                        slack_channel_id: notifier.slack_channel_id,
+                        dry_run: notifier.dry_run,
+                        show_next_oncall: notifier.show_next_oncall,
+                        templates,
+                        rich_messages: notifier.rich_messages,
+                    },
+                )
+                .await;
+        }
+
+        let last_oncalls = LastOncalls::default();
+        Ok(AppState {
+            scheduler,
+            seen_slack_events: SeenEvents::new(),
+            slack_user_cache: Cache::new(Duration::from_secs(60), slack_users_update),
+            oncall_cache: Cache::new(Duration::from_secs(60), move || {
+                oncall_update(last_oncalls.clone())
+            }),
+            slack_channel_cache: Cache::new(Duration::from_secs(60), slack_channel_update),
+        })
+    }
+}
+
+//
+// Endpoints
+//
+
+#[get("/list_slack_users")]
+async fn list_slack_users(data: web::Data<Arc<AppState>>) -> Result<impl Responder> {
+    let users = match data.slack_user_cache.get_all().await {
+        Ok(users) => users,
+        Err(e) => return Ok(e.into()),
+    };
+    Ok(HttpResponse::Ok().json(ListSlackUsersResponse {
+        users: users.into_values().collect(),
+    }))
+}
+
+#[get("/list_opsgenie_users")]
+async fn list_opsgenie_users() -> Result<impl Responder> {
+    let users = match join_all(
+        OncallProviderKind::configured()
+            .into_iter()
+            .map(|kind| kind.provider().list_users()),
+    )
+    .await
+    .into_iter()
+    .collect::<Result<Vec<_>, _>>()
+    {
+        Ok(users) => users.into_iter().flatten().collect(),
+        Err(e) => {
+            return Ok(HttpResponse::InternalServerError().json(ErrorResponse {
+                error: format!("{:?}", e),
+            }));
+        }
+    };
+    Ok(HttpResponse::Ok().json(ListOpsgenieUsersResponse { users }))
+}
+
+#[get("/list_user_groups")]
+async fn list_user_groups() -> Result<impl Responder> {
+    let user_groups = match join_all(
+        ChatProviderKind::configured()
+            .into_iter()
+            .map(|kind| kind.provider().list_user_groups()),
+    )
+    .await
+    .into_iter()
+    .collect::<Result<Vec<_>, _>>()
+    {
+        Ok(user_groups) => user_groups.into_iter().flatten().collect(),
+        Err(e) => return Ok(e.into()),
+    };
+    Ok(HttpResponse::Ok().json(ListUserGroupsResponse { user_groups }))
+}
+
+#[get("/list_oncalls")]
+async fn list_oncalls(data: web::Data<Arc<AppState>>) -> Result<impl Responder> {
+    let oncalls = match data.oncall_cache.get_all().await {
+        Ok(oncalls) => oncalls,
+        Err(e) => {
+            return Ok(HttpResponse::InternalServerError().json(ErrorResponse {
+                error: format!("{:?}", e),
+            }));
+        }
+    };
+    Ok(HttpResponse::Ok().json(ListOncallsResponse {
+        oncalls: oncalls.into_values().collect(),
+    }))
+}
+
+#[get("/list_slack_channels")]
+async fn list_slack_channels(data: web::Data<Arc<AppState>>) -> Result<impl Responder> {
+    let channels = match data.slack_channel_cache.get_all().await {
+        Ok(channels) => channels,
+        Err(e) => return Ok(e.into()),
+    };
+    Ok(HttpResponse::Ok().json(ListSlackChannelsResponse {
+        channels: channels.into_values().collect(),
+    }))
+}
+
+#[post("/add_user_map")]
+async fn add_user_map(
+    req: web::Json<AddUserMapRequest>,
+    identity: web::ReqData<auth::Identity>,
+) -> Result<impl Responder> {
+    // Confirm users exist. If the client didn't say which provider the user belongs to, accept
+    // any configured provider that knows about them.
+    let providers = match req.oncall_provider {
+        Some(kind) => vec![kind],
+        None => OncallProviderKind::configured(),
+    };
+    let find_opsgenie_user = async {
+        let mut result = None;
+        for kind in providers {
+            let user = kind.provider().get_user(&req.opsgenie_id).await;
+            let found = user.is_ok();
+            result = Some(user.map(|_| kind));
+            if found {
+                break;
+            }
+        }
+        result.ok_or_else(|| anyhow::anyhow!("no oncall provider is configured"))
+    };
+    let slack_id = req.slack_id.clone();
+    let find_slack_user = find_in_chat_providers(req.chat_provider, move |chat| {
+        let slack_id = slack_id.clone();
+        Box::pin(async move { chat.get_user(&slack_id).await })
+    });
+    let (slack_user, opsgenie_user) = join!(find_slack_user, find_opsgenie_user);
+    let opsgenie_user = opsgenie_user.and_then(|user| Ok(user?));
+    match slack_user {
+        Err(e) => {
+            return Ok(e.into());
+        }
+        Ok(None) => {
+            return Ok(HttpResponse::NotFound().json(ErrorResponse {
+                error: format!("Chat user with ID {} does not exist", req.slack_id),
+            }));
+        }
+        Ok(Some(_)) => {}
+    }
+    let oncall_provider = match opsgenie_user {
+        Err(e) => {
+            return Ok(HttpResponse::InternalServerError().json(ErrorResponse {
+                error: format!("{:?}", e),
+            }));
+        }
+        Ok(kind) => kind,
+    };
+
+    let conn = db::connection();
+    let add_res = match web::block(move || {
+        db::add_user_mapping(&conn, oncall_provider, &req.opsgenie_id, &req.slack_id)
+    })
+    .await
+    {
+        Err(blocking_error) => {
+            return Ok(HttpResponse::InternalServerError().json(ErrorResponse {
+                error: format!("{:?}", blocking_error),
+            }));
+        }
+        Ok(Err(db_error)) => {
+            return Ok(db_error.into());
+        }
+        Ok(Ok(res)) => res,
+    };
+    audit::record(NewAuditEvent {
+        oncall_provider: Some(oncall_provider),
+        new_value: Some(format_user_mapping(&add_res)),
+        ..NewAuditEvent::new(&identity.name, "add_user_mapping")
+    })
+    .await;
+    Ok(HttpResponse::Ok().json(add_res))
+}
+
+#[post("/remove_user_map")]
+async fn remove_user_map(
+    req: web::Json<RemoveUserMapRequest>,
+    identity: web::ReqData<auth::Identity>,
+) -> Result<impl Responder> {
+    let conn = db::connection();
+    let user_mapping_id = req.user_mapping_id;
+
+    // Remove user mapping from DB first.
+    let removed_user_mapping =
+        match web::block(move || db::remove_user_mapping(&conn, user_mapping_id)).await {
+            Err(blocking_error) => {
+                return Ok(HttpResponse::InternalServerError().json(ErrorResponse {
+                    error: format!("{:?}", blocking_error),
+                }));
+            }
+            Ok(Err(db_error)) => {
+                return Ok(db_error.into());
+            }
+            Ok(Ok(res)) => res,
+        };
+    audit::record(NewAuditEvent {
+        previous_value: Some(format_user_mapping(&removed_user_mapping)),
+        ..NewAuditEvent::new(&identity.name, "remove_user_mapping")
+    })
+    .await;
+
+    Ok(HttpResponse::Ok().json(removed_user_mapping))
+}
+
+#[post("/set_user_map_shift_dms_opt_out")]
+async fn set_user_map_shift_dms_opt_out(
+    req: web::Json<SetUserMapShiftDmsOptOutRequest>,
+    identity: web::ReqData<auth::Identity>,
+) -> Result<impl Responder> {
+    let user_mapping_id = req.user_mapping_id;
+    let opt_out = req.shift_dms_opt_out;
+    let user_mapping = match web::block(move || {
+        let conn = db::connection();
+        db::set_user_mapping_shift_dms_opt_out(&conn, user_mapping_id, opt_out)
+    })
+    .await
+    {
+        Err(blocking_error) => {
+            return Ok(HttpResponse::InternalServerError().json(ErrorResponse {
+                error: format!("{:?}", blocking_error),
+            }));
+        }
+        Ok(Err(db_error)) => {
+            return Ok(db_error.into());
+        }
+        Ok(Ok(res)) => res,
+    };
+    audit::record(NewAuditEvent {
+        new_value: Some(format!(
+            "{} shift_dms_opt_out={}",
+            format_user_mapping(&user_mapping),
+            opt_out
+        )),
+        ..NewAuditEvent::new(&identity.name, "set_user_map_shift_dms_opt_out")
+    })
+    .await;
+
+    Ok(HttpResponse::Ok().json(UserMapping::from(user_mapping)))
+}
+
+#[post("/user_mappings/auto")]
+async fn auto_map_users(
+    req: web::Json<AutoMapUsersRequest>,
+    identity: web::ReqData<auth::Identity>,
+) -> Result<impl Responder> {
+    match auto_mapping::run(&identity.name, req.dry_run).await {
+        Ok(report) => Ok(HttpResponse::Ok().json(report)),
+        Err(e) => Ok(e.into()),
+    }
+}
+
+#[post("/add_sync")]
+async fn add_sync(
+    req: web::Json<AddSyncRequest>,
+    identity: web::ReqData<auth::Identity>,
+    data: web::Data<Arc<AppState>>,
+) -> Result<impl Responder> {
+    if req.oncall_ids.is_empty() {
+        return Ok(HttpResponse::BadRequest().json(ErrorResponse {
+            error: "oncall_ids can't be empty".into(),
+        }));
+    }
+    let user_group_id = req.user_group_id.clone();
+    let user_group = find_in_chat_providers(req.chat_provider, move |chat| {
+        let user_group_id = user_group_id.clone();
+        Box::pin(async move { chat.get_user_group(&user_group_id).await })
+    });
+    // Verify oncall existence
+    let mut schedules = vec![];
+    for oncall_id in req.oncall_ids.iter() {
+        let oncall_provider = resolve_oncall_provider(oncall_id, req.oncall_provider, &data).await;
+        if let Err(e) = oncall_provider.provider().get_oncall_name(oncall_id).await {
+            if e.is_not_found() {
+                return Ok(HttpResponse::NotFound().json(ErrorResponse {
+                    error: format!("Oncall with ID {} does not exist", oncall_id),
+                }));
+            } else {
+                return Ok(HttpResponse::InternalServerError().json(ErrorResponse {
+                    error: format!("Error fetching oncalls from {}", oncall_provider),
+                }));
+            }
+        }
+        schedules.push((oncall_provider, oncall_id.clone()));
+    }
+    let chat_provider = match user_group.await {
+        Ok(Some(user_group)) => user_group.provider,
+        Ok(None) => {
+            return Ok(HttpResponse::NotFound().json(ErrorResponse {
+                error: format!("User group with ID {} does not exist", req.user_group_id),
+            }));
+        }
+        Err(e) => {
+            return Ok(e.into());
+        }
+    };
+
+    let user_group_id = req.user_group_id.clone();
+    let dry_run = req.dry_run;
+    let (added, user_group_syncs) = match web::block(move || {
+        let conn = db::connection();
+        let added = db::add_syncs(&conn, &schedules, chat_provider, &user_group_id, dry_run)?;
+        Ok::<_, db::Error>((
+            added,
+            db::get_user_group_syncs(&conn, chat_provider, &user_group_id)?,
+        ))
+    })
+    .await
+    {
+        Err(blocking_error) => {
+            return Ok(HttpResponse::InternalServerError().json(ErrorResponse {
+                error: format!("{:?}", blocking_error),
+            }));
+        }
+        Ok(Err(db_error)) => {
+            return Ok(db_error.into());
+        }
+        Ok(Ok(res)) => res,
+    };
+
+    // Start syncing if not already
+    for sync in added {
+        data.scheduler
+            .add_user_group(
+                ScheduleKey {
+                    oncall_provider: sync.oncall_provider,
+                    oncall_id: sync.oncall_id.clone(),
+                },
+                UserGroupTarget {
+                    oncall_sync_id: sync.id,
+                    chat_provider,
+                    user_group_id: sync.user_group_id.clone(),
+                    dry_run,
+                },
+            )
+            .await;
+        audit::record(NewAuditEvent {
+            oncall_sync_id: Some(sync.id),
+            oncall_provider: Some(sync.oncall_provider),
+            oncall_id: Some(sync.oncall_id.clone()),
+            chat_provider: Some(chat_provider),
+            user_group_id: Some(sync.user_group_id.clone()),
+            ..NewAuditEvent::new(&identity.name, "add_sync")
+        })
+        .await;
+    }
+
+    match syncs_to_response(user_group_syncs).await {
+        Err(e) => Ok(HttpResponse::InternalServerError().json(ErrorResponse {
+            error: format!("{:?}", e),
+        })),
+        Ok(syncs) => match group_syncs(syncs).pop() {
+            Some(user_group) => Ok(HttpResponse::Ok().json(user_group)),
+            None => Ok(HttpResponse::InternalServerError().json(ErrorResponse {
+                error: "User group has no syncs after adding them".into(),
+            })),
+        },
+    }
+}
+
+#[post("/remove_sync")]
+async fn remove_sync(
+    req: web::Json<RemoveSyncRequest>,
+    identity: web::ReqData<auth::Identity>,
+    data: web::Data<Arc<AppState>>,
+) -> Result<impl Responder> {
+    let conn = db::connection();
+    let oncall_sync_id = req.oncall_sync_id;
+
+    // First, remove the element from the DB
+    let deleted_sync = match web::block(move || db::remove_sync(&conn, oncall_sync_id)).await {
+        Err(blocking_error) => {
+            return Ok(HttpResponse::InternalServerError().json(ErrorResponse {
+                error: format!("{:?}", blocking_error),
+            }));
+        }
+        Ok(Err(db_error)) => {
+            return Ok(db_error.into());
+        }
+        Ok(Ok(res)) => res,
+    };
+
+    // Stop syncing if present
+    {
+        let schedule = ScheduleKey {
+            oncall_provider: deleted_sync.oncall_provider,
+            oncall_id: deleted_sync.oncall_id.clone(),
+        };
+        let target = UserGroupTarget {
+            oncall_sync_id: deleted_sync.id,
+            chat_provider: deleted_sync.chat_provider,
+            user_group_id: deleted_sync.user_group_id.clone(),
+            dry_run: deleted_sync.dry_run,
+        };
+        if !data.scheduler.remove_user_group(&schedule, &target).await {
+            warn!(
+                "User group {:?} not found in scheduler for {:?} after delete",
+                target, schedule
+            );
+        }
+    }
+    audit::record(NewAuditEvent {
+        oncall_sync_id: Some(deleted_sync.id),
+        oncall_provider: Some(deleted_sync.oncall_provider),
+        oncall_id: Some(deleted_sync.oncall_id.clone()),
+        chat_provider: Some(deleted_sync.chat_provider),
+        user_group_id: Some(deleted_sync.user_group_id.clone()),
+        ..NewAuditEvent::new(&identity.name, "remove_sync")
+    })
+    .await;
+
+    Ok(HttpResponse::Ok().json(deleted_sync))
+}
+
+#[post("/set_sync_dry_run")]
+async fn set_sync_dry_run(
+    req: web::Json<SetSyncDryRunRequest>,
+    identity: web::ReqData<auth::Identity>,
+    data: web::Data<Arc<AppState>>,
+) -> Result<impl Responder> {
+    let oncall_sync_id = req.oncall_sync_id;
+    let dry_run = req.dry_run;
+    let sync = match web::block(move || {
+        let conn = db::connection();
+        db::set_sync_dry_run(&conn, oncall_sync_id, dry_run)
+    })
+    .await
+    {
+        Err(blocking_error) => {
+            return Ok(HttpResponse::InternalServerError().json(ErrorResponse {
+                error: format!("{:?}", blocking_error),
+            }));
+        }
+        Ok(Err(db_error)) => {
+            return Ok(db_error.into());
+        }
+        Ok(Ok(res)) => res,
+    };
+
+    // Swap the scheduled target for one with the new flag
+    let schedule = ScheduleKey {
+        oncall_provider: sync.oncall_provider,
+        oncall_id: sync.oncall_id.clone(),
+    };
+    let target = UserGroupTarget {
+        oncall_sync_id: sync.id,
+        chat_provider: sync.chat_provider,
+        user_group_id: sync.user_group_id.clone(),
+        dry_run: !dry_run,
+    };
+    data.scheduler.remove_user_group(&schedule, &target).await;
+    data.scheduler
+        .add_user_group(schedule, UserGroupTarget { dry_run, ..target })
+        .await;
+    audit::record(NewAuditEvent {
+        oncall_sync_id: Some(sync.id),
+        oncall_provider: Some(sync.oncall_provider),
+        oncall_id: Some(sync.oncall_id.clone()),
+        chat_provider: Some(sync.chat_provider),
+        user_group_id: Some(sync.user_group_id.clone()),
+        new_value: Some(dry_run.to_string()),
+        ..NewAuditEvent::new(&identity.name, "set_sync_dry_run")
+    })
+    .await;
+
+    Ok(HttpResponse::Ok().json(sync))
+}
+
+#[get("/synced_with")]
+async fn synced_with(info: web::Query<SyncedWithRequest>) -> Result<impl Responder> {
+    let conn = db::connection();
+    let oncall_id = info.oncall_id.clone();
+    let oncall_provider = info.oncall_provider.unwrap_or_default();
+    let oncall_name = match oncall_provider.provider().get_oncall_name(&oncall_id).await {
+        Err(e) if e.is_not_found() => {
+            return Ok(HttpResponse::NotFound().json(ErrorResponse {
+                error: format!("Oncall with ID {} does not exist", oncall_id),
+            }));
+        }
+        Err(e) => {
+            return Ok(HttpResponse::InternalServerError().json(ErrorResponse {
+                error: format!("Error fetching oncalls from {}: {}", oncall_provider, e),
+            }));
+        }
+        Ok(name) => name,
+    };
+
+    let query = match web::block(move || {
+        db::get_syncs(&conn, &info.oncall_id).map(|syncs| {
+            syncs
+                .into_iter()
+                .filter(|sync| sync.oncall_provider == oncall_provider)
+                .collect::<Vec<_>>()
+        })
+    })
+    .await
+    {
+        Err(blocking_error) => {
+            return Ok(HttpResponse::InternalServerError().json(ErrorResponse {
+                error: format!("{:?}", blocking_error),
+            }));
+        }
+        Ok(Err(db_error)) => {
+            return Ok(db_error.into());
+        }
+        Ok(Ok(res)) => res,
+    };
+    let user_groups = join_all(query.iter().map(|sync| {
+        sync.chat_provider
+            .provider()
+            .get_user_group(&sync.user_group_id)
+    }))
+    .await
+    .into_iter()
+    .collect::<Result<Vec<_>, _>>();
+
+    let user_groups = match user_groups {
+        Err(e) => {
+            return Ok(HttpResponse::InternalServerError().json(ErrorResponse {
+                error: format!("{:?}", e),
+            }));
+        }
+        Ok(ug) => ug,
+    };
+
+    let syncs = user_groups
+        .into_iter()
+        .zip(query.into_iter())
+        .map(|(user_group, sync)| OncallSync {
+            id: sync.id,
+            oncall_provider,
+            oncall_id: oncall_id.clone(),
+            oncall_name: oncall_name.clone(),
+            chat_provider: user_group.provider,
+            user_group_id: user_group.id,
+            user_group_name: user_group.name,
+            user_group_handle: user_group.handle,
+            dry_run: sync.dry_run,
+        })
+        .collect();
+
+    Ok(HttpResponse::Ok().json(SyncedWithResponse { syncs }))
+}
+
+#[get("/list_syncs")]
+async fn list_syncs() -> Result<impl Responder> {
+    let conn = db::connection();
+
+    let query = match web::block(move || db::list_oncall_syncs(&conn)).await {
+        Err(blocking_error) => {
+            return Ok(HttpResponse::InternalServerError().json(ErrorResponse {
+                error: format!("{:?}", blocking_error),
+            }));
+        }
+        Ok(Err(db_error)) => {
+            return Ok(db_error.into());
+        }
+        Ok(Ok(res)) => res,
+    };
+    let syncs = match syncs_to_response(query).await {
+        Err(e) => {
+            return Ok(HttpResponse::InternalServerError().json(ErrorResponse {
+                error: format!("{:?}", e),
+            }));
+        }
+        Ok(syncs) => syncs,
+    };
+
+    Ok(HttpResponse::Ok().json(ListSyncsResponse {
+        user_groups: group_syncs(syncs),
+    }))
+}
+
+/// Looks up the names of the syncs' schedules and user groups.
+async fn syncs_to_response(query: Vec<models::OncallSync>) -> anyhow::Result<Vec<OncallSync>> {
+    let user_groups = join_all(query.iter().map(|sync| {
+        sync.chat_provider
+            .provider()
+            .get_user_group(&sync.user_group_id)
+    }))
+    .await
+    .into_iter()
+    .collect::<Result<Vec<_>, _>>()?;
+    let oncalls = join_all(query.iter().map(|sync| {
+        sync.oncall_provider
+            .provider()
+            .get_oncall_name(&sync.oncall_id)
+    }))
+    .await
+    .into_iter()
+    .collect::<Result<Vec<_>, _>>()?;
+
+    Ok(query
+        .into_iter()
+        .zip(user_groups)
+        .zip(oncalls)
+        .map(|((sync, user_group), oncall_name)| OncallSync {
+            id: sync.id,
+            oncall_provider: sync.oncall_provider,
+            oncall_id: sync.oncall_id,
+            oncall_name,
+            chat_provider: sync.chat_provider,
+            user_group_id: sync.user_group_id,
+            user_group_name: user_group.name,
+            user_group_handle: user_group.handle,
+            dry_run: sync.dry_run,
+        })
+        .collect())
+}
+
+/// Puts the syncs of each user group together, in the order the groups were first synced.
+fn group_syncs(syncs: Vec<OncallSync>) -> Vec<SyncedUserGroup> {
+    let mut user_groups: Vec<SyncedUserGroup> = vec![];
+    for sync in syncs {
+        match user_groups.iter_mut().find(|user_group| {
+            user_group.chat_provider == sync.chat_provider
+                && user_group.user_group_id == sync.user_group_id
+        }) {
+            Some(user_group) => user_group.syncs.push(sync),
+            None => user_groups.push(SyncedUserGroup {
+                chat_provider: sync.chat_provider,
+                user_group_id: sync.user_group_id.clone(),
+                user_group_name: sync.user_group_name.clone(),
+                user_group_handle: sync.user_group_handle.clone(),
+                syncs: vec![sync],
+            }),
+        }
+    }
+    user_groups
+}
+
+#[get("/list_user_mappings")]
+async fn list_user_mappings() -> Result<impl Responder> {
+    let user_mappings = match web::block(|| {
+        let conn = db::connection();
+        db::list_user_mappings(&conn)
+    })
+    .await
+    {
+        Err(blocking_error) => {
+            return Ok(HttpResponse::InternalServerError().json(ErrorResponse {
+                error: format!("{:?}", blocking_error),
+            }));
+        }
+        Ok(Err(db_error)) => {
+            return Ok(db_error.into());
+        }
+        Ok(Ok(res)) => res,
+    };
+
+    let user_mappings = user_mappings.into_iter().map(UserMapping::from).collect();
+
+    Ok(HttpResponse::Ok().json(ListUserMappingsResponse { user_mappings }))
+}
+
+#[get("/get_slack_user_mapping")]
+async fn get_slack_user_mapping(
+    info: web::Query<GetSlackUserMappingRequest>,
+) -> Result<impl Responder> {
+    let slack_user_id = info.into_inner().slack_user_id;
+    let user_mapping = match web::block(move || {
+        let conn = db::connection();
+        db::get_slack_user_mapping(&conn, &slack_user_id)
+    })
+    .await
+    {
+        Err(blocking_error) => {
+            return Ok(HttpResponse::InternalServerError().json(ErrorResponse {
+                error: format!("{:?}", blocking_error),
+            }));
+        }
+        Ok(Err(db_error)) => {
+            return Ok(db_error.into());
+        }
+        Ok(Ok(res)) => res,
+    }
+    .map(UserMapping::from);
+
+    Ok(HttpResponse::Ok().json(GetSlackUserMappingResponse { user_mapping }))
+}
+
+#[get("/notification/list")]
+async fn list_notifications(data: web::Data<Arc<AppState>>) -> Result<impl Responder> {
+    // Fetch notifications from DB
+    let notifications = match web::block(move || {
+        let conn = db::connection();
+        db::list_notified_slack_channels(&conn)
+    })
+    .await
+    {
+        Err(blocking_error) => {
+            return Ok(HttpResponse::InternalServerError().json(ErrorResponse {
+                error: format!("{:?}", blocking_error),
+            }));
+        }
+        Ok(Err(db_error)) => {
+            return Ok(db_error.into());
+        }
+        Ok(Ok(res)) => res,
+    };
+
+    let notifications: Vec<_> = match join_all(notifications.into_iter().map(|notification| {
+        let data = data.clone();
+        async move { db_notification_to_response(notification, &data).await }
+    }))
+    .await
+    .into_iter()
+    .collect::<Result<Vec<_>, anyhow::Error>>()
+    {
+        Ok(notifications) => notifications,
+        Err(e) => {
+            return Ok(HttpResponse::InternalServerError().json(ErrorResponse {
+                error: format!("{}", e),
+            }));
+        }
+    };
+
+    Ok(HttpResponse::Ok().json(ListNotificationsResponse { notifications }))
+}
+
+#[get("/notifications/slack")]
+async fn get_notification_for_slack_channel(
+    data: web::Data<Arc<AppState>>,
+    info: web::Query<GetNotificationForSlackChannelRequest>,
+) -> Result<impl Responder> {
+    let notifications = match web::block(move || {
+        let conn = db::connection();
+        db::get_oncalls_notified_in_channel(&conn, &info.slack_channel_id)
+    })
+    .await
+    {
+        Err(blocking_error) => {
+            return Ok(HttpResponse::InternalServerError().json(ErrorResponse {
+                error: format!("{:?}", blocking_error),
+            }));
+        }
+        Ok(Err(db_error)) => {
+            return Ok(db_error.into());
+        }
+        Ok(Ok(res)) => res,
+    };
+
+    let notifications = match join_all(notifications.into_iter().map(|notification| {
+        let data = data.clone();
+        async move { db_notification_to_response(notification, &data).await }
+    }))
+    .await
+    .into_iter()
+    .collect::<Result<Vec<_>, anyhow::Error>>()
+    {
+        Ok(notifications) => notifications,
+        Err(e) => {
+            return Ok(HttpResponse::InternalServerError().json(ErrorResponse {
+                error: format!("{}", e),
+            }));
+        }
+    };
+
+    Ok(HttpResponse::Ok().json(GetNotificationForSlackChannelResponse { notifications }))
+}
+
+#[get("/notifications/oncall")]
+async fn get_notification_for_oncall(
+    data: web::Data<Arc<AppState>>,
+    info: web::Query<GetNotificationForOncallRequest>,
+) -> Result<impl Responder> {
+    let notifications = match web::block(move || {
+        let conn = db::connection();
+        db::get_channels_notified_for_oncall(&conn, &info.oncall_id)
+    })
+    .await
+    {
+        Err(blocking_error) => {
+            return Ok(HttpResponse::InternalServerError().json(ErrorResponse {
+                error: format!("{:?}", blocking_error),
+            }));
+        }
+        Ok(Err(db_error)) => {
+            return Ok(db_error.into());
+        }
+        Ok(Ok(res)) => res,
+    };
+
+    let notifications = match join_all(notifications.into_iter().map(|notification| {
+        let data = data.clone();
+        async move { db_notification_to_response(notification, &data).await }
+    }))
+    .await
+    .into_iter()
+    .collect::<Result<Vec<_>, anyhow::Error>>()
+    {
+        Ok(notifications) => notifications,
+        Err(e) => {
+            return Ok(HttpResponse::InternalServerError().json(ErrorResponse {
+                error: format!("{}", e),
+            }));
+        }
+    };
+
+    Ok(HttpResponse::Ok().json(GetNotificationForOncallResponse { notifications }))
+}
+
+#[post("/notifications/add")]
+async fn add_notification(
+    data: web::Data<Arc<AppState>>,
+    req: web::Json<AddNotificationRequest>,
+    identity: web::ReqData<auth::Identity>,
+) -> Result<impl Responder> {
+    if let Err(error) = req.templates.validate() {
+        return Ok(HttpResponse::BadRequest().json(ErrorResponse { error }));
+    }
+    let oncall_provider = resolve_oncall_provider(&req.oncall_id, req.oncall_provider, &data).await;
+    let chat_provider =
+        resolve_chat_provider_for_channel(&req.slack_channel_id, req.chat_provider, &data).await;
+    let notification = match web::block(move || {
+        let conn = db::connection();
+        db::add_channel_oncall_notification(
+            &conn,
+            chat_provider,
+            &req.slack_channel_id,
+            oncall_provider,
+            &req.oncall_id,
+            req.dry_run,
+            req.show_next_oncall,
+            &req.templates,
+            req.rich_messages,
+        )
+    })
+    .await
+    {
+        Err(blocking_error) => {
+            return Ok(HttpResponse::InternalServerError().json(ErrorResponse {
+                error: format!("{:?}", blocking_error),
+            }));
+        }
+        Ok(Err(db_error)) => {
+            return Ok(db_error.into());
+        }
+        Ok(Ok(res)) => res,
+    };
+
+    let notification = match db_notification_to_response(notification, &data).await {
+        Ok(notification) => notification,
+        Err(e) => {
+            return Ok(HttpResponse::InternalServerError().json(ErrorResponse {
+                error: format!("{}", e),
+            }));
+        }
+    };
+
+    // Start notifying if not already
+    data.scheduler
+        .add_channel(
+            ScheduleKey {
+                oncall_provider: notification.oncall_provider,
+                oncall_id: notification.oncall_id.clone(),
+            },
+            ChannelTarget {
+                notification_id: notification.id,
+                chat_provider: notification.chat_provider,
+                slack_channel_id: notification.slack_channel_id.clone(),
+                dry_run: notification.dry_run,
+                show_next_oncall: notification.show_next_oncall,
+                templates: notification.templates.clone(),
+                rich_messages: notification.rich_messages,
+            },
+        )
+        .await;
+    audit::record(NewAuditEvent {
+        notification_id: Some(notification.id),
+        oncall_provider: Some(notification.oncall_provider),
+        oncall_id: Some(notification.oncall_id.clone()),
+        chat_provider: Some(notification.chat_provider),
+        slack_channel_id: Some(notification.slack_channel_id.clone()),
+        ..NewAuditEvent::new(&identity.name, "add_notification")
+    })
+    .await;
+
+    Ok(HttpResponse::Ok().json(AddNotificationResponse { notification }))
+}
+
+/// Renders templates against the schedule's current oncalls without saving anything, so they can
+/// be checked before adding a notification. Left out templates render as the defaults.
+#[post("/notifications/preview_templates")]
+async fn preview_templates(
+    data: web::Data<Arc<AppState>>,
+    req: web::Json<PreviewTemplatesRequest>,
+) -> Result<impl Responder> {
+    if let Err(error) = req.templates.validate() {
+        return Ok(HttpResponse::BadRequest().json(ErrorResponse { error }));
+    }
+    let schedule = ScheduleKey {
+        oncall_provider: resolve_oncall_provider(&req.oncall_id, req.oncall_provider, &data).await,
+        oncall_id: req.oncall_id.clone(),
+    };
+    let chat_provider = req.chat_provider.unwrap_or_default();
+    let current_oncalls = match schedule
+        .oncall_provider
+        .provider()
+        .get_current_oncalls(&schedule.oncall_id)
+        .await
+    {
+        Err(e) if e.is_not_found() => {
+            return Ok(HttpResponse::NotFound().json(ErrorResponse {
+                error: format!("Oncall with ID {} does not exist", req.oncall_id),
+            }));
+        }
+        Err(_) => {
+            return Ok(HttpResponse::InternalServerError().json(ErrorResponse {
+                error: format!("Error fetching oncalls from {}", schedule.oncall_provider),
+            }));
+        }
+        Ok(oncalls) => oncalls,
+    };
+
+    let templates = &req.templates;
+    let info = scheduler::schedule_info(
+        &schedule,
+        scheduler::map_to_slack_users(schedule.oncall_provider, current_oncalls).await,
+        templates.uses(Placeholder::Next) || templates.uses(Placeholder::Until),
+        templates.uses(Placeholder::Schedule),
+        data.scheduler.user_groups(&schedule).await,
+    )
+    .await;
+    let values = match notifier::channel_values(chat_provider, &info, templates).await {
+        Err(e) => {
+            return Ok(e.into());
+        }
+        Ok(values) => values,
+    };
+
+    Ok(HttpResponse::Ok().json(PreviewTemplatesResponse {
+        topic: template::render(templates.topic(), &values.topic),
+        message: template::render(templates.message(), &values.message),
+        out_of_hours_message: template::render(templates.out_of_hours(), &values.message),
+    }))
+}
+
+#[post("/notifications/remove")]
+async fn remove_notification(
+    data: web::Data<Arc<AppState>>,
+    req: web::Json<RemoveNotificationRequest>,
+    identity: web::ReqData<auth::Identity>,
+) -> Result<impl Responder> {
+    let notification = match web::block(move || {
+        let conn = db::connection();
+        db::remove_channel_oncall_notification(&conn, req.notification_id)
+    })
+    .await
+    {
+        Err(blocking_error) => {
+            return Ok(HttpResponse::InternalServerError().json(ErrorResponse {
+                error: format!("{:?}", blocking_error),
+            }));
+        }
+        Ok(Err(db_error)) => {
+            return Ok(db_error.into());
+        }
+        Ok(Ok(res)) => res,
+    };
+
+    let notification = match db_notification_to_response(notification, &data).await {
+        Ok(notification) => notification,
+        Err(e) => {
+            return Ok(HttpResponse::InternalServerError().json(ErrorResponse {
+                error: format!("{}", e),
+            }));
+        }
+    };
+
+    // Stop notifying if present
+    {
+        let schedule = ScheduleKey {
+            oncall_provider: notification.oncall_provider,
+            oncall_id: notification.oncall_id.clone(),
+        };
+        let target = ChannelTarget {
+            notification_id: notification.id,
+            chat_provider: notification.chat_provider,
+            slack_channel_id: notification.slack_channel_id.clone(),
+            dry_run: notification.dry_run,
+            show_next_oncall: notification.show_next_oncall,
+            templates: notification.templates.clone(),
+            rich_messages: notification.rich_messages,
+        };
+        if !data.scheduler.remove_channel(&schedule, &target).await {
+            warn!(
+                "Channel {:?} not found in scheduler for {:?} after delete",
+                target, schedule
+            );
+        }
+    }
+    audit::record(NewAuditEvent {
+        notification_id: Some(notification.id),
+        oncall_provider: Some(notification.oncall_provider),
+        oncall_id: Some(notification.oncall_id.clone()),
+        chat_provider: Some(notification.chat_provider),
+        slack_channel_id: Some(notification.slack_channel_id.clone()),
+        ..NewAuditEvent::new(&identity.name, "remove_notification")
+    })
+    .await;
+
+    Ok(HttpResponse::Ok().json(RemoveNotificationResponse { notification }))
+}
+
+#[post("/notifications/set_dry_run")]
+async fn set_notification_dry_run(
+    data: web::Data<Arc<AppState>>,
+    req: web::Json<SetNotificationDryRunRequest>,
+    identity: web::ReqData<auth::Identity>,
+) -> Result<impl Responder> {
+    let notification_id = req.notification_id;
+    let dry_run = req.dry_run;
+    let notification = match web::block(move || {
+        let conn = db::connection();
+        db::set_notification_dry_run(&conn, notification_id, dry_run)
+    })
+    .await
+    {
+        Err(blocking_error) => {
+            return Ok(HttpResponse::InternalServerError().json(ErrorResponse {
+                error: format!("{:?}", blocking_error),
+            }));
+        }
+        Ok(Err(db_error)) => {
+            return Ok(db_error.into());
+        }
+        Ok(Ok(res)) => res,
+    };
+
+    let notification = match db_notification_to_response(notification, &data).await {
+        Ok(notification) => notification,
+        Err(e) => {
+            return Ok(HttpResponse::InternalServerError().json(ErrorResponse {
+                error: format!("{}", e),
+            }));
+        }
+    };
+
+    // Swap the scheduled target for one with the new flag
+    let schedule = ScheduleKey {
+        oncall_provider: notification.oncall_provider,
+        oncall_id: notification.oncall_id.clone(),
+    };
+    let target = ChannelTarget {
+        notification_id: notification.id,
+        chat_provider: notification.chat_provider,
+        slack_channel_id: notification.slack_channel_id.clone(),
+        dry_run: !dry_run,
+        show_next_oncall: notification.show_next_oncall,
+        templates: notification.templates.clone(),
+        rich_messages: notification.rich_messages,
+    };
+    data.scheduler.remove_channel(&schedule, &target).await;
+    data.scheduler
+        .add_channel(schedule, ChannelTarget { dry_run, ..target })
+        .await;
+    audit::record(NewAuditEvent {
+        notification_id: Some(notification.id),
+        oncall_provider: Some(notification.oncall_provider),
+        oncall_id: Some(notification.oncall_id.clone()),
+        chat_provider: Some(notification.chat_provider),
+        slack_channel_id: Some(notification.slack_channel_id.clone()),
+        new_value: Some(dry_run.to_string()),
+        ..NewAuditEvent::new(&identity.name, "set_notification_dry_run")
+    })
+    .await;
+
+    Ok(HttpResponse::Ok().json(AddNotificationResponse { notification }))
+}
+
+#[post("/notifications/set_show_next_oncall")]
+async fn set_notification_show_next_oncall(
+    data: web::Data<Arc<AppState>>,
+    req: web::Json<SetNotificationShowNextOncallRequest>,
+    identity: web::ReqData<auth::Identity>,
+) -> Result<impl Responder> {
+    let notification_id = req.notification_id;
+    let show_next_oncall = req.show_next_oncall;
+    let notification = match web::block(move || {
+        let conn = db::connection();
+        db::set_notification_show_next_oncall(&conn, notification_id, show_next_oncall)
+    })
+    .await
+    {
+        Err(blocking_error) => {
+            return Ok(HttpResponse::InternalServerError().json(ErrorResponse {
+                error: format!("{:?}", blocking_error),
+            }));
+        }
+        Ok(Err(db_error)) => {
+            return Ok(db_error.into());
+        }
+        Ok(Ok(res)) => res,
+    };
+
+    let notification = match db_notification_to_response(notification, &data).await {
+        Ok(notification) => notification,
+        Err(e) => {
+            return Ok(HttpResponse::InternalServerError().json(ErrorResponse {
+                error: format!("{}", e),
+            }));
+        }
+    };
+
+    // Swap the scheduled target for one with the new flag. Adding it reconciles the channel
+    // straight away, so the topic picks up the change.
+    let schedule = ScheduleKey {
+        oncall_provider: notification.oncall_provider,
+        oncall_id: notification.oncall_id.clone(),
+    };
+    let target = ChannelTarget {
+        notification_id: notification.id,
+        chat_provider: notification.chat_provider,
+        slack_channel_id: notification.slack_channel_id.clone(),
+        dry_run: notification.dry_run,
+        show_next_oncall: !show_next_oncall,
+        templates: notification.templates.clone(),
+        rich_messages: notification.rich_messages,
+    };
+    data.scheduler.remove_channel(&schedule, &target).await;
+    data.scheduler
+        .add_channel(
+            schedule,
+            ChannelTarget {
+                show_next_oncall,
+                ..target
+            },
+        )
+        .await;
+    audit::record(NewAuditEvent {
+        notification_id: Some(notification.id),
+        oncall_provider: Some(notification.oncall_provider),
+        oncall_id: Some(notification.oncall_id.clone()),
+        chat_provider: Some(notification.chat_provider),
+        slack_channel_id: Some(notification.slack_channel_id.clone()),
+        new_value: Some(show_next_oncall.to_string()),
+        ..NewAuditEvent::new(&identity.name, "set_notification_show_next_oncall")
+    })
+    .await;
+
+    Ok(HttpResponse::Ok().json(AddNotificationResponse { notification }))
+}
+
+#[post("/notifications/set_rich_messages")]
+async fn set_notification_rich_messages(
+    data: web::Data<Arc<AppState>>,
+    req: web::Json<SetNotificationRichMessagesRequest>,
+    identity: web::ReqData<auth::Identity>,
+) -> Result<impl Responder> {
+    let notification_id = req.notification_id;
+    let rich_messages = req.rich_messages;
+    let notification = match web::block(move || {
+        let conn = db::connection();
+        db::set_notification_rich_messages(&conn, notification_id, rich_messages)
+    })
+    .await
+    {
+        Err(blocking_error) => {
+            return Ok(HttpResponse::InternalServerError().json(ErrorResponse {
+                error: format!("{:?}", blocking_error),
+            }));
+        }
+        Ok(Err(db_error)) => {
+            return Ok(db_error.into());
+        }
+        Ok(Ok(res)) => res,
+    };
+
+    let notification = match db_notification_to_response(notification, &data).await {
+        Ok(notification) => notification,
+        Err(e) => {
+            return Ok(HttpResponse::InternalServerError().json(ErrorResponse {
+                error: format!("{}", e),
+            }));
+        }
+    };
+
+    // Swap the scheduled target for one with the new flag, which only affects future
+    // announcements
+    let schedule = ScheduleKey {
+        oncall_provider: notification.oncall_provider,
+        oncall_id: notification.oncall_id.clone(),
+    };
+    let target = ChannelTarget {
+        notification_id: notification.id,
+        chat_provider: notification.chat_provider,
+        slack_channel_id: notification.slack_channel_id.clone(),
+        dry_run: notification.dry_run,
+        show_next_oncall: notification.show_next_oncall,
+        templates: notification.templates.clone(),
+        rich_messages: !rich_messages,
+    };
+    data.scheduler.remove_channel(&schedule, &target).await;
+    data.scheduler
+        .add_channel(
+            schedule,
+            ChannelTarget {
+                rich_messages,
+                ..target
+            },
+        )
+        .await;
+    audit::record(NewAuditEvent {
+        notification_id: Some(notification.id),
+        oncall_provider: Some(notification.oncall_provider),
+        oncall_id: Some(notification.oncall_id.clone()),
+        chat_provider: Some(notification.chat_provider),
+        slack_channel_id: Some(notification.slack_channel_id.clone()),
+        new_value: Some(rich_messages.to_string()),
+        ..NewAuditEvent::new(&identity.name, "set_notification_rich_messages")
+    })
+    .await;
+
+    Ok(HttpResponse::Ok().json(AddNotificationResponse { notification }))
+}
+
+#[get("/preview")]
+async fn list_previews(
+    data: web::Data<Arc<AppState>>,
+    info: web::Query<PreviewRequest>,
+) -> Result<impl Responder> {
+    let previews = data.scheduler.previews();
+    let (user_groups, channels) = join!(previews.user_groups(), previews.channels());
+    let user_groups = user_groups
+        .into_iter()
+        .filter(|preview| {
+            info.oncall_sync_id
+                .map_or(info.notification_id.is_none(), |id| {
+                    preview.oncall_sync_id == id
+                })
+        })
+        .collect();
+    let channels = channels
+        .into_iter()
+        .filter(|preview| {
+            info.notification_id
+                .map_or(info.oncall_sync_id.is_none(), |id| {
+                    preview.notification_id == id
+                })
+        })
+        .collect();
+
+    Ok(HttpResponse::Ok().json(PreviewResponse {
+        user_groups,
+        channels,
+    }))
+}
+
+#[get("/health")]
+async fn worker_health(data: web::Data<Arc<AppState>>) -> Result<impl Responder> {
+    let health = data.scheduler.health();
+    let (mut user_groups, mut channels) = join!(health.user_groups(), health.channels());
+    user_groups.sort_by_key(|health| health.oncall_sync_id);
+    channels.sort_by_key(|health| health.notification_id);
+    let healthy = user_groups
+        .iter()
+        .map(|health| &health.status)
+        .chain(channels.iter().map(|health| &health.status))
+        .all(|status| status.failure.is_none());
+
+    Ok(HttpResponse::Ok().json(HealthResponse {
+        healthy,
+        user_groups,
+        channels,
+    }))
+}
+
+#[get("/reports/shifts")]
+async fn shift_report(
+    data: web::Data<Arc<AppState>>,
+    info: web::Query<ShiftReportRequest>,
+) -> Result<impl Responder> {
+    let now = audit::now();
+    let until = info.until.unwrap_or(now);
+    let since = info
+        .since
+        .unwrap_or_else(|| until.saturating_sub(DEFAULT_SHIFT_REPORT_DAYS * 24 * 60 * 60));
+    let oncall_provider = info.oncall_provider;
+    let oncall_id = info.oncall_id.clone();
+    let shifts = match web::block(move || {
+        let conn = db::connection();
+        db::list_shifts(&conn, oncall_provider, oncall_id.as_deref(), since, until)
+    })
+    .await
+    {
+        Err(blocking_error) => {
+            return Ok(HttpResponse::InternalServerError().json(ErrorResponse {
+                error: format!("{:?}", blocking_error),
+            }));
+        }
+        Ok(Err(db_error)) => {
+            return Ok(db_error.into());
+        }
+        Ok(Ok(res)) => res,
+    };
+    let summaries = reports::summarize_shifts(&shifts, since, until, now);
+
+    // Names are nice to have, so the report still gets returned if the providers are unreachable
+    let oncalls = data.oncall_cache.get_all().await.unwrap_or_default();
+    let users = user_names(summaries.iter().map(|summary| summary.oncall_provider)).await;
+
+    let entries = summaries
+        .into_iter()
+        .map(|summary| ShiftReportEntry {
+            oncall_name: oncalls
+                .get(&summary.oncall_id)
+                .map(|oncall| oncall.name.clone()),
+            user_name: users.get(&summary.user_id).cloned(),
+            summary,
+        })
+        .collect();
+
+    Ok(HttpResponse::Ok().json(ShiftReportResponse {
+        since,
+        until,
+        entries,
+    }))
+}
+
+#[get("/unmapped_oncalls")]
+async fn list_unmapped_oncalls(data: web::Data<Arc<AppState>>) -> Result<impl Responder> {
+    let shifts = match web::block(move || {
+        let conn = db::connection();
+        db::list_unmapped_shifts(&conn, None)
+    })
+    .await
+    {
+        Err(blocking_error) => {
+            return Ok(HttpResponse::InternalServerError().json(ErrorResponse {
+                error: format!("{:?}", blocking_error),
+            }));
+        }
+        Ok(Err(db_error)) => {
+            return Ok(db_error.into());
+        }
+        Ok(Ok(res)) => res,
+    };
+    // Shifts of schedules that are no longer polled are never closed, so they're left out
+    let schedules = data.scheduler.schedules().await;
+    let shifts: Vec<models::Shift> = shifts
+        .into_iter()
+        .filter(|shift| {
+            schedules.contains(&ScheduleKey {
+                oncall_provider: shift.oncall_provider,
+                oncall_id: shift.oncall_id.clone(),
+            })
+        })
+        .collect();
+
+    // Names are nice to have, so the list still gets returned if the providers are unreachable
+    let oncalls = data.oncall_cache.get_all().await.unwrap_or_default();
+    let users = user_names(shifts.iter().map(|shift| shift.oncall_provider)).await;
+
+    let unmapped_oncalls = shifts
+        .into_iter()
+        .map(|shift| UnmappedOncall {
+            oncall_provider: shift.oncall_provider,
+            oncall_name: oncalls
+                .get(&shift.oncall_id)
+                .map(|oncall| oncall.name.clone()),
+            oncall_id: shift.oncall_id,
+            user_name: users.get(&shift.user_id).cloned(),
+            user_id: shift.user_id,
+            since: shift.started_at,
+            warned_at: shift.unmapped_warned_at,
+        })
+        .collect();
+
+    Ok(HttpResponse::Ok().json(ListUnmappedOncallsResponse { unmapped_oncalls }))
+}
+
+#[get("/audit")]
+async fn list_audit_events(info: web::Query<ListAuditEventsRequest>) -> Result<impl Responder> {
+    let page = info.page.unwrap_or(0).max(0);
+    let page_size = info
+        .page_size
+        .unwrap_or(DEFAULT_AUDIT_PAGE_SIZE)
+        .clamp(1, MAX_AUDIT_PAGE_SIZE);
+    let offset = match page.checked_mul(page_size) {
+        Some(offset) => offset,
+        None => {
+            return Ok(HttpResponse::BadRequest().json(ErrorResponse {
+                error: format!("Page {} is out of range", page),
+            }));
+        }
+    };
+    let events = match web::block(move || {
+        let conn = db::connection();
+        db::list_audit_events(
+            &conn,
+            &db::AuditEventFilter {
+                oncall_id: info.oncall_id.as_deref(),
+                slack_channel_id: info.slack_channel_id.as_deref(),
+                since: info.since,
+                until: info.until,
+            },
+            offset,
+            page_size,
+        )
+    })
+    .await
+    {
+        Err(blocking_error) => {
+            return Ok(HttpResponse::InternalServerError().json(ErrorResponse {
+                error: format!("{:?}", blocking_error),
+            }));
+        }
+        Ok(Err(db_error)) => {
+            return Ok(db_error.into());
+        }
+        Ok(Ok(res)) => res,
+    };
+
+    Ok(HttpResponse::Ok().json(ListAuditEventsResponse {
+        events,
+        page,
+        page_size,
+    }))
+}
+
+#[post("/webhooks/opsgenie")]
+async fn opsgenie_webhook(
+    data: web::Data<Arc<AppState>>,
+    http_req: HttpRequest,
+    req: web::Json<opsgenie::WebhookPayload>,
+) -> Result<impl Responder> {
+    if !opsgenie::webhooks_enabled() {
+        return Ok(HttpResponse::NotFound().json(ErrorResponse {
+            error: "OpsGenie webhooks are not enabled".into(),
+        }));
+    }
+    let secret = http_req
+        .headers()
+        .get(opsgenie::WEBHOOK_SECRET_HEADER)
+        .and_then(|secret| secret.to_str().ok())
+        .unwrap_or_default();
+    if !opsgenie::verify_webhook_secret(secret) {
+        return Ok(HttpResponse::Unauthorized().json(ErrorResponse {
+            error: "invalid webhook secret".into(),
+        }));
+    }
+
+    // Callbacks don't always carry the schedule ID, in which case we look it up by name
+    let oncall_id = match (req.schedule_id(), req.schedule_name()) {
+        (Some(schedule_id), _) => Some(schedule_id.to_string()),
+        (None, Some(schedule_name)) => match data.oncall_cache.get_all().await {
+            Ok(oncalls) => oncalls
+                .into_values()
+                .find(|oncall| {
+                    oncall.provider == OncallProviderKind::Opsgenie && oncall.name == schedule_name
+                })
+                .map(|oncall| oncall.id),
+            Err(e) => {
+                return Ok(HttpResponse::InternalServerError().json(ErrorResponse {
+                    error: format!("{}", e),
+                }));
+            }
+        },
+        (None, None) => None,
+    };
+    let oncall_id = match oncall_id {
+        Some(oncall_id) => oncall_id,
+        None => {
+            // Nothing for us to do, but acknowledge it so OpsGenie doesn't retry
+            info!(
+                "Ignoring OpsGenie webhook {:?} without a known schedule",
+                req.action
+            );
+            return Ok(HttpResponse::Ok().json(OpsgenieWebhookResponse {
+                oncall_id: None,
+                syncs: 0,
+                notifications: 0,
+                triggered: false,
+            }));
+        }
+    };
+
+    let oncall_id_clone = oncall_id.clone();
+    let (syncs, notifications) = match web::block(move || {
+        let conn = db::connection();
+        Ok::<_, db::Error>((
+            db::get_syncs(&conn, &oncall_id_clone)?,
+            db::get_channels_notified_for_oncall(&conn, &oncall_id_clone)?,
+        ))
+    })
+    .await
+    {
+        Err(blocking_error) => {
+            return Ok(HttpResponse::InternalServerError().json(ErrorResponse {
+                error: format!("{:?}", blocking_error),
+            }));
+        }
+        Ok(Err(db_error)) => {
+            return Ok(db_error.into());
+        }
+        Ok(Ok(res)) => res,
+    };
+    let syncs = syncs
+        .into_iter()
+        .filter(|sync| sync.oncall_provider == OncallProviderKind::Opsgenie)
+        .count();
+    let notifications = notifications
+        .into_iter()
+        .filter(|notification| notification.oncall_provider == OncallProviderKind::Opsgenie)
+        .count();
+
+    let triggered = if syncs + notifications > 0 {
+        data.scheduler
+            .trigger(&ScheduleKey {
+                oncall_provider: OncallProviderKind::Opsgenie,
+                oncall_id: oncall_id.clone(),
+            })
+            .await
+    } else {
+        false
+    };
+    info!(
+        "OpsGenie webhook {:?} for schedule {} matched {} syncs and {} notifications",
+        req.action, oncall_id, syncs, notifications
+    );
+
+    Ok(HttpResponse::Ok().json(OpsgenieWebhookResponse {
+        oncall_id: Some(oncall_id),
+        syncs,
+        notifications,
+        triggered,
+    }))
+}
+
+#[post("/slack/commands")]
+async fn slack_command(
+    data: web::Data<Arc<AppState>>,
+    http_req: HttpRequest,
+    body: web::Bytes,
+) -> Result<impl Responder> {
+    if !verify_slack_request(&http_req, &body) {
+        return Ok(HttpResponse::Unauthorized().json(ErrorResponse {
+            error: "invalid slack signature".into(),
+        }));
+    }
+    let command = match serde_urlencoded::from_bytes::<slack::SlashCommand>(&body) {
+        Ok(command) => command,
+        Err(e) => {
+            return Ok(HttpResponse::BadRequest().json(ErrorResponse {
+                error: format!("{}", e),
+            }));
+        }
+    };
+
+    // Slack only shows the response body to the user, so errors are returned as a message too
+    let text = match slash_command::run(&command, &data).await {
+        Ok(text) => text,
+        Err(e) => {
+            warn!("Error running slash command {:?}: {}", command, e);
+            format!("Something went wrong looking up the on-call: {}", e)
+        }
+    };
+
+    Ok(HttpResponse::Ok().json(slack::SlashCommandResponse::ephemeral(text)))
+}
+
+#[post("/slack/events")]
+async fn slack_event(
+    data: web::Data<Arc<AppState>>,
+    http_req: HttpRequest,
+    body: web::Bytes,
+) -> Result<impl Responder> {
+    if !verify_slack_request(&http_req, &body) {
+        return Ok(HttpResponse::Unauthorized().json(ErrorResponse {
+            error: "invalid slack signature".into(),
+        }));
+    }
+    let event_request = match serde_json::from_slice::<slack::EventRequest>(&body) {
+        Ok(event_request) => event_request,
+        Err(e) => {
+            return Ok(HttpResponse::BadRequest().json(ErrorResponse {
+                error: format!("{}", e),
+            }));
+        }
+    };
+
+    match event_request {
+        slack::EventRequest::UrlVerification { challenge } => {
+            Ok(HttpResponse::Ok().json(SlackChallengeResponse { challenge }))
+        }
+        slack::EventRequest::EventCallback { event_id, event } => {
+            // Slack wants an answer within 3 seconds, so do the actual work in the background
+            if data.seen_slack_events.first_seen(&event_id).await {
+                tokio::spawn(slack_events::handle_event(event, data.get_ref().clone()));
+            } else {
+                info!("Ignoring retried slack event {}", event_id);
+            }
+            Ok(HttpResponse::Ok().finish())
+        }
+    }
+}
+
+#[post("/slack/interactivity")]
+async fn slack_interaction(
+    data: web::Data<Arc<AppState>>,
+    http_req: HttpRequest,
+    body: web::Bytes,
+) -> Result<impl Responder> {
+    if !verify_slack_request(&http_req, &body) {
+        return Ok(HttpResponse::Unauthorized().json(ErrorResponse {
+            error: "invalid slack signature".into(),
+        }));
+    }
+    let interaction = match serde_urlencoded::from_bytes::<slack::InteractionRequest>(&body)
+        .map_err(|e| format!("{}", e))
+        .and_then(|request| {
+            serde_json::from_str::<slack::Interaction>(&request.payload)
+                .map_err(|e| format!("{}", e))
+        }) {
+        Ok(interaction) => interaction,
+        Err(error) => {
+            return Ok(HttpResponse::BadRequest().json(ErrorResponse { error }));
+        }
+    };
+
+    // Like events, Slack wants an answer within 3 seconds
+    tokio::spawn(slack_interactivity::handle_interaction(
+        interaction,
+        data.get_ref().clone(),
+    ));
+    Ok(HttpResponse::Ok().finish())
+}
+
+#[get("/whoami")]
+async fn whoami(identity: web::ReqData<auth::Identity>) -> Result<impl Responder> {
+    Ok(HttpResponse::Ok().json(WhoamiResponse {
+        name: identity.name.clone(),
+        role: identity.role,
+    }))
+}
+
+#[get("/api_tokens/list")]
+async fn list_api_tokens() -> Result<impl Responder> {
+    let api_tokens = match web::block(|| {
+        let conn = db::connection();
+        db::list_api_tokens(&conn)
+    })
+    .await
+    {
+        Err(blocking_error) => {
+            return Ok(HttpResponse::InternalServerError().json(ErrorResponse {
+                error: format!("{:?}", blocking_error),
+            }));
+        }
+        Ok(Err(db_error)) => {
+            return Ok(db_error.into());
+        }
+        Ok(Ok(res)) => res,
+    };
+
+    Ok(HttpResponse::Ok().json(ListApiTokensResponse { api_tokens }))
+}
+
+#[post("/api_tokens/add")]
+async fn add_api_token(
+    req: web::Json<AddApiTokenRequest>,
+    identity: web::ReqData<auth::Identity>,
+) -> Result<impl Responder> {
+    if req.name.is_empty() {
+        return Ok(HttpResponse::BadRequest().json(ErrorResponse {
+            error: "API tokens need a name".into(),
+        }));
+    }
+    let token = auth::generate_token();
+    let token_hash = auth::hash_token(&token);
+    let role = req.role;
+    let api_token = match web::block(move || {
+        let conn = db::connection();
+        db::add_api_token(&conn, &req.name, &token_hash, role, audit::now())
+    })
+    .await
+    {
+        Err(blocking_error) => {
+            return Ok(HttpResponse::InternalServerError().json(ErrorResponse {
+                error: format!("{:?}", blocking_error),
+            }));
+        }
+        Ok(Err(db_error)) => {
+            return Ok(db_error.into());
+        }
+        Ok(Ok(res)) => res,
+    };
+    audit::record(NewAuditEvent {
+        new_value: Some(format_api_token(&api_token)),
+        ..NewAuditEvent::new(&identity.name, "add_api_token")
+    })
+    .await;
+
+    Ok(HttpResponse::Ok().json(AddApiTokenResponse { api_token, token }))
+}
+
+#[post("/api_tokens/remove")]
+async fn remove_api_token(
+    req: web::Json<RemoveApiTokenRequest>,
+    identity: web::ReqData<auth::Identity>,
+) -> Result<impl Responder> {
+    let api_token_id = req.api_token_id;
+    let removed_token = match web::block(move || {
+        let conn = db::connection();
+        db::remove_api_token(&conn, api_token_id)
+    })
+    .await
+    {
+        Err(blocking_error) => {
+            return Ok(HttpResponse::InternalServerError().json(ErrorResponse {
+                error: format!("{:?}", blocking_error),
+            }));
+        }
+        Ok(Err(db_error)) => {
+            return Ok(db_error.into());
+        }
+        Ok(Ok(res)) => res,
+    };
+    audit::record(NewAuditEvent {
+        previous_value: Some(format_api_token(&removed_token)),
+        ..NewAuditEvent::new(&identity.name, "remove_api_token")
+    })
+    .await;
+
+    Ok(HttpResponse::Ok().json(removed_token))
+}
+
+async fn not_found() -> Result<impl Responder> {
+    Ok(HttpResponse::NotFound().json(ErrorResponse {
+        error: "the requested page does not exist".into(),
+    }))
+}
+
+/// Builds the app with every endpoint, behind the API token middleware.
+pub fn app(
+    app_state: Arc<AppState>,
+) -> App<
+    impl ServiceFactory<
+        ServiceRequest,
+        Config = (),
+        Response = ServiceResponse<EitherBody<BoxBody>>,
+        Error = actix_web::Error,
+        InitError = (),
+    >,
+> {
+    App::new()
+        .wrap(auth::Authentication)
+        .app_data(web::Data::new(app_state))
+        .service(add_sync)
+        .service(remove_sync)
+        .service(synced_with)
+        .service(list_oncalls)
+        .service(list_user_groups)
+        .service(list_slack_users)
+        .service(list_slack_channels)
+        .service(list_opsgenie_users)
+        .service(add_user_map)
+        .service(remove_user_map)
+        .service(list_syncs)
+        .service(list_user_mappings)
+        .service(set_user_map_shift_dms_opt_out)
+        .service(auto_map_users)
+        .service(get_slack_user_mapping)
+        .service(list_notifications)
+        .service(get_notification_for_slack_channel)
+        .service(get_notification_for_oncall)
+        .service(add_notification)
+        .service(remove_notification)
+        .service(opsgenie_webhook)
+        .service(slack_command)
+        .service(slack_event)
+        .service(slack_interaction)
+        .service(list_audit_events)
+        .service(shift_report)
+        .service(list_unmapped_oncalls)
+        .service(set_sync_dry_run)
+        .service(set_notification_dry_run)
+        .service(set_notification_show_next_oncall)
+        .service(set_notification_rich_messages)
+        .service(preview_templates)
+        .service(list_previews)
+        .service(worker_health)
+        .service(whoami)
+        .service(list_api_tokens)
+        .service(add_api_token)
+        .service(remove_api_token)
+        .default_service(web::route().to(not_found))
+}
@@ -0,0 +1,478 @@
+use crate::chat_provider::{self, ChatProvider, ChatProviderKind};
+use futures::future::BoxFuture;
+use reqwest::{header::AUTHORIZATION, RequestBuilder, StatusCode};
+use serde::{Deserialize, Serialize};
+use std::env;
+
+const PER_PAGE: usize = 200;
+
+#[derive(thiserror::Error, Debug)]
+pub enum Error {
+    #[error("error making request")]
+    RequestError(#[from] reqwest::Error),
+    #[error("got HTTP {0}")]
+    HttpErrorCode(StatusCode),
+}
+
+pub type Result<T = (), E = Error> = std::result::Result<T, E>;
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Group {
+    pub id: String,
+    pub name: Option<String>,
+    pub display_name: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct User {
+    pub id: String,
+    pub username: String,
+    #[serde(default)]
+    pub first_name: String,
+    #[serde(default)]
+    pub last_name: String,
+    #[serde(default)]
+    pub is_bot: bool,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Channel {
+    pub id: String,
+    pub name: String,
+    pub display_name: String,
+    #[serde(default)]
+    pub header: String,
+    #[serde(default)]
+    pub creator_id: String,
+    #[serde(default)]
+    pub update_at: u64,
+}
+
+#[derive(Serialize, Debug, Clone)]
+struct GroupMembersRequest<'a> {
+    user_ids: &'a [String],
+}
+
+#[derive(Serialize, Debug, Clone)]
+struct ChannelPatchRequest<'a> {
+    header: &'a str,
+}
+
+#[derive(Serialize, Debug, Clone)]
+struct CreatePostRequest<'a> {
+    channel_id: &'a str,
+    message: &'a str,
+}
+
+pub async fn list_groups() -> Result<Vec<Group>> {
+    paginated(|client, page| {
+        client
+            .get(api_url("/groups"))
+            .query(&[("filter_allow_reference", "true")])
+            .query(&[("page", page), ("per_page", PER_PAGE)])
+    })
+    .await
+}
+
+pub async fn get_group(id: &str) -> Result<Group> {
+    let client = reqwest::Client::new();
+    let group_response = authorized(client.get(api_url(&format!("/groups/{}", id))))
+        .send()
+        .await?;
+
+    match group_response.status() {
+        reqwest::StatusCode::OK => Ok(group_response.json::<Group>().await?),
+        code => Err(Error::HttpErrorCode(code)),
+    }
+}
+
+pub async fn list_group_members(id: &str) -> Result<Vec<User>> {
+    paginated(|client, page| {
+        client
+            .get(api_url("/users"))
+            .query(&[("in_group", id)])
+            .query(&[("page", page), ("per_page", PER_PAGE)])
+    })
+    .await
+}
+
+/// Mattermost only lets us add and remove members, so work out the difference with the current
+/// membership and apply it.
+pub async fn set_group_members(id: &str, users: &[String]) -> Result {
+    let current_members: Vec<String> = list_group_members(id)
+        .await?
+        .into_iter()
+        .map(|user| user.id)
+        .collect();
+    let to_add: Vec<String> = users
+        .iter()
+        .filter(|user| !current_members.contains(user))
+        .cloned()
+        .collect();
+    let to_remove: Vec<String> = current_members
+        .into_iter()
+        .filter(|user| !users.contains(user))
+        .collect();
+
+    let client = reqwest::Client::new();
+    let url = api_url(&format!("/groups/{}/members", id));
+    if !to_add.is_empty() {
+        let add_response = authorized(client.post(&url))
+            .json(&GroupMembersRequest { user_ids: &to_add })
+            .send()
+            .await?;
+        if !add_response.status().is_success() {
+            return Err(Error::HttpErrorCode(add_response.status()));
+        }
+    }
+    if !to_remove.is_empty() {
+        let remove_response = authorized(client.delete(&url))
+            .json(&GroupMembersRequest {
+                user_ids: &to_remove,
+            })
+            .send()
+            .await?;
+        if !remove_response.status().is_success() {
+            return Err(Error::HttpErrorCode(remove_response.status()));
+        }
+    }
+
+    Ok(())
+}
+
+pub async fn list_users() -> Result<Vec<User>> {
+    Ok(paginated(|client, page| {
+        client
+            .get(api_url("/users"))
+            .query(&[("page", page), ("per_page", PER_PAGE)])
+    })
+    .await?
+    .into_iter()
+    .filter(|user: &User| !user.is_bot)
+    .collect())
+}
+
+pub async fn get_user(id: &str) -> Result<User> {
+    let client = reqwest::Client::new();
+    let user_response = authorized(client.get(api_url(&format!("/users/{}", id))))
+        .send()
+        .await?;
+
+    match user_response.status() {
+        reqwest::StatusCode::OK => Ok(user_response.json::<User>().await?),
+        code => Err(Error::HttpErrorCode(code)),
+    }
+}
+
+pub async fn get_user_by_email(email: &str) -> Result<Option<User>> {
+    let client = reqwest::Client::new();
+    let user_response = authorized(client.get(api_url(&format!("/users/email/{}", email))))
+        .send()
+        .await?;
+
+    match user_response.status() {
+        reqwest::StatusCode::OK => Ok(Some(user_response.json::<User>().await?)),
+        reqwest::StatusCode::NOT_FOUND => Ok(None),
+        code => Err(Error::HttpErrorCode(code)),
+    }
+}
+
+pub async fn list_channels() -> Result<Vec<Channel>> {
+    let team_id = mattermost_team_id();
+    paginated(|client, page| {
+        client
+            .get(api_url(&format!("/teams/{}/channels", team_id)))
+            .query(&[("page", page), ("per_page", PER_PAGE)])
+    })
+    .await
+}
+
+pub async fn get_channel(id: &str) -> Result<Channel> {
+    let client = reqwest::Client::new();
+    let channel_response = authorized(client.get(api_url(&format!("/channels/{}", id))))
+        .send()
+        .await?;
+
+    match channel_response.status() {
+        reqwest::StatusCode::OK => Ok(channel_response.json::<Channel>().await?),
+        code => Err(Error::HttpErrorCode(code)),
+    }
+}
+
+pub async fn set_channel_header(channel_id: &str, header: &str) -> Result<Channel> {
+    let client = reqwest::Client::new();
+    let patch_response =
+        authorized(client.put(api_url(&format!("/channels/{}/patch", channel_id))))
+            .json(&ChannelPatchRequest { header })
+            .send()
+            .await?;
+
+    match patch_response.status() {
+        reqwest::StatusCode::OK => Ok(patch_response.json::<Channel>().await?),
+        code => Err(Error::HttpErrorCode(code)),
+    }
+}
+
+pub async fn create_post(channel_id: &str, message: &str) -> Result {
+    let client = reqwest::Client::new();
+    let post_response = authorized(client.post(api_url("/posts")))
+        .json(&CreatePostRequest {
+            channel_id,
+            message,
+        })
+        .send()
+        .await?;
+
+    match post_response.status() {
+        reqwest::StatusCode::CREATED | reqwest::StatusCode::OK => Ok(()),
+        code => Err(Error::HttpErrorCode(code)),
+    }
+}
+
+/// Returns the direct message channel between the bot and the user, creating it if needed.
+pub async fn create_direct_channel(user_id: &str) -> Result<Channel> {
+    let client = reqwest::Client::new();
+    let me_response = authorized(client.get(api_url("/users/me"))).send().await?;
+    let me = match me_response.status() {
+        reqwest::StatusCode::OK => me_response.json::<User>().await?,
+        code => return Err(Error::HttpErrorCode(code)),
+    };
+
+    let channel_response = authorized(client.post(api_url("/channels/direct")))
+        .json(&[&me.id[..], user_id])
+        .send()
+        .await?;
+    match channel_response.status() {
+        reqwest::StatusCode::CREATED | reqwest::StatusCode::OK => {
+            Ok(channel_response.json::<Channel>().await?)
+        }
+        code => Err(Error::HttpErrorCode(code)),
+    }
+}
+
+/// Fetches every page of a Mattermost list endpoint. Mattermost signals the last page by
+/// returning fewer than `per_page` items.
+async fn paginated<T, F>(request: F) -> Result<Vec<T>>
+where
+    T: serde::de::DeserializeOwned,
+    F: Fn(&reqwest::Client, usize) -> RequestBuilder,
+{
+    let client = reqwest::Client::new();
+    let mut items = vec![];
+    let mut page = 0;
+
+    loop {
+        let response = authorized(request(&client, page)).send().await?;
+        let mut page_items = match response.status() {
+            reqwest::StatusCode::OK => response.json::<Vec<T>>().await?,
+            code => return Err(Error::HttpErrorCode(code)),
+        };
+        let page_len = page_items.len();
+        items.append(&mut page_items);
+        if page_len < PER_PAGE {
+            break;
+        }
+        page += 1;
+    }
+
+    Ok(items)
+}
+
+/// [`ChatProvider`] implementation backed by the free functions in this module.
+pub struct Mattermost;
+
+impl ChatProvider for Mattermost {
+    fn kind(&self) -> ChatProviderKind {
+        ChatProviderKind::Mattermost
+    }
+
+    fn is_configured(&self) -> bool {
+        env::var("MATTERMOST_URL").is_ok()
+            && env::var("MATTERMOST_TOKEN").is_ok()
+            && env::var("MATTERMOST_TEAM_ID").is_ok()
+    }
+
+    fn list_user_groups(
+        &self,
+    ) -> BoxFuture<'_, chat_provider::Result<Vec<chat_provider::UserGroup>>> {
+        Box::pin(async move { Ok(list_groups().await?.into_iter().map(Group::into).collect()) })
+    }
+
+    fn get_user_group<'a>(
+        &'a self,
+        id: &'a str,
+    ) -> BoxFuture<'a, chat_provider::Result<chat_provider::UserGroup>> {
+        Box::pin(async move { Ok(get_group(id).await?.into()) })
+    }
+
+    fn get_user_group_members<'a>(
+        &'a self,
+        id: &'a str,
+    ) -> BoxFuture<'a, chat_provider::Result<Vec<String>>> {
+        Box::pin(async move {
+            Ok(list_group_members(id)
+                .await?
+                .into_iter()
+                .map(|user| user.id)
+                .collect())
+        })
+    }
+
+    fn set_user_group<'a>(
+        &'a self,
+        id: &'a str,
+        users: &'a [String],
+    ) -> BoxFuture<'a, chat_provider::Result> {
+        Box::pin(async move { Ok(set_group_members(id, users).await?) })
+    }
+
+    fn list_users(&self) -> BoxFuture<'_, chat_provider::Result<Vec<chat_provider::User>>> {
+        Box::pin(async move { Ok(list_users().await?.into_iter().map(User::into).collect()) })
+    }
+
+    fn get_user<'a>(
+        &'a self,
+        id: &'a str,
+    ) -> BoxFuture<'a, chat_provider::Result<chat_provider::User>> {
+        Box::pin(async move { Ok(get_user(id).await?.into()) })
+    }
+
+    fn find_user_by_email<'a>(
+        &'a self,
+        email: &'a str,
+    ) -> BoxFuture<'a, chat_provider::Result<Option<chat_provider::User>>> {
+        Box::pin(async move { Ok(get_user_by_email(email).await?.map(User::into)) })
+    }
+
+    fn list_channels(&self) -> BoxFuture<'_, chat_provider::Result<Vec<chat_provider::Channel>>> {
+        Box::pin(async move {
+            Ok(list_channels()
+                .await?
+                .into_iter()
+                .map(Channel::into)
+                .collect())
+        })
+    }
+
+    fn get_channel<'a>(
+        &'a self,
+        id: &'a str,
+    ) -> BoxFuture<'a, chat_provider::Result<chat_provider::Channel>> {
+        Box::pin(async move { Ok(get_channel(id).await?.into()) })
+    }
+
+    fn set_channel_topic<'a>(
+        &'a self,
+        channel_id: &'a str,
+        topic: &'a str,
+    ) -> BoxFuture<'a, chat_provider::Result> {
+        Box::pin(async move {
+            set_channel_header(channel_id, topic).await?;
+            Ok(())
+        })
+    }
+
+    fn post_message<'a>(
+        &'a self,
+        channel_id: &'a str,
+        message: &'a str,
+    ) -> BoxFuture<'a, chat_provider::Result> {
+        Box::pin(async move { Ok(create_post(channel_id, message).await?) })
+    }
+
+    fn post_direct_message<'a>(
+        &'a self,
+        user_id: &'a str,
+        message: &'a str,
+    ) -> BoxFuture<'a, chat_provider::Result> {
+        Box::pin(async move {
+            let channel = create_direct_channel(user_id).await?;
+            Ok(create_post(&channel.id, message).await?)
+        })
+    }
+
+    fn format_mention<'a>(
+        &'a self,
+        user_id: &'a str,
+    ) -> BoxFuture<'a, chat_provider::Result<String>> {
+        // Mattermost mentions are by username rather than ID
+        Box::pin(async move { Ok(format!("@{}", get_user(user_id).await?.username)) })
+    }
+
+    fn format_user_group_mention<'a>(
+        &'a self,
+        user_group_id: &'a str,
+    ) -> BoxFuture<'a, chat_provider::Result<String>> {
+        // Like users, groups are mentioned by name
+        Box::pin(async move {
+            let group: chat_provider::UserGroup = get_group(user_group_id).await?.into();
+            Ok(format!("@{}", group.handle))
+        })
+    }
+}
+
+impl From<Group> for chat_provider::UserGroup {
+    fn from(group: Group) -> chat_provider::UserGroup {
+        chat_provider::UserGroup {
+            handle: group.name.unwrap_or_else(|| group.display_name.clone()),
+            id: group.id,
+            name: group.display_name,
+            provider: ChatProviderKind::Mattermost,
+        }
+    }
+}
+
+impl From<User> for chat_provider::User {
+    fn from(user: User) -> chat_provider::User {
+        let real_name = format!("{} {}", user.first_name, user.last_name)
+            .trim()
+            .to_string();
+        chat_provider::User {
+            id: user.id,
+            name: user.username,
+            real_name: if real_name.is_empty() {
+                None
+            } else {
+                Some(real_name)
+            },
+            is_bot: user.is_bot,
+            provider: ChatProviderKind::Mattermost,
+        }
+    }
+}
+
+impl From<Channel> for chat_provider::Channel {
+    fn from(channel: Channel) -> chat_provider::Channel {
+        chat_provider::Channel {
+            id: channel.id,
+            name: channel.display_name,
+            topic: chat_provider::ChannelTopic {
+                value: channel.header,
+                creator: channel.creator_id,
+                // Mattermost timestamps are in milliseconds
+                last_set: channel.update_at / 1000,
+            },
+            provider: ChatProviderKind::Mattermost,
+        }
+    }
+}
+
+fn authorized(request: RequestBuilder) -> RequestBuilder {
+    request.header(AUTHORIZATION, format!("Bearer {}", mattermost_token()))
+}
+
+fn api_url(path: &str) -> String {
+    format!("{}/api/v4{}", mattermost_url().trim_end_matches('/'), path)
+}
+
+fn mattermost_url() -> String {
+    env::var("MATTERMOST_URL").expect("MATTERMOST_URL must be set")
+}
+
+fn mattermost_token() -> String {
+    env::var("MATTERMOST_TOKEN").expect("MATTERMOST_TOKEN must be set")
+}
+
+fn mattermost_team_id() -> String {
+    env::var("MATTERMOST_TEAM_ID").expect("MATTERMOST_TEAM_ID must be set")
+}
@@ -1,4 +1,13 @@
-use crate::schema::{notified_slack_channel, oncall_syncs, user_mapping};
+use crate::{
+    auth::Role,
+    chat_provider::ChatProviderKind,
+    oncall_provider::OncallProviderKind,
+    schema::{
+        api_tokens, audit_events, notified_slack_channel, oncall_syncs, shift_reminders, shifts,
+        user_mapping,
+    },
+    template::Templates,
+};
 use serde::{Deserialize, Serialize};
 
 #[derive(Queryable, Clone, Serialize, Deserialize)]
@@ -6,6 +15,10 @@ pub struct OncallSync {
     pub id: i32,
     pub oncall_id: String,
     pub user_group_id: String,
+    pub oncall_provider: OncallProviderKind,
+    pub chat_provider: ChatProviderKind,
+    /// Only preview changes instead of applying them
+    pub dry_run: bool,
 }
 
 #[derive(Insertable, Serialize, Deserialize)]
@@ -13,6 +26,9 @@ pub struct OncallSync {
 pub struct NewOncallSync<'a> {
     pub oncall_id: &'a str,
     pub user_group_id: &'a str,
+    pub oncall_provider: OncallProviderKind,
+    pub chat_provider: ChatProviderKind,
+    pub dry_run: bool,
 }
 
 #[derive(Queryable, Clone, Serialize, Deserialize, Debug)]
@@ -20,6 +36,10 @@ pub struct UserMapping {
     pub id: i32,
     pub opsgenie_id: String,
     pub slack_id: String,
+    /// Set when the user doesn't want direct messages when their shifts start and end
+    pub shift_dms_opt_out: bool,
+    /// Provider the oncall user belongs to
+    pub oncall_provider: OncallProviderKind,
 }
 
 #[derive(Insertable, Serialize, Deserialize)]
@@ -27,6 +47,7 @@ pub struct UserMapping {
 pub struct NewUserMapping<'a> {
     pub opsgenie_id: &'a str,
     pub slack_id: &'a str,
+    pub oncall_provider: OncallProviderKind,
 }
 
 #[derive(Queryable, Clone, Serialize, Deserialize, Debug)]
@@ -34,6 +55,28 @@ pub struct NotifiedSlackChannel {
     pub id: i32,
     pub oncall_id: String,
     pub slack_channel_id: String,
+    pub oncall_provider: OncallProviderKind,
+    pub chat_provider: ChatProviderKind,
+    /// Only preview changes instead of applying them
+    pub dry_run: bool,
+    /// Also show who is next and when they take over
+    pub show_next_oncall: bool,
+    /// Templates for the topic and announcements, NULL for the defaults
+    pub topic_template: Option<String>,
+    pub message_template: Option<String>,
+    pub out_of_hours_template: Option<String>,
+    /// Post announcements with buttons, on chat providers that support it
+    pub rich_messages: bool,
+}
+
+impl NotifiedSlackChannel {
+    pub fn templates(&self) -> Templates {
+        Templates {
+            topic_template: self.topic_template.clone(),
+            message_template: self.message_template.clone(),
+            out_of_hours_template: self.out_of_hours_template.clone(),
+        }
+    }
 }
 
 #[derive(Insertable, Serialize, Deserialize)]
@@ -41,4 +84,122 @@ pub struct NotifiedSlackChannel {
 pub struct NewNotifiedSlackChannel<'a> {
     pub oncall_id: &'a str,
     pub slack_channel_id: &'a str,
+    pub oncall_provider: OncallProviderKind,
+    pub chat_provider: ChatProviderKind,
+    pub dry_run: bool,
+    pub show_next_oncall: bool,
+    pub topic_template: Option<&'a str>,
+    pub message_template: Option<&'a str>,
+    pub out_of_hours_template: Option<&'a str>,
+    pub rich_messages: bool,
+}
+
+#[derive(Queryable, Clone, Serialize, Deserialize, Debug)]
+pub struct AuditEvent {
+    pub id: i32,
+    pub created_at: i64,
+    pub actor: String,
+    pub action: String,
+    pub outcome: String,
+    pub error: Option<String>,
+    pub oncall_sync_id: Option<i32>,
+    pub notification_id: Option<i32>,
+    pub oncall_provider: Option<OncallProviderKind>,
+    pub oncall_id: Option<String>,
+    pub chat_provider: Option<ChatProviderKind>,
+    pub user_group_id: Option<String>,
+    pub slack_channel_id: Option<String>,
+    pub previous_value: Option<String>,
+    pub new_value: Option<String>,
+}
+
+#[derive(Insertable, Serialize, Deserialize, Debug, Default)]
+#[table_name = "audit_events"]
+pub struct NewAuditEvent {
+    pub created_at: i64,
+    pub actor: String,
+    pub action: String,
+    pub outcome: String,
+    pub error: Option<String>,
+    pub oncall_sync_id: Option<i32>,
+    pub notification_id: Option<i32>,
+    pub oncall_provider: Option<OncallProviderKind>,
+    pub oncall_id: Option<String>,
+    pub chat_provider: Option<ChatProviderKind>,
+    pub user_group_id: Option<String>,
+    pub slack_channel_id: Option<String>,
+    pub previous_value: Option<String>,
+    pub new_value: Option<String>,
+}
+
+#[derive(Queryable, Clone, Serialize, Deserialize, Debug)]
+pub struct Shift {
+    pub id: i32,
+    pub oncall_provider: OncallProviderKind,
+    pub oncall_id: String,
+    pub user_id: String,
+    pub started_at: i64,
+    /// None while the shift is still going on
+    pub ended_at: Option<i64>,
+    /// When admins were warned that the user has no user mapping
+    pub unmapped_warned_at: Option<i64>,
+}
+
+#[derive(Insertable, Serialize, Deserialize, Debug)]
+#[table_name = "shifts"]
+pub struct NewShift<'a> {
+    pub oncall_provider: OncallProviderKind,
+    pub oncall_id: &'a str,
+    pub user_id: &'a str,
+    pub started_at: i64,
+}
+
+/// A DM reminding a user of their upcoming shift, queued until `remind_at`.
+#[derive(Queryable, Clone, Serialize, Deserialize, Debug)]
+pub struct ShiftReminder {
+    pub id: i32,
+    pub oncall_provider: OncallProviderKind,
+    pub oncall_id: String,
+    pub chat_provider: ChatProviderKind,
+    /// Oncall provider user ID
+    pub user_id: String,
+    pub shift_start: i64,
+    pub is_override: bool,
+    pub remind_at: i64,
+    /// None until the reminder has been sent
+    pub sent_at: Option<i64>,
+}
+
+#[derive(Insertable, Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[table_name = "shift_reminders"]
+pub struct NewShiftReminder {
+    pub oncall_provider: OncallProviderKind,
+    pub oncall_id: String,
+    pub chat_provider: ChatProviderKind,
+    pub user_id: String,
+    pub shift_start: i64,
+    pub is_override: bool,
+    pub remind_at: i64,
+}
+
+#[derive(Queryable, Clone, Serialize, Deserialize, Debug)]
+pub struct ApiToken {
+    pub id: i32,
+    /// Recorded as the actor of every change made with this token
+    pub name: String,
+    /// SHA-256 of the token. The token itself is only shown once, when it's created.
+    #[serde(skip_serializing)]
+    #[allow(dead_code)]
+    pub token_hash: String,
+    pub role: Role,
+    pub created_at: i64,
+}
+
+#[derive(Insertable, Serialize, Deserialize, Debug)]
+#[table_name = "api_tokens"]
+pub struct NewApiToken<'a> {
+    pub name: &'a str,
+    pub token_hash: &'a str,
+    pub role: Role,
+    pub created_at: i64,
 }
@@ -1,200 +1,448 @@
-use crate::{db, opsgenie, slack};
-use log::{info, warn};
-use std::time::Duration;
-use tokio::{
-    join,
-    sync::oneshot::{self, error::TryRecvError, Receiver, Sender},
-    time::sleep,
+use crate::{
+    audit,
+    chat_provider::{self, Announcement, ChatProviderKind},
+    models::NewAuditEvent,
+    oncall_provider::OncallMember,
+    preview::{ChannelPreview, Previews},
+    scheduler::{ChannelTarget, ScheduleKey, UserGroupTarget},
+    template::{self, Placeholder, Templates, Values, TOPIC_SEPARATOR},
 };
+use log::{info, warn};
+use tokio::join;
 
-const TOPIC_PREFIX: &str = "Current oncall: ";
-const TOPIC_SEPARATOR: &str = " | ";
+const NEXT_TOPIC_PREFIX: &str = "Next oncall: ";
+/// Separates the schedules' parts of the topic in channels notified about several schedules.
+const SCHEDULE_SEPARATOR: &str = "; ";
 
-#[derive(Debug)]
-pub struct SlackNotifier {
-    oncall_id: String,
-    slack_channel_id: String,
-    stop_tx: Option<Sender<()>>,
+/// Who takes over from the current oncalls, with chat user IDs.
+#[derive(Debug, Clone)]
+pub struct NextOncall {
+    pub members: Vec<OncallMember>,
+    /// Unix timestamp of the handoff, if the schedule has one coming up
+    pub handoff_at: Option<i64>,
 }
 
-impl SlackNotifier {
-    pub fn new(oncall_id: String, slack_channel_id: String) -> SlackNotifier {
-        let (stop_tx, stop_rx) = oneshot::channel();
-        let oncall_id_clone = oncall_id.clone();
-        let slack_channel_id_clone = slack_channel_id.clone();
-        tokio::spawn(async move {
-            slack_notifier(oncall_id_clone, slack_channel_id_clone, stop_rx).await
-        });
-        SlackNotifier {
-            stop_tx: Some(stop_tx),
-            slack_channel_id,
-            oncall_id,
-        }
+/// What's known about a schedule when notifying its channels. Everything but the current oncalls
+/// is only fetched when a channel shows it, and is left out when fetching it failed.
+#[derive(Debug, Clone, Default)]
+pub struct ScheduleInfo {
+    /// Current oncalls, with chat user IDs
+    pub members: Vec<OncallMember>,
+    pub next: Option<NextOncall>,
+    pub name: Option<String>,
+    /// User groups synced with the schedule, for `{user_group}`
+    pub user_groups: Vec<UserGroupTarget>,
+}
+
+/// Placeholder values for a channel's templates. Announcements say which team or escalation each
+/// user is on call through, topics are kept short.
+#[derive(Debug, Clone)]
+pub struct ChannelValues {
+    pub topic: Values,
+    pub message: Values,
+}
+
+pub async fn format_mentions(
+    chat_provider: ChatProviderKind,
+    members: &[OncallMember],
+) -> chat_provider::Result<Vec<String>> {
+    futures::future::join_all(
+        members
+            .iter()
+            .map(|member| chat_provider.provider().format_mention(&member.user_id)),
+    )
+    .await
+    .into_iter()
+    .collect()
+}
+
+pub fn join_mentions(mentions: &[String]) -> String {
+    if mentions.is_empty() {
+        "nobody".to_string()
+    } else {
+        mentions.join(" ")
     }
 }
 
-impl Drop for SlackNotifier {
-    fn drop(&mut self) {
-        // Extract out `stop_tx` (which should never be none)
-        match std::mem::take(&mut self.stop_tx) {
-            Some(stop_tx) => {
-                // Sending a stop will be sufficient to stop the oncall syncer on the next iteration
-                if let Err(_) = stop_tx.send(()) {
-                    warn!("Slack notifier for oncall ID \"{}\" and slack channel ID \"{}\" failed to send stop. It's likely something went wrong with the syncer task.", self.oncall_id, self.slack_channel_id);
+/// Works out the placeholder values for a channel in the given chat workspace. Only mentioning
+/// the current and next oncalls can fail, a user group that can't be mentioned is left out.
+pub async fn channel_values(
+    chat_provider: ChatProviderKind,
+    info: &ScheduleInfo,
+    templates: &Templates,
+) -> chat_provider::Result<ChannelValues> {
+    let mentions = format_mentions(chat_provider, &info.members).await?;
+    let users_with_sources = mentions
+        .iter()
+        .zip(&info.members)
+        .map(|(mention, member)| match &member.via {
+            Some(via) => format!("{} (via {})", mention, via),
+            None => mention.clone(),
+        })
+        .collect::<Vec<_>>();
+    let next = match &info.next {
+        Some(next) => Some(join_mentions(
+            &format_mentions(chat_provider, &next.members).await?,
+        )),
+        None => None,
+    };
+    let until = info
+        .next
+        .as_ref()
+        .and_then(|next| next.handoff_at)
+        .map(|handoff_at| chat_provider.provider().format_time(handoff_at));
+    let user_group = match info
+        .user_groups
+        .iter()
+        .find(|target| target.chat_provider == chat_provider)
+    {
+        Some(target) if templates.uses(Placeholder::UserGroup) => {
+            match chat_provider
+                .provider()
+                .format_user_group_mention(&target.user_group_id)
+                .await
+            {
+                Err(e) => {
+                    warn!(
+                        "Error formatting mention of user group {}: {}",
+                        target.user_group_id, e
+                    );
+                    None
                 }
-            }
-            None => {
-                warn!("self.stop_tx for slack notifier with oncall ID \"{}\" and slack channel ID \"{}\" was none. Did you double-drop?", self.oncall_id, self.slack_channel_id);
+                Ok(mention) => Some(mention),
             }
         }
-    }
+        _ => None,
+    };
+
+    let topic = Values {
+        users: join_mentions(&mentions),
+        schedule: info.name.clone(),
+        next,
+        until,
+        user_group,
+    };
+    Ok(ChannelValues {
+        message: Values {
+            users: join_mentions(&users_with_sources),
+            ..topic.clone()
+        },
+        topic,
+    })
 }
 
-async fn slack_notifier(oncall_id: String, slack_channel_id: String, mut stop_rx: Receiver<()>) {
-    let sleep_time = Duration::from_secs(60);
-    let mut first_iter = true;
+/// A schedule notified in a channel, with what's known about it.
+#[derive(Debug, Clone)]
+pub struct ChannelSchedule {
+    pub schedule: ScheduleKey,
+    pub target: ChannelTarget,
+    pub info: ScheduleInfo,
+}
 
-    loop {
-        // While putting the sleep at the end gets rid of this if, putting it here allows us to use
-        // continues to break flow cleanly and avoid relentless retries if there's an issue
-        if first_iter {
-            first_iter = false;
-        } else {
-            sleep(sleep_time).await;
-        }
+impl ChannelSchedule {
+    /// What the schedule is called in a topic or announcement shared with other schedules.
+    fn label(&self) -> &str {
+        self.info
+            .name
+            .as_deref()
+            .unwrap_or(&self.schedule.oncall_id)
+    }
+}
 
-        // First, make sure we haven't gotten a stop message
-        match stop_rx.try_recv() {
-            // We've been asked to stop, or the process above is dead. Stop, so return immediately
-            Ok(_) | Err(TryRecvError::Closed) => {
-                return;
-            }
-            // Do nothing if empty
-            Err(TryRecvError::Empty) => {}
-        };
+/// A schedule's share of the channel's topic and announcement.
+struct Rendered {
+    /// Topic without the template's prefix
+    topic: String,
+    message: String,
+    next: Option<String>,
+}
 
-        info!(
-            "Checking notification for oncall_id {} and slack_channel_id {}",
-            oncall_id, slack_channel_id
-        );
+async fn render_schedule(
+    chat_provider: ChatProviderKind,
+    part: &ChannelSchedule,
+    shared: bool,
+) -> chat_provider::Result<Rendered> {
+    let templates = &part.target.templates;
+    let values = channel_values(chat_provider, &part.info, templates).await?;
+    let next = match &values.topic.next {
+        Some(next) if part.target.show_next_oncall => Some(match &values.topic.until {
+            Some(until) => format!("{} from {}", next, until),
+            None => next.clone(),
+        }),
+        _ => None,
+    };
 
-        let current_oncalls = match opsgenie::get_current_oncalls(&oncall_id).await {
-            Err(e) => {
-                warn!(
-                    "Error fetching current oncall data for {}: {}",
-                    oncall_id, e
-                );
-                continue;
-            }
-            Ok(oncalls) => oncalls,
-        };
+    let topic_template = templates.topic();
+    let topic = template::render(topic_template, &values.topic);
+    let topic = topic
+        .strip_prefix(template::topic_prefix(topic_template))
+        .unwrap_or(&topic)
+        .to_string();
+    let message_template = if part.info.members.is_empty() {
+        templates.out_of_hours()
+    } else {
+        templates.message()
+    };
+    let message = template::render(message_template, &values.message);
+    let message = match &next {
+        Some(next) => format!("{}\nNext up: {}", message, next),
+        None => message,
+    };
 
-        // This filters out any users we don't have a mapping for
-        let tasks = current_oncalls.into_iter().map(|opsgenie_user_id| {
-            tokio::spawn(async move {
-                let connection = db::connection();
-                db::get_opsgenie_user_mapping(&connection, &opsgenie_user_id)
-            })
+    // In a channel shared with other schedules, say which schedule each part is about unless
+    // the template already does
+    if !shared {
+        return Ok(Rendered {
+            topic,
+            message,
+            next,
         });
-        let slack_users = futures::future::join_all(tasks)
-            .await
-            .into_iter()
-            .filter_map(|user_mapping| match user_mapping {
-                Err(e) => {
-                    warn!("Error fetching user mapping: {}", e);
-                    None
-                }
-                Ok(Err(e)) => {
-                    warn!("Error fetching user mapping: {}", e);
-                    None
-                }
-                Ok(Ok(user_mapping)) => user_mapping.map(|user_mapping| user_mapping.slack_id),
-            })
-            .collect::<Vec<_>>();
-
-        // Generate @ section of topic
-        let users_string = slack_users.iter().fold("".to_string(), |full, user_id| {
-            let user_at = format!("<@{}>", user_id);
-            if full == "" {
-                user_at
-            } else {
-                format!("{} {}", full, user_at)
-            }
-        });
-        let users_string = if users_string == "" {
-            "nobody".to_string()
+    }
+    Ok(Rendered {
+        topic: if template::uses(topic_template, Placeholder::Schedule) {
+            topic
         } else {
-            users_string
-        };
+            format!("{}: {}", part.label(), topic)
+        },
+        message: if template::uses(message_template, Placeholder::Schedule) {
+            message
+        } else {
+            format!("*{}*: {}", part.label(), message)
+        },
+        next: next.map(|next| format!("{}: {}", part.label(), next)),
+    })
+}
 
-        // Check the channel's topic to see if it needs updating
-        let channel = match slack::get_channel(&slack_channel_id).await {
-            Err(e) => {
-                warn!("Error fetching slack channel {}: {}", slack_channel_id, e);
-                continue;
-            }
-            Ok(c) => c,
-        };
-        let mut needs_update = true;
-        let mut has_topic = false;
-        let new_topic = channel
+/// Brings a channel's topic in line with the schedules notified in it, announcing the oncalls of
+/// the schedules whose part of the topic changed. `schedules` are all of the channel's schedules
+/// in the order they're shown, including `own`, the notification being reconciled. They share
+/// one topic segment, which starts with the first schedule's topic prefix and has a part per
+/// schedule. Each schedule's part of the topic and announcement is rendered from its
+/// notification's templates, and named after the schedule when the channel has several.
+/// Announcements get buttons with `rich_messages`, plain ones are combined into one message.
+/// Channels with `show_next_oncall` also get who is next and when they take over in their topic
+/// and announcements, if it could be fetched. Topic changes are recorded in the audit log. In
+/// dry-run mode the new topic and message are only stored in `previews`. Returns the error that
+/// kept the channel from being updated, if any.
+pub async fn notify_channel(
+    own: &ChannelSchedule,
+    schedules: &[ChannelSchedule],
+    dry_run: bool,
+    previews: &Previews,
+) -> chat_provider::Result {
+    let chat_provider = own.target.chat_provider;
+    let slack_channel_id = &own.target.slack_channel_id[..];
+    info!(
+        "Checking notification for {} slack_channel_id {}",
+        chat_provider, slack_channel_id
+    );
+
+    let shared = schedules.len() > 1;
+    let rendered = match futures::future::join_all(
+        schedules
+            .iter()
+            .map(|part| render_schedule(chat_provider, part, shared)),
+    )
+    .await
+    .into_iter()
+    .collect::<chat_provider::Result<Vec<_>>>()
+    {
+        Err(e) => {
+            warn!("Error formatting user mentions: {}", e);
+            return Err(e);
+        }
+        Ok(rendered) => rendered,
+    };
+    let next_parts: Vec<&str> = rendered
+        .iter()
+        .filter_map(|rendered| rendered.next.as_deref())
+        .collect();
+    let next_string = if next_parts.is_empty() {
+        None
+    } else {
+        Some(next_parts.join(SCHEDULE_SEPARATOR))
+    };
+    let show_next_oncall = schedules.iter().any(|part| part.target.show_next_oncall);
+
+    // Check the channel's topic to see if it needs updating
+    let channel = match chat_provider.provider().get_channel(slack_channel_id).await {
+        Err(e) => {
+            warn!("Error fetching slack channel {}: {}", slack_channel_id, e);
+            return Err(e);
+        }
+        Ok(c) => c,
+    };
+    let topic_prefix = schedules
+        .first()
+        .map(|part| template::topic_prefix(part.target.templates.topic()))
+        .unwrap_or_default();
+    let topic_parts: Vec<&str> = rendered
+        .iter()
+        .map(|rendered| &rendered.topic[..])
+        .collect();
+    let topic_element = format!("{}{}", topic_prefix, topic_parts.join(SCHEDULE_SEPARATOR));
+    // Which schedules' oncalls changed since the topic was last set
+    let mut changed = vec![true; schedules.len()];
+    let mut has_topic = false;
+    let mut has_next = false;
+    let mut elements: Vec<String> = if channel.topic.value.is_empty() {
+        vec![]
+    } else {
+        channel
             .topic
             .value
             .split(TOPIC_SEPARATOR)
-            .map(|element| {
-                if element.starts_with(TOPIC_PREFIX) {
+            .filter_map(|element| {
+                if let Some(previous) = element.strip_prefix(topic_prefix) {
                     has_topic = true;
-                    needs_update = element[TOPIC_PREFIX.len()..] != users_string;
-                    format!("{}{}", TOPIC_PREFIX, users_string)
+                    // Parts can only be told apart once they're named after their schedule
+                    let previous: Vec<&str> = if shared {
+                        previous.split(SCHEDULE_SEPARATOR).collect()
+                    } else {
+                        vec![previous]
+                    };
+                    changed = topic_parts
+                        .iter()
+                        .map(|part| !previous.contains(part))
+                        .collect();
+                    Some(topic_element.clone())
+                } else if element.starts_with(NEXT_TOPIC_PREFIX) {
+                    has_next = true;
+                    match &next_string {
+                        Some(next_string) => Some(format!("{}{}", NEXT_TOPIC_PREFIX, next_string)),
+                        // Keep what we showed last time if the next oncall couldn't be fetched
+                        None if show_next_oncall => Some(element.to_string()),
+                        None => None,
+                    }
                 } else {
-                    element.to_string()
+                    Some(element.to_string())
                 }
             })
-            .fold("".to_string(), |full, element| {
-                if full == "" {
-                    element.to_string()
+            .collect()
+    };
+    if !has_topic {
+        elements.push(topic_element);
+    }
+    if let (false, Some(next_string)) = (has_next, &next_string) {
+        elements.push(format!("{}{}", NEXT_TOPIC_PREFIX, next_string));
+    }
+    let new_topic = elements.join(TOPIC_SEPARATOR);
+    let topic_changed = new_topic != channel.topic.value;
+
+    let announced: Vec<(&ChannelSchedule, &Rendered)> = schedules
+        .iter()
+        .zip(&rendered)
+        .zip(&changed)
+        .filter(|(_, changed)| **changed)
+        .map(|(announced, _)| announced)
+        .collect();
+
+    if dry_run {
+        previews
+            .set_channel(ChannelPreview {
+                notification_id: own.target.notification_id,
+                oncall_provider: own.schedule.oncall_provider,
+                oncall_id: own.schedule.oncall_id.clone(),
+                chat_provider,
+                slack_channel_id: slack_channel_id.to_string(),
+                current_topic: channel.topic.value.clone(),
+                new_topic,
+                message: if announced.is_empty() {
+                    None
+                } else {
+                    Some(
+                        announced
+                            .iter()
+                            .map(|(_, rendered)| &rendered.message[..])
+                            .collect::<Vec<_>>()
+                            .join("\n"),
+                    )
+                },
+                computed_at: audit::now(),
+            })
+            .await;
+        return Ok(());
+    }
+
+    // Finally, if needed, update the slack channel topic and send a message. The next oncall's
+    // part of the topic can change without the current oncall changing, which isn't worth
+    // announcing.
+    if topic_changed {
+        let chat = chat_provider.provider();
+        let combined_message = announced
+            .iter()
+            .filter(|(part, _)| !part.target.rich_messages)
+            .map(|(_, rendered)| &rendered.message[..])
+            .collect::<Vec<_>>()
+            .join("\n");
+        let post_messages = async {
+            let rich = announced
+                .iter()
+                .filter(|(part, _)| part.target.rich_messages)
+                .map(|(part, rendered)| async move {
+                    let user_ids: Vec<String> = part
+                        .info
+                        .members
+                        .iter()
+                        .map(|member| member.user_id.clone())
+                        .collect();
+                    let announcement = Announcement {
+                        text: &rendered.message,
+                        oncall_provider: part.schedule.oncall_provider,
+                        oncall_id: &part.schedule.oncall_id,
+                        schedule_name: part.info.name.as_deref(),
+                        user_ids: &user_ids,
+                        until: part.info.next.as_ref().and_then(|next| next.handoff_at),
+                    };
+                    chat.post_announcement(slack_channel_id, &announcement)
+                        .await
+                });
+            let plain = async {
+                if combined_message.is_empty() {
+                    Ok(())
                 } else {
-                    format!("{}{}{}", full, TOPIC_SEPARATOR, element)
+                    chat.post_message(slack_channel_id, &combined_message).await
                 }
-            });
-        let new_topic = if has_topic {
-            new_topic
-        } else if new_topic == "" {
-            format!("{}{}", TOPIC_PREFIX, users_string)
-        } else {
-            format!(
-                "{}{}{}{}",
-                new_topic, TOPIC_SEPARATOR, TOPIC_PREFIX, users_string
-            )
+            };
+            let (rich_results, plain_result) = join!(futures::future::join_all(rich), plain);
+            rich_results
+                .into_iter()
+                .chain([plain_result])
+                .collect::<chat_provider::Result>()
         };
+        let (post_result, topic_result) = join!(
+            post_messages,
+            chat.set_channel_topic(slack_channel_id, &new_topic)
+        );
 
-        // Finally, if needed, update the slack channel topic and send a message.
-        if needs_update {
-            let posted_message = if slack_users.is_empty() {
-                format!("This channel's oncall is out of hours. Please wait for the next oncall for urgent requests.")
-            } else {
-                format!(
-                    "There's a new oncall! Please direct all questions to {}",
-                    users_string
-                )
-            };
-            let (post_result, topic_result) = join!(
-                slack::post_message(&slack_channel_id, &posted_message),
-                slack::set_channel_topic(&slack_channel_id, &new_topic)
+        if let Err(e) = &post_result {
+            warn!(
+                "Failed to send message to channel {}: {}",
+                slack_channel_id, e
+            );
+        }
+        if let Err(e) = &topic_result {
+            warn!(
+                "Failed to update topic on channel {}: {}",
+                slack_channel_id, e
             );
+        }
 
-            if let Err(e) = post_result {
-                warn!(
-                    "Failed to send message to channel {}: {}",
-                    &slack_channel_id, e
-                );
-            }
-            if let Err(e) = topic_result {
-                warn!(
-                    "Failed to update topic on channel {}: {}",
-                    &slack_channel_id, e
-                );
+        audit::record(
+            NewAuditEvent {
+                notification_id: Some(own.target.notification_id),
+                oncall_provider: Some(own.schedule.oncall_provider),
+                oncall_id: Some(own.schedule.oncall_id.clone()),
+                chat_provider: Some(chat_provider),
+                slack_channel_id: Some(slack_channel_id.to_string()),
+                previous_value: Some(channel.topic.value.clone()),
+                new_value: Some(new_topic.clone()),
+                ..NewAuditEvent::new(audit::SYSTEM_ACTOR, "set_channel_topic")
             }
-        }
+            .with_result(&topic_result),
+        )
+        .await;
+        return topic_result.and(post_result);
     }
+    Ok(())
 }
@@ -0,0 +1,299 @@
+use crate::{opsgenie, pagerduty};
+use diesel::{
+    backend::Backend,
+    deserialize::{self, FromSql},
+    serialize::{self, Output, ToSql},
+    sql_types::Text,
+    sqlite::Sqlite,
+};
+use futures::future::BoxFuture;
+use serde::{Deserialize, Serialize};
+use std::{fmt, io::Write, str::FromStr};
+use time::{format_description::well_known::Rfc3339, OffsetDateTime};
+
+#[derive(thiserror::Error, Debug)]
+pub enum Error {
+    #[error("opsgenie error: {0}")]
+    Opsgenie(#[from] opsgenie::Error),
+    #[error("pagerduty error: {0}")]
+    Pagerduty(#[from] pagerduty::Error),
+}
+
+impl Error {
+    /// Returns true if the provider told us the requested resource does not exist.
+    pub fn is_not_found(&self) -> bool {
+        match self {
+            Error::Opsgenie(opsgenie::Error::HttpErrorCode(code))
+            | Error::Pagerduty(pagerduty::Error::HttpErrorCode(code)) => {
+                *code == reqwest::StatusCode::NOT_FOUND
+            }
+            _ => false,
+        }
+    }
+}
+
+pub type Result<T = (), E = Error> = std::result::Result<T, E>;
+
+#[derive(thiserror::Error, Debug)]
+#[error("unknown oncall provider \"{0}\"")]
+pub struct UnknownProvider(String);
+
+/// Identifies which on-call service a sync or notification is backed by. This is what gets stored
+/// in the `oncall_provider` column of the DB.
+#[derive(
+    Serialize,
+    Deserialize,
+    Debug,
+    Clone,
+    Copy,
+    Default,
+    PartialEq,
+    Eq,
+    Hash,
+    AsExpression,
+    FromSqlRow,
+)]
+#[serde(rename_all = "lowercase")]
+#[sql_type = "Text"]
+pub enum OncallProviderKind {
+    #[default]
+    Opsgenie,
+    Pagerduty,
+}
+
+impl OncallProviderKind {
+    pub const ALL: [OncallProviderKind; 2] =
+        [OncallProviderKind::Opsgenie, OncallProviderKind::Pagerduty];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            OncallProviderKind::Opsgenie => "opsgenie",
+            OncallProviderKind::Pagerduty => "pagerduty",
+        }
+    }
+
+    /// How the service spells its name, for messages.
+    pub fn display_name(&self) -> &'static str {
+        match self {
+            OncallProviderKind::Opsgenie => "OpsGenie",
+            OncallProviderKind::Pagerduty => "PagerDuty",
+        }
+    }
+
+    pub fn provider(&self) -> &'static dyn OncallProvider {
+        match self {
+            OncallProviderKind::Opsgenie => &opsgenie::Opsgenie,
+            OncallProviderKind::Pagerduty => &pagerduty::Pagerduty,
+        }
+    }
+
+    /// A provider is configured when its API key is present in the environment.
+    pub fn is_configured(&self) -> bool {
+        self.provider().is_configured()
+    }
+
+    pub fn configured() -> Vec<OncallProviderKind> {
+        OncallProviderKind::ALL
+            .into_iter()
+            .filter(|kind| kind.is_configured())
+            .collect()
+    }
+}
+
+impl fmt::Display for OncallProviderKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for OncallProviderKind {
+    type Err = UnknownProvider;
+
+    fn from_str(s: &str) -> Result<OncallProviderKind, UnknownProvider> {
+        OncallProviderKind::ALL
+            .into_iter()
+            .find(|kind| kind.as_str() == s)
+            .ok_or_else(|| UnknownProvider(s.into()))
+    }
+}
+
+impl ToSql<Text, Sqlite> for OncallProviderKind {
+    fn to_sql<W: Write>(&self, out: &mut Output<W, Sqlite>) -> serialize::Result {
+        ToSql::<Text, Sqlite>::to_sql(self.as_str(), out)
+    }
+}
+
+impl FromSql<Text, Sqlite> for OncallProviderKind {
+    fn from_sql(bytes: Option<&<Sqlite as Backend>::RawValue>) -> deserialize::Result<Self> {
+        let value = <String as FromSql<Text, Sqlite>>::from_sql(bytes)?;
+        Ok(value.parse()?)
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Oncall {
+    pub id: String,
+    pub name: String,
+    pub provider: OncallProviderKind,
+}
+
+/// Kind of group that can be put in a rotation instead of a single user.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum GroupKind {
+    Team,
+    Escalation,
+}
+
+impl fmt::Display for GroupKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            GroupKind::Team => "team",
+            GroupKind::Escalation => "escalation",
+        })
+    }
+}
+
+/// The group in the rotation that put a user on call.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct Via {
+    pub kind: GroupKind,
+    pub name: String,
+}
+
+impl fmt::Display for Via {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {}", self.kind, self.name)
+    }
+}
+
+/// A user on call for a schedule.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct OncallMember {
+    pub user_id: String,
+    /// None if the user is in the rotation themselves
+    pub via: Option<Via>,
+}
+
+impl OncallMember {
+    pub fn user(user_id: String) -> OncallMember {
+        OncallMember { user_id, via: None }
+    }
+}
+
+/// Parses the RFC 3339 times used by the providers' APIs into Unix timestamps.
+pub fn parse_time(time: &str) -> Option<i64> {
+    OffsetDateTime::parse(time, &Rfc3339)
+        .ok()
+        .map(|time| time.unix_timestamp())
+}
+
+/// Formats a Unix timestamp the way the providers' APIs expect, the reverse of [`parse_time`].
+pub fn format_time(timestamp: i64) -> String {
+    OffsetDateTime::from_unix_timestamp(timestamp)
+        .ok()
+        .and_then(|time| time.format(&Rfc3339).ok())
+        .unwrap_or_default()
+}
+
+/// The same user can be on call through several groups, so keep the first occurrence of each.
+pub fn dedup_members(members: Vec<OncallMember>) -> Vec<OncallMember> {
+    let mut deduped: Vec<OncallMember> = vec![];
+    for member in members {
+        if !deduped
+            .iter()
+            .any(|existing| existing.user_id == member.user_id)
+        {
+            deduped.push(member);
+        }
+    }
+    deduped
+}
+
+/// A user's shift coming up in a schedule.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct UpcomingShift {
+    pub user_id: String,
+    /// Unix timestamps of the shift's start and end
+    pub start: i64,
+    pub end: i64,
+    /// Whether the shift comes from an override rather than the schedule's rotations
+    pub is_override: bool,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct User {
+    pub id: String,
+    pub username: String,
+    #[serde(rename = "fullName")]
+    pub full_name: String,
+    pub provider: OncallProviderKind,
+}
+
+/// Common interface over the services that own on-call schedules (OpsGenie, PagerDuty, ...). The
+/// workers and handlers only talk to schedules through this trait, so a sync or notification can
+/// be backed by any of the implementations.
+pub trait OncallProvider: Send + Sync {
+    fn kind(&self) -> OncallProviderKind;
+
+    fn is_configured(&self) -> bool;
+
+    fn list_oncalls(&self) -> BoxFuture<'_, Result<Vec<Oncall>>>;
+
+    fn get_oncall_name<'a>(&'a self, id: &'a str) -> BoxFuture<'a, Result<String>>;
+
+    fn list_users(&self) -> BoxFuture<'_, Result<Vec<User>>>;
+
+    fn get_user<'a>(&'a self, id: &'a str) -> BoxFuture<'a, Result<User>>;
+
+    /// Returns the users currently on call for the given schedule. Groups in the rotation are
+    /// expanded to their members.
+    fn get_current_oncalls<'a>(
+        &'a self,
+        oncall_id: &'a str,
+    ) -> BoxFuture<'a, Result<Vec<OncallMember>>>;
+
+    /// Returns the users that will be on call once the current shift ends.
+    fn get_next_oncalls<'a>(
+        &'a self,
+        oncall_id: &'a str,
+    ) -> BoxFuture<'a, Result<Vec<OncallMember>>>;
+
+    /// Returns when the current shift ends and the next oncalls take over, as a Unix timestamp.
+    /// None if the schedule has no upcoming handoff.
+    fn get_handoff_time<'a>(&'a self, oncall_id: &'a str) -> BoxFuture<'a, Result<Option<i64>>>;
+
+    /// Puts the user on call for the schedule between the two Unix timestamps, in place of
+    /// whoever the rotation says.
+    fn add_override<'a>(
+        &'a self,
+        oncall_id: &'a str,
+        user_id: &'a str,
+        start: i64,
+        end: i64,
+    ) -> BoxFuture<'a, Result>;
+
+    /// Returns the shifts of single users starting between now and the `until` Unix timestamp,
+    /// ordered by start. Providers without a timeline return none.
+    fn get_upcoming_shifts<'a>(
+        &'a self,
+        _oncall_id: &'a str,
+        _until: i64,
+    ) -> BoxFuture<'a, Result<Vec<UpcomingShift>>> {
+        Box::pin(async move { Ok(vec![]) })
+    }
+
+    /// Returns how many alerts are open for the team behind the schedule. None if the provider
+    /// can't tell which alerts belong to a schedule.
+    fn count_open_alerts<'a>(
+        &'a self,
+        _oncall_id: &'a str,
+    ) -> BoxFuture<'a, Result<Option<usize>>> {
+        Box::pin(async move { Ok(None) })
+    }
+
+    /// Link to the schedule in the provider's web app, if it has a stable one.
+    fn schedule_url(&self, _oncall_id: &str) -> Option<String> {
+        None
+    }
+}
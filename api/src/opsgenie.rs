@@ -1,13 +1,39 @@
+use crate::{
+    api_client::{ApiClient, RateLimit},
+    audit,
+    oncall_provider::{
+        self, GroupKind, OncallMember, OncallProvider, OncallProviderKind, UpcomingShift, Via,
+    },
+};
+use futures::future::{join_all, BoxFuture};
+use log::warn;
 use reqwest::{header::AUTHORIZATION, StatusCode};
 use serde::{Deserialize, Serialize};
-use std::env;
+use std::{env, sync::OnceLock, time::Duration};
+use subtle::ConstantTimeEq;
+
+/// Alerts get created asynchronously, so we poll their request status this many times.
+const ALERT_REQUEST_ATTEMPTS: usize = 5;
+const ALERT_REQUEST_POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Header OpsGenie webhook integrations must be configured to send with the shared secret.
+pub const WEBHOOK_SECRET_HEADER: &str = "X-Oncall-Bot-Secret";
+
+/// OpsGenie's quota depends on the plan, so this can be changed with
+/// `OPSGENIE_REQUESTS_PER_MINUTE`.
+const DEFAULT_REQUESTS_PER_MINUTE: u32 = 300;
+
+static CLIENT: OnceLock<ApiClient> = OnceLock::new();
 
 #[derive(thiserror::Error, Debug)]
+#[allow(clippy::enum_variant_names)]
 pub enum Error {
     #[error("error making request")]
     RequestError(#[from] reqwest::Error),
     #[error("got HTTP {0}")]
     HttpErrorCode(StatusCode),
+    #[error("alert request {0} was not processed in time")]
+    AlertRequestNotProcessed(String),
 }
 
 pub type Result<T = (), E = Error> = std::result::Result<T, E>;
@@ -22,6 +48,74 @@ pub struct Oncall {
 struct Schedule {
     pub id: String,
     pub name: String,
+    #[serde(rename = "ownerTeam")]
+    pub owner_team: Option<Team>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct Team {
+    pub id: String,
+    pub name: String,
+    /// Only included when fetching the team itself
+    #[serde(default)]
+    pub members: Vec<TeamMember>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Alert {
+    pub id: String,
+    pub url: String,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct Responder<'a> {
+    pub id: &'a str,
+    #[serde(rename = "type")]
+    pub typ: &'a str,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct CreateAlertRequest<'a> {
+    pub message: &'a str,
+    pub description: &'a str,
+    pub responders: Vec<Responder<'a>>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct CreateOverrideRequest<'a> {
+    #[serde(borrow)]
+    pub user: Responder<'a>,
+    #[serde(rename = "startDate")]
+    pub start_date: String,
+    #[serde(rename = "endDate")]
+    pub end_date: String,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct CreateAlertResponse {
+    #[serde(rename = "requestId")]
+    pub request_id: String,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct AlertRequestStatus {
+    #[serde(rename = "alertId")]
+    pub alert_id: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct AlertRequestStatusResponse {
+    pub data: AlertRequestStatus,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct AlertCount {
+    pub count: usize,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct AlertCountResponse {
+    pub data: AlertCount,
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -35,8 +129,29 @@ pub struct User {
 #[derive(Serialize, Deserialize, Debug)]
 pub struct OncallParticipant {
     pub id: String,
+    #[serde(default)]
+    pub name: String,
+    /// `user`, `team` or `escalation`
     #[serde(rename = "type")]
     pub typ: String,
+    /// Members of a team or escalation, when OpsGenie includes them
+    #[serde(default)]
+    pub participants: Vec<OncallParticipant>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct TeamMemberUser {
+    pub id: String,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct TeamMember {
+    pub user: TeamMemberUser,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct GetTeamResponse {
+    pub data: Team,
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -45,6 +160,88 @@ pub struct CurrentOncall {
     pub on_call_participants: Vec<OncallParticipant>,
 }
 
+#[derive(Serialize, Deserialize, Debug)]
+pub struct NextOncall {
+    #[serde(rename = "nextOnCallParticipants", alias = "nextOnCallRecipients")]
+    pub next_on_call_participants: Vec<OncallParticipant>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct TimelineRecipient {
+    pub id: String,
+    /// `user`, `team`, `escalation` or `none`
+    #[serde(rename = "type")]
+    pub typ: String,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct TimelinePeriod {
+    #[serde(rename = "startDate")]
+    pub start_date: String,
+    #[serde(rename = "endDate")]
+    pub end_date: String,
+    /// `default`, `override`, `historical` or `forwarding`
+    #[serde(rename = "type", default)]
+    pub typ: Option<String>,
+    pub recipient: Option<TimelineRecipient>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct TimelineRotation {
+    #[serde(default)]
+    pub periods: Vec<TimelinePeriod>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct Timeline {
+    #[serde(default)]
+    pub rotations: Vec<TimelineRotation>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct ScheduleTimeline {
+    #[serde(rename = "finalTimeline")]
+    pub final_timeline: Timeline,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct TimelineResponse {
+    pub data: ScheduleTimeline,
+}
+
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct WebhookSchedule {
+    pub id: Option<String>,
+    pub name: Option<String>,
+}
+
+/// Callback sent by OpsGenie when a schedule, override or on-call rotation changes. Depending on
+/// the integration the schedule is either nested or flattened into the top level.
+#[derive(Serialize, Deserialize, Debug, Default)]
+#[serde(rename_all = "camelCase", default)]
+pub struct WebhookPayload {
+    pub action: Option<String>,
+    pub schedule: Option<WebhookSchedule>,
+    pub schedule_id: Option<String>,
+    pub schedule_name: Option<String>,
+}
+
+impl WebhookPayload {
+    pub fn schedule_id(&self) -> Option<&str> {
+        self.schedule
+            .as_ref()
+            .and_then(|schedule| schedule.id.as_deref())
+            .or(self.schedule_id.as_deref())
+    }
+
+    pub fn schedule_name(&self) -> Option<&str> {
+        self.schedule
+            .as_ref()
+            .and_then(|schedule| schedule.name.as_deref())
+            .or(self.schedule_name.as_deref())
+    }
+}
+
 #[derive(Serialize, Deserialize, Debug)]
 struct ScheduleListResponse {
     pub data: Vec<Schedule>,
@@ -70,13 +267,23 @@ struct CurrentOncallResponse {
     pub data: CurrentOncall,
 }
 
+#[derive(Serialize, Deserialize, Debug)]
+struct NextOncallResponse {
+    pub data: NextOncall,
+}
+
 pub async fn list_oncalls() -> Result<Vec<Oncall>> {
     let opsgenie_key = opsgenie_key();
-    let client = reqwest::Client::new();
+    let client = client();
     let schedules_response = client
-        .get("https://api.opsgenie.com/v2/schedules")
-        .header(AUTHORIZATION, format!("GenieKey {}", opsgenie_key))
-        .send()
+        .send(
+            "schedules",
+            true,
+            client
+                .http()
+                .get(format!("{}/v2/schedules", opsgenie_api_url()))
+                .header(AUTHORIZATION, format!("GenieKey {}", opsgenie_key)),
+        )
         .await?;
 
     Ok(match schedules_response.status() {
@@ -96,31 +303,41 @@ pub async fn list_oncalls() -> Result<Vec<Oncall>> {
 }
 
 pub async fn get_oncall_name(id: &str) -> Result<String> {
+    Ok(get_schedule(id).await?.name)
+}
+
+async fn get_schedule(id: &str) -> Result<Schedule> {
     let opsgenie_key = opsgenie_key();
-    let client = reqwest::Client::new();
+    let client = client();
     let schedule_response = client
-        .get(format!("https://api.opsgenie.com/v2/schedules/{}", id))
-        .header(AUTHORIZATION, format!("GenieKey {}", opsgenie_key))
-        .send()
+        .send(
+            "schedules",
+            true,
+            client
+                .http()
+                .get(format!("{}/v2/schedules/{}", opsgenie_api_url(), id))
+                .header(AUTHORIZATION, format!("GenieKey {}", opsgenie_key)),
+        )
         .await?;
 
     match schedule_response.status() {
-        reqwest::StatusCode::OK => Ok(schedule_response
-            .json::<GetScheduleResponse>()
-            .await?
-            .data
-            .name),
+        reqwest::StatusCode::OK => Ok(schedule_response.json::<GetScheduleResponse>().await?.data),
         code => Err(Error::HttpErrorCode(code)),
     }
 }
 
 pub async fn list_users() -> Result<Vec<User>> {
     let opsgenie_key = opsgenie_key();
-    let client = reqwest::Client::new();
+    let client = client();
     let users_response = client
-        .get("https://api.opsgenie.com/v2/users")
-        .header(AUTHORIZATION, format!("GenieKey {}", opsgenie_key))
-        .send()
+        .send(
+            "users",
+            true,
+            client
+                .http()
+                .get(format!("{}/v2/users", opsgenie_api_url()))
+                .header(AUTHORIZATION, format!("GenieKey {}", opsgenie_key)),
+        )
         .await?;
 
     match users_response.status() {
@@ -131,11 +348,16 @@ pub async fn list_users() -> Result<Vec<User>> {
 
 pub async fn get_user(id: &str) -> Result<User> {
     let opsgenie_key = opsgenie_key();
-    let client = reqwest::Client::new();
+    let client = client();
     let user_response = client
-        .get(format!("https://api.opsgenie.com/v2/users/{}", id))
-        .header(AUTHORIZATION, format!("GenieKey {}", opsgenie_key))
-        .send()
+        .send(
+            "users",
+            true,
+            client
+                .http()
+                .get(format!("{}/v2/users/{}", opsgenie_api_url(), id))
+                .header(AUTHORIZATION, format!("GenieKey {}", opsgenie_key)),
+        )
         .await?;
 
     match user_response.status() {
@@ -144,37 +366,600 @@ pub async fn get_user(id: &str) -> Result<User> {
     }
 }
 
-pub async fn get_current_oncalls(oncall_id: &str) -> Result<Vec<String>> {
+pub async fn get_current_oncalls(oncall_id: &str) -> Result<Vec<OncallMember>> {
     let opsgenie_key = opsgenie_key();
-    let client = reqwest::Client::new();
+    let client = client();
     let oncall_response = client
-        .get(format!(
-            "https://api.opsgenie.com/v2/schedules/{}/on-calls",
-            oncall_id
-        ))
-        .header(AUTHORIZATION, format!("GenieKey {}", opsgenie_key))
-        .send()
+        .send(
+            "schedules",
+            true,
+            client
+                .http()
+                .get(format!(
+                    "{}/v2/schedules/{}/on-calls",
+                    opsgenie_api_url(),
+                    oncall_id
+                ))
+                .header(AUTHORIZATION, format!("GenieKey {}", opsgenie_key)),
+        )
         .await?;
 
     match oncall_response.status() {
-        reqwest::StatusCode::OK => Ok(oncall_response
-            .json::<CurrentOncallResponse>()
+        reqwest::StatusCode::OK => {
+            flatten_participants(
+                oncall_response
+                    .json::<CurrentOncallResponse>()
+                    .await?
+                    .data
+                    .on_call_participants,
+            )
+            .await
+        }
+        code => Err(Error::HttpErrorCode(code)),
+    }
+}
+
+pub async fn get_next_oncalls(oncall_id: &str) -> Result<Vec<OncallMember>> {
+    let opsgenie_key = opsgenie_key();
+    let client = client();
+    let oncall_response = client
+        .send(
+            "schedules",
+            true,
+            client
+                .http()
+                .get(format!(
+                    "{}/v2/schedules/{}/next-on-calls",
+                    opsgenie_api_url(),
+                    oncall_id
+                ))
+                .header(AUTHORIZATION, format!("GenieKey {}", opsgenie_key)),
+        )
+        .await?;
+
+    match oncall_response.status() {
+        reqwest::StatusCode::OK => {
+            flatten_participants(
+                oncall_response
+                    .json::<NextOncallResponse>()
+                    .await?
+                    .data
+                    .next_on_call_participants,
+            )
+            .await
+        }
+        code => Err(Error::HttpErrorCode(code)),
+    }
+}
+
+/// Fetches the schedule's final timeline, overrides included, from now on for `interval`
+/// `interval_unit`s (`days`, `weeks` or `months`).
+async fn get_timeline(oncall_id: &str, interval: u64, interval_unit: &str) -> Result<Timeline> {
+    let opsgenie_key = opsgenie_key();
+    let client = client();
+    let timeline_response = client
+        .send(
+            "schedules",
+            true,
+            client
+                .http()
+                .get(format!(
+                    "{}/v2/schedules/{}/timeline",
+                    opsgenie_api_url(),
+                    oncall_id
+                ))
+                .query(&[
+                    ("interval", &interval.to_string()[..]),
+                    ("intervalUnit", interval_unit),
+                ])
+                .header(AUTHORIZATION, format!("GenieKey {}", opsgenie_key)),
+        )
+        .await?;
+
+    match timeline_response.status() {
+        reqwest::StatusCode::OK => Ok(timeline_response
+            .json::<TimelineResponse>()
+            .await?
+            .data
+            .final_timeline),
+        code => Err(Error::HttpErrorCode(code)),
+    }
+}
+
+pub async fn get_handoff_time(oncall_id: &str) -> Result<Option<i64>> {
+    let timeline = get_timeline(oncall_id, 1, "weeks").await?;
+    let now = audit::now();
+    Ok(timeline
+        .rotations
+        .iter()
+        .flat_map(|rotation| &rotation.periods)
+        .filter_map(|period| {
+            let start = oncall_provider::parse_time(&period.start_date)?;
+            let end = oncall_provider::parse_time(&period.end_date)?;
+            if start > now {
+                Some(start)
+            } else if end > now {
+                Some(end)
+            } else {
+                None
+            }
+        })
+        .min())
+}
+
+/// Returns the shifts of single users starting between now and `until`. Back to back periods of
+/// the same user, e.g. from consecutive rotations, are one shift.
+pub async fn get_upcoming_shifts(oncall_id: &str, until: i64) -> Result<Vec<UpcomingShift>> {
+    let now = audit::now();
+    let days = (until - now).max(0) as u64 / (24 * 60 * 60) + 1;
+    let timeline = get_timeline(oncall_id, days, "days").await?;
+    let periods: Vec<(&str, i64, i64, bool)> = timeline
+        .rotations
+        .iter()
+        .flat_map(|rotation| &rotation.periods)
+        .filter_map(|period| {
+            let recipient = period.recipient.as_ref().filter(|r| r.typ == "user")?;
+            Some((
+                &recipient.id[..],
+                oncall_provider::parse_time(&period.start_date)?,
+                oncall_provider::parse_time(&period.end_date)?,
+                period.typ.as_deref() == Some("override"),
+            ))
+        })
+        .collect();
+
+    let mut shifts: Vec<UpcomingShift> = periods
+        .iter()
+        .filter(|(user_id, start, _, _)| {
+            *start > now
+                && *start <= until
+                && !periods
+                    .iter()
+                    .any(|(other_id, _, other_end, _)| other_id == user_id && other_end == start)
+        })
+        .map(|&(user_id, start, end, is_override)| UpcomingShift {
+            user_id: user_id.to_string(),
+            start,
+            end,
+            is_override,
+        })
+        .collect();
+    shifts.sort_by_key(|shift| shift.start);
+    Ok(shifts)
+}
+
+/// Returns the IDs of the team's members.
+async fn get_team_members(team_id: &str) -> Result<Vec<String>> {
+    let opsgenie_key = opsgenie_key();
+    let client = client();
+    let team_response = client
+        .send(
+            "teams",
+            true,
+            client
+                .http()
+                .get(format!("{}/v2/teams/{}", opsgenie_api_url(), team_id))
+                .header(AUTHORIZATION, format!("GenieKey {}", opsgenie_key)),
+        )
+        .await?;
+
+    match team_response.status() {
+        reqwest::StatusCode::OK => Ok(team_response
+            .json::<GetTeamResponse>()
             .await?
             .data
-            .on_call_participants
+            .members
             .into_iter()
-            .filter_map(|participant| {
-                if participant.typ == "user" {
-                    Some(participant.id)
-                } else {
-                    None
-                }
-            })
+            .map(|member| member.user.id)
             .collect()),
         code => Err(Error::HttpErrorCode(code)),
     }
 }
 
+/// Resolves the participants of a rotation to users. Teams and escalations are expanded to their
+/// members, and each member remembers the group in the rotation they're on call through. Teams
+/// OpsGenie didn't expand for us are looked up.
+async fn flatten_participants(participants: Vec<OncallParticipant>) -> Result<Vec<OncallMember>> {
+    let mut members = vec![];
+    let mut teams = vec![];
+    for participant in participants {
+        collect_participant(participant, None, &mut members, &mut teams);
+    }
+
+    let team_members = join_all(teams.iter().map(|(team_id, _)| get_team_members(team_id))).await;
+    for ((_, via), team_members) in teams.into_iter().zip(team_members) {
+        members.extend(team_members?.into_iter().map(|user_id| OncallMember {
+            user_id,
+            via: Some(via.clone()),
+        }));
+    }
+    Ok(oncall_provider::dedup_members(members))
+}
+
+fn collect_participant(
+    participant: OncallParticipant,
+    via: Option<&Via>,
+    members: &mut Vec<OncallMember>,
+    teams: &mut Vec<(String, Via)>,
+) {
+    let kind = match &participant.typ[..] {
+        "user" => {
+            members.push(OncallMember {
+                user_id: participant.id,
+                via: via.cloned(),
+            });
+            return;
+        }
+        "team" => GroupKind::Team,
+        "escalation" => GroupKind::Escalation,
+        typ => {
+            warn!(
+                "Ignoring oncall participant {} of type {}",
+                participant.id, typ
+            );
+            return;
+        }
+    };
+    let via = via.cloned().unwrap_or(Via {
+        kind,
+        name: participant.name,
+    });
+
+    if participant.participants.is_empty() {
+        match kind {
+            GroupKind::Team => teams.push((participant.id, via)),
+            GroupKind::Escalation => {
+                warn!(
+                    "Escalation {} has no participants to notify",
+                    participant.id
+                )
+            }
+        }
+        return;
+    }
+    for nested in participant.participants {
+        collect_participant(nested, Some(&via), members, teams);
+    }
+}
+
+/// Puts the user on call for the schedule between the two Unix timestamps.
+pub async fn add_override(schedule_id: &str, user_id: &str, start: i64, end: i64) -> Result {
+    let opsgenie_key = opsgenie_key();
+    let client = client();
+    let override_response = client
+        .send(
+            "schedules",
+            false,
+            client
+                .http()
+                .post(format!(
+                    "{}/v2/schedules/{}/overrides",
+                    opsgenie_api_url(),
+                    schedule_id
+                ))
+                .header(AUTHORIZATION, format!("GenieKey {}", opsgenie_key))
+                .json(&CreateOverrideRequest {
+                    user: Responder {
+                        id: user_id,
+                        typ: "user",
+                    },
+                    start_date: oncall_provider::format_time(start),
+                    end_date: oncall_provider::format_time(end),
+                }),
+        )
+        .await?;
+
+    match override_response.status() {
+        reqwest::StatusCode::CREATED | reqwest::StatusCode::OK => Ok(()),
+        code => Err(Error::HttpErrorCode(code)),
+    }
+}
+
+/// Counts the open alerts of the team owning the schedule, or of the schedule itself if it has no
+/// owner team. These are the alerts [`create_alert`] would add to.
+pub async fn count_open_alerts(schedule_id: &str) -> Result<usize> {
+    let opsgenie_key = opsgenie_key();
+    let client = client();
+    let schedule = get_schedule(schedule_id).await?;
+    let responder = match &schedule.owner_team {
+        Some(team) => &team.name,
+        None => &schedule.name,
+    };
+    let query = format!("status: open AND responders: \"{}\"", responder);
+
+    let count_response = client
+        .send(
+            "alerts",
+            true,
+            client
+                .http()
+                .get(format!("{}/v2/alerts/count", opsgenie_api_url()))
+                .query(&[("query", &query[..])])
+                .header(AUTHORIZATION, format!("GenieKey {}", opsgenie_key)),
+        )
+        .await?;
+    match count_response.status() {
+        reqwest::StatusCode::OK => Ok(count_response
+            .json::<AlertCountResponse>()
+            .await?
+            .data
+            .count),
+        code => Err(Error::HttpErrorCode(code)),
+    }
+}
+
+/// Opens an alert assigned to the team owning the schedule, or to the schedule itself if it has no
+/// owner team.
+pub async fn create_alert(schedule_id: &str, message: &str, description: &str) -> Result<Alert> {
+    let opsgenie_key = opsgenie_key();
+    let client = client();
+    let schedule = get_schedule(schedule_id).await?;
+    let responder = match &schedule.owner_team {
+        Some(team) => Responder {
+            id: &team.id,
+            typ: "team",
+        },
+        None => Responder {
+            id: &schedule.id,
+            typ: "schedule",
+        },
+    };
+
+    let alert_response = client
+        .send(
+            "alerts",
+            false,
+            client
+                .http()
+                .post(format!("{}/v2/alerts", opsgenie_api_url()))
+                .header(AUTHORIZATION, format!("GenieKey {}", opsgenie_key))
+                .json(&CreateAlertRequest {
+                    message,
+                    description,
+                    responders: vec![responder],
+                }),
+        )
+        .await?;
+    let request_id = match alert_response.status() {
+        reqwest::StatusCode::ACCEPTED | reqwest::StatusCode::OK => {
+            alert_response
+                .json::<CreateAlertResponse>()
+                .await?
+                .request_id
+        }
+        code => return Err(Error::HttpErrorCode(code)),
+    };
+
+    for _ in 0..ALERT_REQUEST_ATTEMPTS {
+        tokio::time::sleep(ALERT_REQUEST_POLL_INTERVAL).await;
+        let status_response = client
+            .send(
+                "alerts",
+                true,
+                client
+                    .http()
+                    .get(format!(
+                        "{}/v2/alerts/requests/{}",
+                        opsgenie_api_url(),
+                        request_id
+                    ))
+                    .header(AUTHORIZATION, format!("GenieKey {}", opsgenie_key)),
+            )
+            .await?;
+        match status_response.status() {
+            reqwest::StatusCode::OK => {
+                if let Some(alert_id) = status_response
+                    .json::<AlertRequestStatusResponse>()
+                    .await?
+                    .data
+                    .alert_id
+                {
+                    return Ok(Alert {
+                        url: format!("{}/alert/detail/{}/details", opsgenie_app_url(), alert_id),
+                        id: alert_id,
+                    });
+                }
+            }
+            // The request hasn't been processed yet
+            reqwest::StatusCode::NOT_FOUND => {}
+            code => return Err(Error::HttpErrorCode(code)),
+        }
+    }
+
+    Err(Error::AlertRequestNotProcessed(request_id))
+}
+
+/// [`OncallProvider`] implementation backed by the free functions in this module.
+pub struct Opsgenie;
+
+impl OncallProvider for Opsgenie {
+    fn kind(&self) -> OncallProviderKind {
+        OncallProviderKind::Opsgenie
+    }
+
+    fn is_configured(&self) -> bool {
+        env::var("OPSGENIE_API_KEY").is_ok()
+    }
+
+    fn list_oncalls(&self) -> BoxFuture<'_, oncall_provider::Result<Vec<oncall_provider::Oncall>>> {
+        Box::pin(async move {
+            Ok(list_oncalls()
+                .await?
+                .into_iter()
+                .map(|oncall| oncall_provider::Oncall {
+                    id: oncall.id,
+                    name: oncall.name,
+                    provider: OncallProviderKind::Opsgenie,
+                })
+                .collect())
+        })
+    }
+
+    fn get_oncall_name<'a>(
+        &'a self,
+        id: &'a str,
+    ) -> BoxFuture<'a, oncall_provider::Result<String>> {
+        Box::pin(async move { Ok(get_oncall_name(id).await?) })
+    }
+
+    fn list_users(&self) -> BoxFuture<'_, oncall_provider::Result<Vec<oncall_provider::User>>> {
+        Box::pin(async move { Ok(list_users().await?.into_iter().map(User::into).collect()) })
+    }
+
+    fn get_user<'a>(
+        &'a self,
+        id: &'a str,
+    ) -> BoxFuture<'a, oncall_provider::Result<oncall_provider::User>> {
+        Box::pin(async move { Ok(get_user(id).await?.into()) })
+    }
+
+    fn get_current_oncalls<'a>(
+        &'a self,
+        oncall_id: &'a str,
+    ) -> BoxFuture<'a, oncall_provider::Result<Vec<OncallMember>>> {
+        Box::pin(async move { Ok(get_current_oncalls(oncall_id).await?) })
+    }
+
+    fn get_next_oncalls<'a>(
+        &'a self,
+        oncall_id: &'a str,
+    ) -> BoxFuture<'a, oncall_provider::Result<Vec<OncallMember>>> {
+        Box::pin(async move { Ok(get_next_oncalls(oncall_id).await?) })
+    }
+
+    fn get_handoff_time<'a>(
+        &'a self,
+        oncall_id: &'a str,
+    ) -> BoxFuture<'a, oncall_provider::Result<Option<i64>>> {
+        Box::pin(async move { Ok(get_handoff_time(oncall_id).await?) })
+    }
+
+    fn add_override<'a>(
+        &'a self,
+        oncall_id: &'a str,
+        user_id: &'a str,
+        start: i64,
+        end: i64,
+    ) -> BoxFuture<'a, oncall_provider::Result> {
+        Box::pin(async move { Ok(add_override(oncall_id, user_id, start, end).await?) })
+    }
+
+    fn get_upcoming_shifts<'a>(
+        &'a self,
+        oncall_id: &'a str,
+        until: i64,
+    ) -> BoxFuture<'a, oncall_provider::Result<Vec<UpcomingShift>>> {
+        Box::pin(async move { Ok(get_upcoming_shifts(oncall_id, until).await?) })
+    }
+
+    fn count_open_alerts<'a>(
+        &'a self,
+        oncall_id: &'a str,
+    ) -> BoxFuture<'a, oncall_provider::Result<Option<usize>>> {
+        Box::pin(async move { Ok(Some(count_open_alerts(oncall_id).await?)) })
+    }
+
+    fn schedule_url(&self, oncall_id: &str) -> Option<String> {
+        Some(format!(
+            "{}/settings/schedule/detail/{}",
+            opsgenie_app_url(),
+            oncall_id
+        ))
+    }
+}
+
+impl From<User> for oncall_provider::User {
+    fn from(user: User) -> oncall_provider::User {
+        oncall_provider::User {
+            id: user.id,
+            username: user.username,
+            full_name: user.full_name,
+            provider: OncallProviderKind::Opsgenie,
+        }
+    }
+}
+
+/// Webhooks are only accepted when `OPSGENIE_WEBHOOK_SECRET` is set to a non-empty value.
+pub fn webhooks_enabled() -> bool {
+    webhook_secret().is_some()
+}
+
+/// Checks the secret sent along with a webhook against `OPSGENIE_WEBHOOK_SECRET`.
+pub fn verify_webhook_secret(secret: &str) -> bool {
+    match webhook_secret() {
+        Some(expected) => bool::from(expected.as_bytes().ct_eq(secret.as_bytes())),
+        None => false,
+    }
+}
+
+/// An empty secret counts as unset, otherwise an empty one sent along would match it.
+fn webhook_secret() -> Option<String> {
+    env::var("OPSGENIE_WEBHOOK_SECRET")
+        .ok()
+        .filter(|secret| !secret.is_empty())
+}
+
+/// OpsGenie counts requests separately for each API (schedules, users, alerts, ...), so each one
+/// gets its own bucket with the per-minute quota.
+fn client() -> &'static ApiClient {
+    CLIENT.get_or_init(|| {
+        let requests_per_minute = env::var("OPSGENIE_REQUESTS_PER_MINUTE")
+            .map(|requests| {
+                requests
+                    .parse()
+                    .expect("unable to parse OPSGENIE_REQUESTS_PER_MINUTE to a number")
+            })
+            .unwrap_or(DEFAULT_REQUESTS_PER_MINUTE);
+        let limit = RateLimit::per_minute(requests_per_minute);
+        ApiClient::new(
+            "opsgenie",
+            limit,
+            &[
+                ("schedules", limit),
+                ("users", limit),
+                ("teams", limit),
+                ("alerts", limit),
+            ],
+        )
+    })
+}
+
+/// Base URL of the REST API, without a trailing slash. Can be overridden with `OPSGENIE_API_URL`,
+/// e.g. `https://api.eu.opsgenie.com` for EU accounts or a local fake.
+fn opsgenie_api_url() -> String {
+    env::var("OPSGENIE_API_URL")
+        .map(|url| url.trim_end_matches('/').to_string())
+        .unwrap_or_else(|_| "https://api.opsgenie.com".into())
+}
+
+fn opsgenie_app_url() -> String {
+    env::var("OPSGENIE_APP_URL").unwrap_or_else(|_| "https://app.opsgenie.com".into())
+}
+
 fn opsgenie_key() -> String {
     env::var("OPSGENIE_API_KEY").expect("OPSGENIE_API_KEY must be set")
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Tests run in parallel and share the environment, so the secret is only changed in one test
+    #[test]
+    fn webhook_secret_must_be_set_and_match() {
+        env::remove_var("OPSGENIE_WEBHOOK_SECRET");
+        assert!(!webhooks_enabled());
+        assert!(!verify_webhook_secret(""));
+        assert!(!verify_webhook_secret("hunter2"));
+
+        env::set_var("OPSGENIE_WEBHOOK_SECRET", "");
+        assert!(!webhooks_enabled());
+        assert!(!verify_webhook_secret(""));
+
+        env::set_var("OPSGENIE_WEBHOOK_SECRET", "hunter2");
+        assert!(webhooks_enabled());
+        assert!(verify_webhook_secret("hunter2"));
+        assert!(!verify_webhook_secret(""));
+        assert!(!verify_webhook_secret("hunter3"));
+    }
+}
@@ -0,0 +1,389 @@
+use crate::oncall_provider::{self, OncallMember, OncallProvider, OncallProviderKind};
+use futures::future::BoxFuture;
+use reqwest::{
+    header::{ACCEPT, AUTHORIZATION},
+    RequestBuilder, StatusCode,
+};
+use serde::{Deserialize, Serialize};
+use std::env;
+
+const PAGE_LIMIT: usize = 100;
+
+#[derive(thiserror::Error, Debug)]
+pub enum Error {
+    #[error("error making request")]
+    RequestError(#[from] reqwest::Error),
+    #[error("got HTTP {0}")]
+    HttpErrorCode(StatusCode),
+}
+
+pub type Result<T = (), E = Error> = std::result::Result<T, E>;
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Schedule {
+    pub id: String,
+    pub name: String,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct User {
+    pub id: String,
+    pub name: String,
+    pub email: String,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct UserReference {
+    pub id: String,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct Oncall {
+    pub user: UserReference,
+    /// Shift boundaries. These are null for users that are permanently on call.
+    pub start: Option<String>,
+    pub end: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct OverrideUser<'a> {
+    pub id: &'a str,
+    #[serde(rename = "type")]
+    pub typ: &'a str,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct Override<'a> {
+    pub start: String,
+    pub end: String,
+    #[serde(borrow)]
+    pub user: OverrideUser<'a>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct CreateOverridesRequest<'a> {
+    #[serde(borrow)]
+    pub overrides: Vec<Override<'a>>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct ListSchedulesResponse {
+    pub schedules: Vec<Schedule>,
+    pub more: bool,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct GetScheduleResponse {
+    pub schedule: Schedule,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct ListUsersResponse {
+    pub users: Vec<User>,
+    pub more: bool,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct GetUserResponse {
+    pub user: User,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct ListOncallsResponse {
+    pub oncalls: Vec<Oncall>,
+}
+
+pub async fn list_schedules() -> Result<Vec<Schedule>> {
+    let client = reqwest::Client::new();
+    let mut schedules = vec![];
+
+    loop {
+        let offset = schedules.len().to_string();
+        let limit = PAGE_LIMIT.to_string();
+        let schedules_response = authorized(
+            client
+                .get(format!("{}/schedules", pagerduty_api_url()))
+                .query(&[("offset", &offset[..]), ("limit", &limit[..])]),
+        )
+        .send()
+        .await?;
+
+        let mut page = match schedules_response.status() {
+            reqwest::StatusCode::OK => schedules_response.json::<ListSchedulesResponse>().await?,
+            code => return Err(Error::HttpErrorCode(code)),
+        };
+        let page_len = page.schedules.len();
+        schedules.append(&mut page.schedules);
+        if !page.more || page_len == 0 {
+            break;
+        }
+    }
+
+    Ok(schedules)
+}
+
+pub async fn get_schedule_name(id: &str) -> Result<String> {
+    let client = reqwest::Client::new();
+    let schedule_response =
+        authorized(client.get(format!("{}/schedules/{}", pagerduty_api_url(), id)))
+            .send()
+            .await?;
+
+    match schedule_response.status() {
+        reqwest::StatusCode::OK => Ok(schedule_response
+            .json::<GetScheduleResponse>()
+            .await?
+            .schedule
+            .name),
+        code => Err(Error::HttpErrorCode(code)),
+    }
+}
+
+pub async fn list_users() -> Result<Vec<User>> {
+    let client = reqwest::Client::new();
+    let mut users = vec![];
+
+    loop {
+        let offset = users.len().to_string();
+        let limit = PAGE_LIMIT.to_string();
+        let users_response = authorized(
+            client
+                .get(format!("{}/users", pagerduty_api_url()))
+                .query(&[("offset", &offset[..]), ("limit", &limit[..])]),
+        )
+        .send()
+        .await?;
+
+        let mut page = match users_response.status() {
+            reqwest::StatusCode::OK => users_response.json::<ListUsersResponse>().await?,
+            code => return Err(Error::HttpErrorCode(code)),
+        };
+        let page_len = page.users.len();
+        users.append(&mut page.users);
+        if !page.more || page_len == 0 {
+            break;
+        }
+    }
+
+    Ok(users)
+}
+
+pub async fn get_user(id: &str) -> Result<User> {
+    let client = reqwest::Client::new();
+    let user_response = authorized(client.get(format!("{}/users/{}", pagerduty_api_url(), id)))
+        .send()
+        .await?;
+
+    match user_response.status() {
+        reqwest::StatusCode::OK => Ok(user_response.json::<GetUserResponse>().await?.user),
+        code => Err(Error::HttpErrorCode(code)),
+    }
+}
+
+pub async fn get_current_oncalls(schedule_id: &str) -> Result<Vec<String>> {
+    Ok(dedup_users(list_schedule_oncalls(schedule_id, None).await?))
+}
+
+/// The next shift starts when the first of the current ones ends.
+async fn get_current_shift_end(schedule_id: &str) -> Result<Option<String>> {
+    Ok(list_schedule_oncalls(schedule_id, None)
+        .await?
+        .into_iter()
+        .filter_map(|oncall| oncall.end)
+        .min())
+}
+
+pub async fn get_handoff_time(schedule_id: &str) -> Result<Option<i64>> {
+    Ok(get_current_shift_end(schedule_id)
+        .await?
+        .and_then(|end| oncall_provider::parse_time(&end)))
+}
+
+pub async fn get_next_oncalls(schedule_id: &str) -> Result<Vec<String>> {
+    let current_end = match get_current_shift_end(schedule_id).await? {
+        Some(end) => end,
+        None => return Ok(vec![]),
+    };
+
+    let next_oncalls = list_schedule_oncalls(schedule_id, Some(&current_end))
+        .await?
+        .into_iter()
+        .filter(|oncall| oncall.start.as_ref() == Some(&current_end))
+        .collect();
+    Ok(dedup_users(next_oncalls))
+}
+
+pub async fn add_override(schedule_id: &str, user_id: &str, start: i64, end: i64) -> Result {
+    let client = reqwest::Client::new();
+    let override_response = authorized(client.post(format!(
+        "{}/schedules/{}/overrides",
+        pagerduty_api_url(),
+        schedule_id
+    )))
+    .json(&CreateOverridesRequest {
+        overrides: vec![Override {
+            start: oncall_provider::format_time(start),
+            end: oncall_provider::format_time(end),
+            user: OverrideUser {
+                id: user_id,
+                typ: "user_reference",
+            },
+        }],
+    })
+    .send()
+    .await?;
+
+    match override_response.status() {
+        StatusCode::CREATED | StatusCode::OK => Ok(()),
+        code => Err(Error::HttpErrorCode(code)),
+    }
+}
+
+/// Lists the oncall entries for the schedule, either right now or at the given time. Times are
+/// always in UTC so that they can be compared as strings.
+async fn list_schedule_oncalls(schedule_id: &str, at: Option<&str>) -> Result<Vec<Oncall>> {
+    let client = reqwest::Client::new();
+    let mut request = client
+        .get(format!("{}/oncalls", pagerduty_api_url()))
+        .query(&[("schedule_ids[]", schedule_id), ("time_zone", "UTC")]);
+    if let Some(at) = at {
+        request = request.query(&[("since", at), ("until", at)]);
+    }
+    let oncalls_response = authorized(request).send().await?;
+
+    match oncalls_response.status() {
+        reqwest::StatusCode::OK => Ok(oncalls_response
+            .json::<ListOncallsResponse>()
+            .await?
+            .oncalls),
+        code => Err(Error::HttpErrorCode(code)),
+    }
+}
+
+/// The same user can show up once per escalation level, so deduplicate while keeping order
+fn dedup_users(oncalls: Vec<Oncall>) -> Vec<String> {
+    let mut user_ids: Vec<String> = vec![];
+    for oncall in oncalls {
+        if !user_ids.contains(&oncall.user.id) {
+            user_ids.push(oncall.user.id);
+        }
+    }
+    user_ids
+}
+
+/// [`OncallProvider`] implementation backed by the free functions in this module.
+pub struct Pagerduty;
+
+impl OncallProvider for Pagerduty {
+    fn kind(&self) -> OncallProviderKind {
+        OncallProviderKind::Pagerduty
+    }
+
+    fn is_configured(&self) -> bool {
+        env::var("PAGERDUTY_API_KEY").is_ok()
+    }
+
+    fn list_oncalls(&self) -> BoxFuture<'_, oncall_provider::Result<Vec<oncall_provider::Oncall>>> {
+        Box::pin(async move {
+            Ok(list_schedules()
+                .await?
+                .into_iter()
+                .map(|schedule| oncall_provider::Oncall {
+                    id: schedule.id,
+                    name: schedule.name,
+                    provider: OncallProviderKind::Pagerduty,
+                })
+                .collect())
+        })
+    }
+
+    fn get_oncall_name<'a>(
+        &'a self,
+        id: &'a str,
+    ) -> BoxFuture<'a, oncall_provider::Result<String>> {
+        Box::pin(async move { Ok(get_schedule_name(id).await?) })
+    }
+
+    fn list_users(&self) -> BoxFuture<'_, oncall_provider::Result<Vec<oncall_provider::User>>> {
+        Box::pin(async move { Ok(list_users().await?.into_iter().map(User::into).collect()) })
+    }
+
+    fn get_user<'a>(
+        &'a self,
+        id: &'a str,
+    ) -> BoxFuture<'a, oncall_provider::Result<oncall_provider::User>> {
+        Box::pin(async move { Ok(get_user(id).await?.into()) })
+    }
+
+    fn get_current_oncalls<'a>(
+        &'a self,
+        oncall_id: &'a str,
+    ) -> BoxFuture<'a, oncall_provider::Result<Vec<OncallMember>>> {
+        Box::pin(async move {
+            Ok(get_current_oncalls(oncall_id)
+                .await?
+                .into_iter()
+                .map(OncallMember::user)
+                .collect())
+        })
+    }
+
+    fn get_next_oncalls<'a>(
+        &'a self,
+        oncall_id: &'a str,
+    ) -> BoxFuture<'a, oncall_provider::Result<Vec<OncallMember>>> {
+        Box::pin(async move {
+            Ok(get_next_oncalls(oncall_id)
+                .await?
+                .into_iter()
+                .map(OncallMember::user)
+                .collect())
+        })
+    }
+
+    fn get_handoff_time<'a>(
+        &'a self,
+        oncall_id: &'a str,
+    ) -> BoxFuture<'a, oncall_provider::Result<Option<i64>>> {
+        Box::pin(async move { Ok(get_handoff_time(oncall_id).await?) })
+    }
+
+    fn add_override<'a>(
+        &'a self,
+        oncall_id: &'a str,
+        user_id: &'a str,
+        start: i64,
+        end: i64,
+    ) -> BoxFuture<'a, oncall_provider::Result> {
+        Box::pin(async move { Ok(add_override(oncall_id, user_id, start, end).await?) })
+    }
+}
+
+impl From<User> for oncall_provider::User {
+    fn from(user: User) -> oncall_provider::User {
+        oncall_provider::User {
+            id: user.id,
+            username: user.email,
+            full_name: user.name,
+            provider: OncallProviderKind::Pagerduty,
+        }
+    }
+}
+
+fn authorized(request: RequestBuilder) -> RequestBuilder {
+    request
+        .header(AUTHORIZATION, format!("Token token={}", pagerduty_key()))
+        .header(ACCEPT, "application/vnd.pagerduty+json;version=2")
+}
+
+/// Base URL of the REST API, without a trailing slash. Can be overridden with `PAGERDUTY_API_URL`,
+/// e.g. to use the EU service region or a local fake.
+fn pagerduty_api_url() -> String {
+    env::var("PAGERDUTY_API_URL")
+        .map(|url| url.trim_end_matches('/').to_string())
+        .unwrap_or_else(|_| "https://api.pagerduty.com".into())
+}
+
+fn pagerduty_key() -> String {
+    env::var("PAGERDUTY_API_KEY").expect("PAGERDUTY_API_KEY must be set")
+}
@@ -0,0 +1,78 @@
+use crate::{chat_provider::ChatProviderKind, oncall_provider::OncallProviderKind};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use tokio::sync::Mutex;
+
+/// What a dry-run sync would have done to its user group on the last reconcile.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct UserGroupPreview {
+    pub oncall_sync_id: i32,
+    pub oncall_provider: OncallProviderKind,
+    pub oncall_id: String,
+    pub chat_provider: ChatProviderKind,
+    pub user_group_id: String,
+    /// None if the current members couldn't be fetched
+    pub current_users: Option<Vec<String>>,
+    pub new_users: Vec<String>,
+    pub computed_at: i64,
+}
+
+/// What a dry-run notification would have done to its channel on the last reconcile.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ChannelPreview {
+    pub notification_id: i32,
+    pub oncall_provider: OncallProviderKind,
+    pub oncall_id: String,
+    pub chat_provider: ChatProviderKind,
+    pub slack_channel_id: String,
+    pub current_topic: String,
+    pub new_topic: String,
+    /// Message that would be posted. None if the topic is already up to date.
+    pub message: Option<String>,
+    pub computed_at: i64,
+}
+
+/// Latest preview of every sync and notification in dry-run mode, keyed by their DB ID.
+pub struct Previews {
+    user_groups: Mutex<HashMap<i32, UserGroupPreview>>,
+    channels: Mutex<HashMap<i32, ChannelPreview>>,
+}
+
+impl Previews {
+    pub fn new() -> Previews {
+        Previews {
+            user_groups: Mutex::new(HashMap::new()),
+            channels: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub async fn set_user_group(&self, preview: UserGroupPreview) {
+        self.user_groups
+            .lock()
+            .await
+            .insert(preview.oncall_sync_id, preview);
+    }
+
+    pub async fn set_channel(&self, preview: ChannelPreview) {
+        self.channels
+            .lock()
+            .await
+            .insert(preview.notification_id, preview);
+    }
+
+    pub async fn remove_user_group(&self, oncall_sync_id: i32) {
+        self.user_groups.lock().await.remove(&oncall_sync_id);
+    }
+
+    pub async fn remove_channel(&self, notification_id: i32) {
+        self.channels.lock().await.remove(&notification_id);
+    }
+
+    pub async fn user_groups(&self) -> Vec<UserGroupPreview> {
+        self.user_groups.lock().await.values().cloned().collect()
+    }
+
+    pub async fn channels(&self) -> Vec<ChannelPreview> {
+        self.channels.lock().await.values().cloned().collect()
+    }
+}
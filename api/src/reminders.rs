@@ -0,0 +1,215 @@
+//! DMs reminding users of their upcoming shifts, e.g. a day and an hour before, set up with
+//! `SHIFT_REMINDERS`. Reminders are queued in the DB so that they survive restarts and are only
+//! sent once. Users opted out of shift DMs don't get them either.
+
+use crate::{
+    audit,
+    chat_provider::ChatProviderKind,
+    db,
+    models::{NewShiftReminder, ShiftReminder},
+    oncall_provider::OncallProviderKind,
+    scheduler::ScheduleKey,
+    shift_dms,
+};
+use futures::future::join_all;
+use log::{info, warn};
+use std::{collections::HashMap, time::Duration};
+
+/// Shifts are looked up this far beyond the longest lead time, so that reminders are queued
+/// before they're due rather than found once it's too late.
+const PLANNING_MARGIN_SECS: i64 = 60 * 60;
+
+/// Queues reminders for the shifts coming up in the schedules, then sends the reminders that are
+/// due. Each schedule comes with the chat workspaces it's live in, where its reminders are sent.
+pub async fn process(schedules: &[(ScheduleKey, Vec<ChatProviderKind>)], lead_times: &[Duration]) {
+    let now = audit::now();
+    join_all(
+        schedules
+            .iter()
+            .map(|(schedule, chat_providers)| plan(schedule, chat_providers, lead_times, now)),
+    )
+    .await;
+    send_due(schedules, now).await;
+}
+
+async fn plan(
+    schedule: &ScheduleKey,
+    chat_providers: &[ChatProviderKind],
+    lead_times: &[Duration],
+    now: i64,
+) {
+    let horizon = lead_times
+        .iter()
+        .max()
+        .map(|lead_time| lead_time.as_secs() as i64)
+        .unwrap_or_default()
+        + PLANNING_MARGIN_SECS;
+    let shifts = match schedule
+        .oncall_provider
+        .provider()
+        .get_upcoming_shifts(&schedule.oncall_id, now + horizon)
+        .await
+    {
+        Err(e) => {
+            warn!(
+                "Error fetching upcoming shifts of {} oncall {}: {}",
+                schedule.oncall_provider, schedule.oncall_id, e
+            );
+            return;
+        }
+        Ok(shifts) => shifts,
+    };
+
+    let mut planned = vec![];
+    for shift in shifts.iter() {
+        for &chat_provider in chat_providers {
+            for lead_time in lead_times {
+                planned.push(NewShiftReminder {
+                    oncall_provider: schedule.oncall_provider,
+                    oncall_id: schedule.oncall_id.clone(),
+                    chat_provider,
+                    user_id: shift.user_id.clone(),
+                    shift_start: shift.start,
+                    is_override: shift.is_override,
+                    remind_at: shift.start - lead_time.as_secs() as i64,
+                });
+            }
+        }
+    }
+    let schedule_clone = schedule.clone();
+    let result = tokio::task::spawn_blocking(move || {
+        let connection = db::connection();
+        db::plan_shift_reminders(
+            &connection,
+            schedule_clone.oncall_provider,
+            &schedule_clone.oncall_id,
+            &planned,
+            now,
+        )
+    })
+    .await;
+    match result {
+        Err(e) => warn!("Error queueing shift reminders for {:?}: {}", schedule, e),
+        Ok(Err(e)) => warn!("Error queueing shift reminders for {:?}: {}", schedule, e),
+        Ok(Ok(0)) => {}
+        Ok(Ok(queued)) => info!("Queued {} shift reminders for {:?}", queued, schedule),
+    }
+}
+
+async fn send_due(schedules: &[(ScheduleKey, Vec<ChatProviderKind>)], now: i64) {
+    let due = match tokio::task::spawn_blocking(move || {
+        let connection = db::connection();
+        db::claim_due_shift_reminders(&connection, now)
+    })
+    .await
+    {
+        Err(e) => {
+            warn!("Error fetching due shift reminders: {}", e);
+            return;
+        }
+        Ok(Err(e)) => {
+            warn!("Error fetching due shift reminders: {}", e);
+            return;
+        }
+        Ok(Ok(due)) => due,
+    };
+
+    let mut names: HashMap<ScheduleKey, String> = HashMap::new();
+    for reminder in due {
+        let schedule = ScheduleKey {
+            oncall_provider: reminder.oncall_provider,
+            oncall_id: reminder.oncall_id.clone(),
+        };
+        // Reminders of schedules that stopped being synced or notified are dropped
+        let is_live = schedules.iter().any(|(live, chat_providers)| {
+            *live == schedule && chat_providers.contains(&reminder.chat_provider)
+        });
+        if !is_live || reminder.shift_start <= now {
+            continue;
+        }
+        let slack_id = match recipient(reminder.oncall_provider, &reminder.user_id).await {
+            Some(slack_id) => slack_id,
+            None => continue,
+        };
+        if !names.contains_key(&schedule) {
+            let name = match schedule
+                .oncall_provider
+                .provider()
+                .get_oncall_name(&schedule.oncall_id)
+                .await
+            {
+                Err(e) => {
+                    warn!(
+                        "Error fetching name of {} oncall {}: {}",
+                        schedule.oncall_provider, schedule.oncall_id, e
+                    );
+                    schedule.oncall_id.clone()
+                }
+                Ok(name) => name,
+            };
+            names.insert(schedule.clone(), name);
+        }
+
+        let message = reminder_message(&reminder, &names[&schedule], now);
+        let chat = reminder.chat_provider.provider();
+        match chat.post_direct_message(&slack_id, &message).await {
+            Err(e) => {
+                warn!(
+                    "Error sending shift reminder to {} user {}: {}",
+                    reminder.chat_provider, slack_id, e
+                );
+                // Try again on the next run
+                let reminder_id = reminder.id;
+                let _ = tokio::task::spawn_blocking(move || {
+                    let connection = db::connection();
+                    db::release_shift_reminder(&connection, reminder_id)
+                })
+                .await;
+            }
+            Ok(()) => info!(
+                "Sent shift reminder for {} oncall {} to {} user {}",
+                reminder.oncall_provider, reminder.oncall_id, reminder.chat_provider, slack_id
+            ),
+        }
+    }
+}
+
+/// Chat user ID of the oncall provider user, unless they aren't mapped or opted out.
+async fn recipient(oncall_provider: OncallProviderKind, user_id: &str) -> Option<String> {
+    let user_id = user_id.to_string();
+    match tokio::task::spawn_blocking(move || {
+        let connection = db::connection();
+        db::get_opsgenie_user_mapping(&connection, oncall_provider, &user_id)
+    })
+    .await
+    {
+        Err(e) => warn!("Error fetching user mapping: {}", e),
+        Ok(Err(e)) => warn!("Error fetching user mapping: {}", e),
+        Ok(Ok(mapping)) => {
+            return mapping
+                .filter(|mapping| !mapping.shift_dms_opt_out)
+                .map(|mapping| mapping.slack_id)
+        }
+    }
+    None
+}
+
+fn reminder_message(reminder: &ShiftReminder, schedule_name: &str, now: i64) -> String {
+    let message = format!(
+        "Heads up: your shift on *{}* starts in {}, at {}.",
+        schedule_name,
+        shift_dms::format_duration(reminder.shift_start - now),
+        reminder
+            .chat_provider
+            .provider()
+            .format_time(reminder.shift_start)
+    );
+    if reminder.is_override {
+        format!(
+            "{} It's an override, not part of the regular rotation.",
+            message
+        )
+    } else {
+        message
+    }
+}
@@ -0,0 +1,200 @@
+use crate::{models::Shift, oncall_provider::OncallProviderKind};
+use log::warn;
+use serde::{Deserialize, Serialize};
+use std::{collections::HashMap, env, sync::OnceLock};
+
+const SECS_PER_HOUR: i64 = 60 * 60;
+const SECS_PER_DAY: i64 = 24 * SECS_PER_HOUR;
+/// Hours from `NIGHT_START_HOUR` until `NIGHT_END_HOUR` (local time) count as night hours.
+const NIGHT_START_HOUR: i64 = 22;
+const NIGHT_END_HOUR: i64 = 7;
+
+/// Time on call for one user in one schedule.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ShiftSummary {
+    pub oncall_provider: OncallProviderKind,
+    pub oncall_id: String,
+    pub user_id: String,
+    pub shifts: usize,
+    pub hours: f64,
+    /// Hours on Saturdays and Sundays
+    pub weekend_hours: f64,
+    /// Hours between 22:00 and 07:00. Weekend nights count as both weekend and night hours.
+    pub night_hours: f64,
+}
+
+#[derive(Default)]
+struct Seconds {
+    total: i64,
+    weekend: i64,
+    night: i64,
+}
+
+/// Adds up the time each user spent on call in every schedule within `[since, until)`. Shifts that
+/// are still going on are counted up to `now`.
+pub fn summarize_shifts(shifts: &[Shift], since: i64, until: i64, now: i64) -> Vec<ShiftSummary> {
+    summarize(shifts, since, until, now, utc_offset_secs())
+}
+
+fn summarize(
+    shifts: &[Shift],
+    since: i64,
+    until: i64,
+    now: i64,
+    utc_offset: i64,
+) -> Vec<ShiftSummary> {
+    let mut summaries: HashMap<(OncallProviderKind, &str, &str), (usize, Seconds)> = HashMap::new();
+    for shift in shifts {
+        let start = shift.started_at.max(since);
+        let end = shift.ended_at.unwrap_or(now).min(until);
+        if start >= end {
+            continue;
+        }
+
+        let (count, seconds) = summaries
+            .entry((shift.oncall_provider, &shift.oncall_id, &shift.user_id))
+            .or_default();
+        *count += 1;
+        add_seconds(seconds, start + utc_offset, end + utc_offset);
+    }
+
+    let mut summaries: Vec<ShiftSummary> = summaries
+        .into_iter()
+        .map(
+            |((oncall_provider, oncall_id, user_id), (shifts, seconds))| ShiftSummary {
+                oncall_provider,
+                oncall_id: oncall_id.to_string(),
+                user_id: user_id.to_string(),
+                shifts,
+                hours: to_hours(seconds.total),
+                weekend_hours: to_hours(seconds.weekend),
+                night_hours: to_hours(seconds.night),
+            },
+        )
+        .collect();
+    summaries.sort_by(|a, b| (&a.oncall_id, &a.user_id).cmp(&(&b.oncall_id, &b.user_id)));
+    summaries
+}
+
+/// Splits the (local) time range at every hour boundary so each piece can be classified.
+fn add_seconds(seconds: &mut Seconds, start: i64, end: i64) {
+    let mut current = start;
+    while current < end {
+        let next = ((current.div_euclid(SECS_PER_HOUR) + 1) * SECS_PER_HOUR).min(end);
+        let duration = next - current;
+        let hour = current.rem_euclid(SECS_PER_DAY) / SECS_PER_HOUR;
+        // The epoch was a Thursday, so day 2 is a Saturday and day 3 a Sunday
+        let weekday = current.div_euclid(SECS_PER_DAY).rem_euclid(7);
+
+        seconds.total += duration;
+        if weekday == 2 || weekday == 3 {
+            seconds.weekend += duration;
+        }
+        if !(NIGHT_END_HOUR..NIGHT_START_HOUR).contains(&hour) {
+            seconds.night += duration;
+        }
+        current = next;
+    }
+}
+
+fn to_hours(seconds: i64) -> f64 {
+    seconds as f64 / SECS_PER_HOUR as f64
+}
+
+/// Weekends and nights are computed in the timezone given by `REPORT_UTC_OFFSET_HOURS`, UTC by
+/// default or if it isn't a number. Only read once.
+fn utc_offset_secs() -> i64 {
+    static UTC_OFFSET_SECS: OnceLock<i64> = OnceLock::new();
+    *UTC_OFFSET_SECS.get_or_init(|| {
+        let hours = match env::var("REPORT_UTC_OFFSET_HOURS") {
+            Ok(hours) => hours.parse::<i64>().unwrap_or_else(|_| {
+                warn!(
+                    "Unable to parse REPORT_UTC_OFFSET_HOURS \"{}\" to a number, using UTC",
+                    hours
+                );
+                0
+            }),
+            Err(_) => 0,
+        };
+        hours * SECS_PER_HOUR
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Friday 2026-10-16 21:00 UTC
+    const FRIDAY_21: i64 = 1792184400;
+    /// Saturday 2026-10-17 08:00 UTC
+    const SATURDAY_08: i64 = 1792224000;
+    /// Wednesday 2026-10-14 10:00 UTC
+    const WEDNESDAY_10: i64 = 1791972000;
+
+    fn shift(started_at: i64, ended_at: Option<i64>) -> Shift {
+        Shift {
+            id: 1,
+            oncall_provider: OncallProviderKind::Opsgenie,
+            oncall_id: "schedule".into(),
+            user_id: "user".into(),
+            started_at,
+            ended_at,
+            unmapped_warned_at: None,
+        }
+    }
+
+    /// Total, weekend and night hours of the only summary
+    fn hours(summaries: &[ShiftSummary]) -> (f64, f64, f64) {
+        assert_eq!(summaries.len(), 1, "{:?}", summaries);
+        let summary = &summaries[0];
+        (summary.hours, summary.weekend_hours, summary.night_hours)
+    }
+
+    #[test]
+    fn splits_weekend_and_night_hours() {
+        let shifts = [shift(FRIDAY_21, Some(SATURDAY_08))];
+        let summaries = summarize(&shifts, 0, i64::MAX, SATURDAY_08, 0);
+        // Weekend from Saturday 00:00, night from 22:00 until 07:00
+        assert_eq!(hours(&summaries), (11.0, 8.0, 9.0));
+        assert_eq!(summaries[0].shifts, 1);
+    }
+
+    #[test]
+    fn clips_shifts_to_the_range() {
+        let shifts = [shift(FRIDAY_21, Some(SATURDAY_08))];
+        let since = FRIDAY_21 + 2 * SECS_PER_HOUR;
+        let until = FRIDAY_21 + 5 * SECS_PER_HOUR;
+        let summaries = summarize(&shifts, since, until, SATURDAY_08, 0);
+        assert_eq!(hours(&summaries), (3.0, 2.0, 3.0));
+
+        // Shifts entirely outside of the range are left out
+        let summaries = summarize(&shifts, SATURDAY_08, i64::MAX, SATURDAY_08, 0);
+        assert!(summaries.is_empty(), "{:?}", summaries);
+    }
+
+    #[test]
+    fn counts_open_shifts_up_to_now() {
+        let shifts = [
+            shift(WEDNESDAY_10 - 4 * SECS_PER_HOUR, Some(WEDNESDAY_10)),
+            shift(WEDNESDAY_10, None),
+        ];
+        let now = WEDNESDAY_10 + 5 * SECS_PER_HOUR / 2;
+        let summaries = summarize(&shifts, 0, i64::MAX, now, 0);
+        // The first shift runs from 06:00, so its first hour is a night hour
+        assert_eq!(hours(&summaries), (6.5, 0.0, 1.0));
+        assert_eq!(summaries[0].shifts, 2);
+    }
+
+    #[test]
+    fn applies_the_utc_offset() {
+        let shifts = [shift(FRIDAY_21, Some(SATURDAY_08))];
+
+        // Friday 23:00 until Saturday 10:00 local time
+        let summaries = summarize(&shifts, 0, i64::MAX, SATURDAY_08, 2 * SECS_PER_HOUR);
+        assert_eq!(hours(&summaries), (11.0, 10.0, 8.0));
+
+        // Friday 16:00 until Saturday 03:00 local time
+        let summaries = summarize(&shifts, 0, i64::MAX, SATURDAY_08, -5 * SECS_PER_HOUR);
+        assert_eq!(hours(&summaries), (11.0, 3.0, 5.0));
+    }
+}
@@ -0,0 +1,1078 @@
+use crate::{
+    audit, auto_mapping,
+    chat_provider::{self, ChatProviderKind},
+    db,
+    health::{Failure, Health},
+    notifier::{self, ChannelSchedule, NextOncall, ScheduleInfo},
+    oncall_provider::{OncallMember, OncallProviderKind},
+    opsgenie,
+    preview::Previews,
+    reminders, shift_dms,
+    template::{Placeholder, Templates},
+    unmapped::{self, AdminChannel},
+    user_group_sync,
+};
+use futures::{future::join_all, Future};
+use log::{info, warn};
+use rand::Rng;
+use std::{
+    collections::{HashMap, HashSet},
+    env,
+    sync::Arc,
+    time::Duration,
+};
+use tokio::{
+    join, select,
+    sync::{
+        oneshot::{self, Receiver, Sender},
+        Mutex, Notify,
+    },
+    time::{interval, sleep_until, Instant, MissedTickBehavior},
+};
+
+const DEFAULT_POLL_INTERVAL_SECS: u64 = 60;
+const DEFAULT_POLL_JITTER_SECS: u64 = 5;
+const DEFAULT_WEBHOOK_FALLBACK_POLL_INTERVAL_SECS: u64 = 900;
+const DEFAULT_REMINDER_INTERVAL_SECS: u64 = 60;
+
+/// How often each schedule gets polled.
+#[derive(Debug, Clone)]
+pub struct SchedulerConfig {
+    /// Puts every sync and notification in dry-run mode, regardless of their own flag
+    pub dry_run: bool,
+    /// Sends DMs to the users whose shifts start and end
+    pub shift_dms: bool,
+    /// How long before their shifts users get reminded. No reminders are sent when empty.
+    pub reminder_lead_times: Vec<Duration>,
+    /// How often upcoming shifts are checked for reminders
+    pub reminder_interval: Duration,
+    /// How often users get mapped by email. Only on demand when None.
+    pub auto_map_interval: Option<Duration>,
+    /// Where admins get warned about oncalls without a user mapping
+    pub admin_channel: Option<AdminChannel>,
+    pub default_interval: Duration,
+    pub jitter: Duration,
+    /// Per-provider overrides of `default_interval`. Providers that push changes to us through
+    /// webhooks only need a slow fallback poll.
+    pub provider_intervals: HashMap<OncallProviderKind, Duration>,
+    /// Per-schedule overrides of `default_interval`, keyed by oncall ID
+    pub intervals: HashMap<String, Duration>,
+}
+
+impl SchedulerConfig {
+    /// Reads the config from `POLL_INTERVAL_SECS`, `POLL_JITTER_SECS` and
+    /// `SCHEDULE_POLL_INTERVALS`. The latter is a comma separated list of
+    /// `<oncall ID>=<seconds>` overrides. When OpsGenie webhooks are enabled, OpsGenie schedules
+    /// are polled every `WEBHOOK_FALLBACK_POLL_INTERVAL_SECS` instead. Setting `DRY_RUN` to
+    /// `true` or `1` turns on dry-run mode globally, and `SHIFT_DMS` turns on shift DMs.
+    /// `SHIFT_REMINDERS` is a comma separated list of how long before their shifts users get
+    /// reminded, e.g. `24h,1h`, checked every `REMINDER_INTERVAL_SECS`. Unmapped users are
+    /// mapped by email every `AUTO_MAP_USERS_INTERVAL_SECS` when it's set. See
+    /// [`AdminChannel::from_env`] for the admin channel.
+    pub fn from_env() -> SchedulerConfig {
+        let dry_run = env::var("DRY_RUN")
+            .map(|dry_run| dry_run == "true" || dry_run == "1")
+            .unwrap_or(false);
+        let shift_dms = env::var("SHIFT_DMS")
+            .map(|shift_dms| shift_dms == "true" || shift_dms == "1")
+            .unwrap_or(false);
+        let default_interval = env::var("POLL_INTERVAL_SECS")
+            .map(|secs| {
+                secs.parse()
+                    .expect("unable to parse POLL_INTERVAL_SECS to a number")
+            })
+            .unwrap_or(DEFAULT_POLL_INTERVAL_SECS);
+        let jitter = env::var("POLL_JITTER_SECS")
+            .map(|secs| {
+                secs.parse()
+                    .expect("unable to parse POLL_JITTER_SECS to a number")
+            })
+            .unwrap_or(DEFAULT_POLL_JITTER_SECS);
+        let reminder_lead_times = env::var("SHIFT_REMINDERS")
+            .unwrap_or_default()
+            .split(',')
+            .filter(|entry| !entry.trim().is_empty())
+            .map(|entry| {
+                parse_lead_time(entry.trim()).expect(
+                    "SHIFT_REMINDERS entries must be a number followed by d, h, m or s, e.g. 24h",
+                )
+            })
+            .collect();
+        let reminder_interval = env::var("REMINDER_INTERVAL_SECS")
+            .map(|secs| {
+                secs.parse()
+                    .expect("unable to parse REMINDER_INTERVAL_SECS to a number")
+            })
+            .unwrap_or(DEFAULT_REMINDER_INTERVAL_SECS);
+        let auto_map_interval = env::var("AUTO_MAP_USERS_INTERVAL_SECS").ok().map(|secs| {
+            Duration::from_secs(
+                secs.parse()
+                    .expect("unable to parse AUTO_MAP_USERS_INTERVAL_SECS to a number"),
+            )
+        });
+        let mut provider_intervals = HashMap::new();
+        if opsgenie::webhooks_enabled() {
+            let fallback_interval = env::var("WEBHOOK_FALLBACK_POLL_INTERVAL_SECS")
+                .map(|secs| {
+                    secs.parse()
+                        .expect("unable to parse WEBHOOK_FALLBACK_POLL_INTERVAL_SECS to a number")
+                })
+                .unwrap_or(DEFAULT_WEBHOOK_FALLBACK_POLL_INTERVAL_SECS);
+            provider_intervals.insert(
+                OncallProviderKind::Opsgenie,
+                Duration::from_secs(fallback_interval),
+            );
+        }
+        let intervals = env::var("SCHEDULE_POLL_INTERVALS")
+            .unwrap_or_default()
+            .split(',')
+            .filter(|entry| !entry.trim().is_empty())
+            .map(|entry| {
+                let (oncall_id, secs) = entry
+                    .split_once('=')
+                    .expect("SCHEDULE_POLL_INTERVALS entries must look like <oncall ID>=<seconds>");
+                let secs = secs
+                    .trim()
+                    .parse()
+                    .expect("unable to parse SCHEDULE_POLL_INTERVALS interval to a number");
+                (oncall_id.trim().to_string(), Duration::from_secs(secs))
+            })
+            .collect();
+
+        SchedulerConfig {
+            dry_run,
+            shift_dms,
+            reminder_lead_times,
+            reminder_interval: Duration::from_secs(reminder_interval),
+            auto_map_interval,
+            admin_channel: AdminChannel::from_env(),
+            default_interval: Duration::from_secs(default_interval),
+            jitter: Duration::from_secs(jitter),
+            provider_intervals,
+            intervals,
+        }
+    }
+
+    /// Time until the next poll of the given schedule, including a random jitter so schedules
+    /// added at the same time don't all hit the provider at once.
+    fn next_interval(&self, schedule: &ScheduleKey) -> Duration {
+        let interval = self
+            .intervals
+            .get(&schedule.oncall_id)
+            .or_else(|| self.provider_intervals.get(&schedule.oncall_provider))
+            .cloned()
+            .unwrap_or(self.default_interval);
+        let jitter_ms = self.jitter.as_millis() as u64;
+        if jitter_ms == 0 {
+            interval
+        } else {
+            interval + Duration::from_millis(rand::thread_rng().gen_range(0..=jitter_ms))
+        }
+    }
+}
+
+/// Parses durations like `24h`, `90m` or `1d`.
+fn parse_lead_time(lead_time: &str) -> Option<Duration> {
+    let unit = match lead_time.chars().last()? {
+        'd' => 24 * 60 * 60,
+        'h' => 60 * 60,
+        'm' => 60,
+        's' => 1,
+        _ => return None,
+    };
+    let amount: u64 = lead_time[..lead_time.len() - 1].parse().ok()?;
+    Some(Duration::from_secs(amount * unit))
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct ScheduleKey {
+    pub oncall_provider: OncallProviderKind,
+    pub oncall_id: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct UserGroupTarget {
+    pub oncall_sync_id: i32,
+    pub chat_provider: ChatProviderKind,
+    pub user_group_id: String,
+    pub dry_run: bool,
+}
+
+impl UserGroupTarget {
+    fn same_user_group(&self, other: &UserGroupTarget) -> bool {
+        self.chat_provider == other.chat_provider && self.user_group_id == other.user_group_id
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct ChannelTarget {
+    pub notification_id: i32,
+    pub chat_provider: ChatProviderKind,
+    pub slack_channel_id: String,
+    pub dry_run: bool,
+    pub show_next_oncall: bool,
+    pub templates: Templates,
+    pub rich_messages: bool,
+}
+
+impl ChannelTarget {
+    fn same_channel(&self, other: &ChannelTarget) -> bool {
+        self.chat_provider == other.chat_provider && self.slack_channel_id == other.slack_channel_id
+    }
+
+    /// Whether the next oncall has to be fetched for the channel's topic or announcements. Rich
+    /// announcements show when the current shift ends.
+    fn needs_next_oncall(&self) -> bool {
+        self.show_next_oncall
+            || self.rich_messages
+            || self.templates.uses(Placeholder::Next)
+            || self.templates.uses(Placeholder::Until)
+    }
+}
+
+#[derive(Debug)]
+struct ScheduleEntry {
+    user_groups: HashSet<UserGroupTarget>,
+    channels: HashSet<ChannelTarget>,
+    next_run: Instant,
+}
+
+impl ScheduleEntry {
+    fn new() -> ScheduleEntry {
+        ScheduleEntry {
+            user_groups: HashSet::new(),
+            channels: HashSet::new(),
+            next_run: Instant::now(),
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.user_groups.is_empty() && self.channels.is_empty()
+    }
+}
+
+struct Shared {
+    schedules: Mutex<HashMap<ScheduleKey, ScheduleEntry>>,
+    /// What was last fetched about each schedule, for the channels it shares with others
+    latest: Mutex<HashMap<ScheduleKey, ScheduleInfo>>,
+    /// Held while updating a channel or user group shared by several schedules, so that two of
+    /// them reconciling at once don't both announce the change or overwrite each other
+    shared_targets: Mutex<()>,
+    /// Schedules being reconciled. They aren't picked up again until their reconcile is over, so
+    /// a slow provider never has two reconciles of the same schedule racing each other.
+    in_flight: std::sync::Mutex<HashSet<ScheduleKey>>,
+    wake: Notify,
+    config: SchedulerConfig,
+    previews: Previews,
+    health: Health,
+}
+
+/// Central reconciler for all user group syncs and channel notifications. Every distinct schedule
+/// is polled once per interval and the current oncalls are fanned out to every user group and
+/// channel attached to it.
+pub struct Scheduler {
+    shared: Arc<Shared>,
+    stop_tx: Option<Sender<()>>,
+    /// Stop the tasks started with [`spawn_every`]
+    periodic_stop_txs: Vec<Sender<()>>,
+}
+
+impl Scheduler {
+    pub fn new(config: SchedulerConfig) -> Scheduler {
+        let (stop_tx, stop_rx) = oneshot::channel();
+        let shared = Arc::new(Shared {
+            schedules: Mutex::new(HashMap::new()),
+            latest: Mutex::new(HashMap::new()),
+            shared_targets: Mutex::new(()),
+            in_flight: std::sync::Mutex::new(HashSet::new()),
+            wake: Notify::new(),
+            config,
+            previews: Previews::new(),
+            health: Health::new(),
+        });
+        let shared_clone = shared.clone();
+        tokio::spawn(async move { run(shared_clone, stop_rx).await });
+
+        // Periodic jobs get their own tasks so that they never hold up polling
+        let mut periodic_stop_txs = vec![];
+        if !shared.config.reminder_lead_times.is_empty() {
+            let shared_clone = shared.clone();
+            periodic_stop_txs.push(spawn_every(shared.config.reminder_interval, move || {
+                send_reminders(shared_clone.clone())
+            }));
+        }
+        if let Some(auto_map_interval) = shared.config.auto_map_interval {
+            let dry_run = shared.config.dry_run;
+            periodic_stop_txs.push(spawn_every(auto_map_interval, move || {
+                auto_map_users(dry_run)
+            }));
+        }
+        Scheduler {
+            shared,
+            stop_tx: Some(stop_tx),
+            periodic_stop_txs,
+        }
+    }
+
+    /// Starts syncing the user group with the schedule. Returns false if it was already synced.
+    pub async fn add_user_group(&self, schedule: ScheduleKey, target: UserGroupTarget) -> bool {
+        let mut schedules = self.shared.schedules.lock().await;
+        let entry = schedules.entry(schedule).or_insert_with(ScheduleEntry::new);
+        let added = entry.user_groups.insert(target.clone());
+        if added {
+            self.reconcile_user_group_soon(&mut schedules, &target);
+        }
+        added
+    }
+
+    /// Stops syncing the user group with the schedule. Returns false if it wasn't being synced.
+    pub async fn remove_user_group(
+        &self,
+        schedule: &ScheduleKey,
+        target: &UserGroupTarget,
+    ) -> bool {
+        let mut schedules = self.shared.schedules.lock().await;
+        let removed = match schedules.get_mut(schedule) {
+            Some(entry) => entry.user_groups.remove(target),
+            None => false,
+        };
+        if remove_if_empty(&mut schedules, schedule) {
+            self.shared.latest.lock().await.remove(schedule);
+        }
+        if removed {
+            self.reconcile_user_group_soon(&mut schedules, target);
+            self.shared
+                .previews
+                .remove_user_group(target.oncall_sync_id)
+                .await;
+            self.shared
+                .health
+                .remove_user_group(target.oncall_sync_id)
+                .await;
+        }
+        removed
+    }
+
+    /// Starts notifying the channel about the schedule. Returns false if it was already notified.
+    pub async fn add_channel(&self, schedule: ScheduleKey, target: ChannelTarget) -> bool {
+        let mut schedules = self.shared.schedules.lock().await;
+        let entry = schedules.entry(schedule).or_insert_with(ScheduleEntry::new);
+        let added = entry.channels.insert(target.clone());
+        if added {
+            self.reconcile_channel_soon(&mut schedules, &target);
+        }
+        added
+    }
+
+    /// Stops notifying the channel about the schedule. Returns false if it wasn't being notified.
+    pub async fn remove_channel(&self, schedule: &ScheduleKey, target: &ChannelTarget) -> bool {
+        let mut schedules = self.shared.schedules.lock().await;
+        let removed = match schedules.get_mut(schedule) {
+            Some(entry) => entry.channels.remove(target),
+            None => false,
+        };
+        if remove_if_empty(&mut schedules, schedule) {
+            self.shared.latest.lock().await.remove(schedule);
+        }
+        if removed {
+            self.reconcile_channel_soon(&mut schedules, target);
+            self.shared
+                .previews
+                .remove_channel(target.notification_id)
+                .await;
+            self.shared
+                .health
+                .remove_channel(target.notification_id)
+                .await;
+        }
+        removed
+    }
+
+    /// Reconciles the schedule right away instead of waiting for its next poll. Returns false if
+    /// nothing is attached to the schedule.
+    pub async fn trigger(&self, schedule: &ScheduleKey) -> bool {
+        let mut schedules = self.shared.schedules.lock().await;
+        match schedules.get_mut(schedule) {
+            Some(entry) => {
+                self.reconcile_soon(entry);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// The user groups synced with the schedule.
+    pub async fn user_groups(&self, schedule: &ScheduleKey) -> Vec<UserGroupTarget> {
+        self.shared
+            .schedules
+            .lock()
+            .await
+            .get(schedule)
+            .map(|entry| entry.user_groups.iter().cloned().collect())
+            .unwrap_or_default()
+    }
+
+    /// The schedules with anything attached, which are the ones being polled.
+    pub async fn schedules(&self) -> Vec<ScheduleKey> {
+        self.shared.schedules.lock().await.keys().cloned().collect()
+    }
+
+    /// Latest changes computed for syncs and notifications in dry-run mode.
+    pub fn previews(&self) -> &Previews {
+        &self.shared.previews
+    }
+
+    /// Outcome of the latest reconcile of every sync and notification.
+    pub fn health(&self) -> &Health {
+        &self.shared.health
+    }
+
+    fn reconcile_soon(&self, entry: &mut ScheduleEntry) {
+        entry.next_run = Instant::now();
+        self.shared.wake.notify_one();
+    }
+
+    /// Reconciles every schedule synced with the target's user group, as they share its members.
+    fn reconcile_user_group_soon(
+        &self,
+        schedules: &mut HashMap<ScheduleKey, ScheduleEntry>,
+        target: &UserGroupTarget,
+    ) {
+        for entry in schedules.values_mut() {
+            if entry
+                .user_groups
+                .iter()
+                .any(|other| other.same_user_group(target))
+            {
+                self.reconcile_soon(entry);
+            }
+        }
+    }
+
+    /// Reconciles every schedule notified in the target's channel, as they share its topic.
+    fn reconcile_channel_soon(
+        &self,
+        schedules: &mut HashMap<ScheduleKey, ScheduleEntry>,
+        target: &ChannelTarget,
+    ) {
+        for entry in schedules.values_mut() {
+            if entry
+                .channels
+                .iter()
+                .any(|other| other.same_channel(target))
+            {
+                self.reconcile_soon(entry);
+            }
+        }
+    }
+}
+
+impl Drop for Scheduler {
+    fn drop(&mut self) {
+        // Extract out `stop_tx` (which should never be none)
+        match std::mem::take(&mut self.stop_tx) {
+            Some(stop_tx) => {
+                // Sending a stop will be sufficient to stop the scheduler on its next wake up
+                if stop_tx.send(()).is_err() {
+                    warn!("Scheduler failed to send stop. It's likely something went wrong with the scheduler task.");
+                }
+            }
+            None => {
+                warn!("self.stop_tx for scheduler was none. Did you double-drop?");
+            }
+        }
+        for stop_tx in self.periodic_stop_txs.drain(..) {
+            if stop_tx.send(()).is_err() {
+                warn!("Scheduler failed to stop a periodic task. It's likely the task panicked.");
+            }
+        }
+    }
+}
+
+/// Returns true if the schedule had nothing left attached and was removed.
+fn remove_if_empty(
+    schedules: &mut HashMap<ScheduleKey, ScheduleEntry>,
+    schedule: &ScheduleKey,
+) -> bool {
+    if let Some(true) = schedules.get(schedule).map(ScheduleEntry::is_empty) {
+        schedules.remove(schedule);
+        return true;
+    }
+    false
+}
+
+async fn run(shared: Arc<Shared>, mut stop_rx: Receiver<()>) {
+    loop {
+        // Grab everything that's due and isn't already being reconciled, and push its next run
+        // back before releasing the lock. Each reconcile runs in its own task, so that slow
+        // providers don't delay the rest of the schedules.
+        let now = Instant::now();
+        let due = {
+            let mut schedules = shared.schedules.lock().await;
+            let mut in_flight = shared.in_flight.lock().unwrap();
+            let mut due = vec![];
+            for (schedule, entry) in schedules.iter_mut() {
+                if entry.next_run > now || !in_flight.insert(schedule.clone()) {
+                    continue;
+                }
+                entry.next_run = now + shared.config.next_interval(schedule);
+                due.push((
+                    schedule.clone(),
+                    entry.user_groups.iter().cloned().collect::<Vec<_>>(),
+                    entry.channels.iter().cloned().collect::<Vec<_>>(),
+                ));
+            }
+            due
+        };
+
+        for (schedule, user_groups, channels) in due {
+            let in_flight = InFlight {
+                shared: shared.clone(),
+                schedule: schedule.clone(),
+            };
+            tokio::spawn(async move {
+                reconcile(&in_flight.shared, schedule, user_groups, channels).await;
+                drop(in_flight);
+            });
+        }
+
+        // Schedules being reconciled wake the loop up when they're done instead
+        let next_wake = {
+            let schedules = shared.schedules.lock().await;
+            let in_flight = shared.in_flight.lock().unwrap();
+            schedules
+                .iter()
+                .filter(|(schedule, _)| !in_flight.contains(*schedule))
+                .map(|(_, entry)| entry.next_run)
+                .min()
+                .unwrap_or_else(|| Instant::now() + shared.config.default_interval)
+        };
+
+        select! {
+            _ = sleep_until(next_wake) => {}
+            _ = shared.wake.notified() => {}
+            // We've been asked to stop, or the process above is dead. Stop, so return immediately
+            _ = &mut stop_rx => {
+                return;
+            }
+        }
+    }
+}
+
+/// Runs `job` every `period`, starting right away, until the returned sender is used or dropped. A
+/// run that takes longer than `period` pushes the next one back rather than queueing up more.
+fn spawn_every<F, Fut>(period: Duration, mut job: F) -> Sender<()>
+where
+    F: FnMut() -> Fut + Send + 'static,
+    Fut: Future<Output = ()> + Send,
+{
+    let (stop_tx, mut stop_rx) = oneshot::channel();
+    tokio::spawn(async move {
+        // Intervals can't be zero
+        let mut ticks = interval(period.max(Duration::from_millis(1)));
+        ticks.set_missed_tick_behavior(MissedTickBehavior::Delay);
+        loop {
+            select! {
+                _ = ticks.tick() => job().await,
+                _ = &mut stop_rx => return,
+            }
+        }
+    });
+    stop_tx
+}
+
+/// Queues and sends reminders for the schedules that are live in any chat workspace.
+async fn send_reminders(shared: Arc<Shared>) {
+    let live = {
+        let schedules = shared.schedules.lock().await;
+        schedules
+            .iter()
+            .map(|(schedule, entry)| {
+                let user_groups: Vec<_> = entry.user_groups.iter().cloned().collect();
+                let channels: Vec<_> = entry.channels.iter().cloned().collect();
+                (
+                    schedule.clone(),
+                    live_chat_providers(&shared.config, &user_groups, &channels),
+                )
+            })
+            .filter(|(_, chat_providers)| !chat_providers.is_empty())
+            .collect::<Vec<_>>()
+    };
+    reminders::process(&live, &shared.config.reminder_lead_times).await;
+}
+
+async fn auto_map_users(dry_run: bool) {
+    match auto_mapping::run(audit::SYSTEM_ACTOR, dry_run).await {
+        Ok(report) => info!(
+            "Mapped {} users by email, {} conflicts, {} users without a chat account and {} errors",
+            report.created.len(),
+            report.conflicts.len(),
+            report.unmatched.len(),
+            report.errors.len()
+        ),
+        Err(e) => warn!("Error mapping users by email: {}", e),
+    }
+}
+
+/// Marks a schedule as being reconciled until dropped, even if the reconcile panics. The scheduler
+/// is woken up afterwards in case the schedule was triggered in the meantime.
+struct InFlight {
+    shared: Arc<Shared>,
+    schedule: ScheduleKey,
+}
+
+impl Drop for InFlight {
+    fn drop(&mut self) {
+        self.shared
+            .in_flight
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .remove(&self.schedule);
+        self.shared.wake.notify_one();
+    }
+}
+
+/// Fetches the schedule's current oncalls once and fans them out to every attached target.
+async fn reconcile(
+    shared: &Shared,
+    schedule: ScheduleKey,
+    user_groups: Vec<UserGroupTarget>,
+    channels: Vec<ChannelTarget>,
+) {
+    info!(
+        "Reconciling {} oncall_id {} for {} user groups and {} channels",
+        schedule.oncall_provider,
+        schedule.oncall_id,
+        user_groups.len(),
+        channels.len()
+    );
+
+    let current_oncalls = match schedule
+        .oncall_provider
+        .provider()
+        .get_current_oncalls(&schedule.oncall_id)
+        .await
+    {
+        Err(e) => {
+            warn!(
+                "Error fetching current oncall data for {} oncall {}: {}",
+                schedule.oncall_provider, schedule.oncall_id, e
+            );
+            // None of the targets could be updated, so they're all unhealthy
+            let health = &shared.health;
+            join!(
+                join_all(user_groups.iter().map(|target| {
+                    health.record_user_group(&schedule, target, Some(Failure::from(&e)))
+                })),
+                join_all(channels.iter().map(|target| {
+                    health.record_channel(&schedule, target, Some(Failure::from(&e)))
+                }))
+            );
+            return;
+        }
+        Ok(oncalls) => oncalls,
+    };
+
+    let oncall_user_ids: Vec<String> = current_oncalls
+        .iter()
+        .map(|member| member.user_id.clone())
+        .collect();
+    let shift_changes = record_shifts(&schedule, &oncall_user_ids).await;
+    let slack_members = map_to_slack_users(schedule.oncall_provider, current_oncalls).await;
+    let slack_users: Vec<String> = slack_members
+        .iter()
+        .map(|member| member.user_id.clone())
+        .collect();
+
+    let dm_chat_providers = if shared.config.shift_dms {
+        live_chat_providers(&shared.config, &user_groups, &channels)
+    } else {
+        vec![]
+    };
+    let dm_members = slack_members.clone();
+
+    let (group_siblings, siblings) = {
+        let schedules = shared.schedules.lock().await;
+        (
+            shared_targets(&schedules, &schedule, &user_groups, |entry| {
+                &entry.user_groups
+            }),
+            shared_targets(&schedules, &schedule, &channels, |entry| &entry.channels),
+        )
+    };
+    let info = schedule_info(
+        &schedule,
+        slack_members,
+        channels.iter().any(ChannelTarget::needs_next_oncall),
+        // Schedules sharing a channel are told apart by name
+        !siblings.is_empty()
+            || channels
+                .iter()
+                .any(|target| target.rich_messages || target.templates.uses(Placeholder::Schedule)),
+        user_groups.clone(),
+    )
+    .await;
+    if !siblings.is_empty() || !group_siblings.is_empty() {
+        shared
+            .latest
+            .lock()
+            .await
+            .insert(schedule.clone(), info.clone());
+    }
+
+    let previews = &shared.previews;
+    let health = &shared.health;
+    join!(
+        join_all(user_groups.iter().map(|target| async {
+            let dry_run = shared.config.dry_run || target.dry_run;
+            let result = match group_siblings.get(&target.oncall_sync_id) {
+                None => {
+                    user_group_sync::sync_user_group(
+                        &schedule,
+                        target,
+                        std::slice::from_ref(&slack_users),
+                        dry_run,
+                        previews,
+                    )
+                    .await
+                }
+                Some(siblings) => {
+                    let _guard = shared.shared_targets.lock().await;
+                    match user_group_oncalls(shared, target, &slack_users, siblings).await {
+                        Some(oncalls) => {
+                            user_group_sync::sync_user_group(
+                                &schedule, target, &oncalls, dry_run, previews,
+                            )
+                            .await
+                        }
+                        None => {
+                            info!(
+                                "Not updating user group {} until all its schedules were fetched",
+                                target.user_group_id
+                            );
+                            return;
+                        }
+                    }
+                }
+            };
+            health
+                .record_user_group(&schedule, target, failure(&result))
+                .await;
+        })),
+        join_all(channels.iter().map(|target| async {
+            let dry_run = shared.config.dry_run || target.dry_run;
+            let own = ChannelSchedule {
+                schedule: schedule.clone(),
+                target: target.clone(),
+                info: info.clone(),
+            };
+            let result = match siblings.get(&target.notification_id) {
+                None => {
+                    notifier::notify_channel(&own, std::slice::from_ref(&own), dry_run, previews)
+                        .await
+                }
+                Some(siblings) => {
+                    let _guard = shared.shared_targets.lock().await;
+                    match channel_schedules(shared, &own, siblings).await {
+                        Some(schedules) => {
+                            notifier::notify_channel(&own, &schedules, dry_run, previews).await
+                        }
+                        None => {
+                            info!(
+                                "Not updating channel {} until all its schedules were fetched",
+                                target.slack_channel_id
+                            );
+                            return;
+                        }
+                    }
+                }
+            };
+            health
+                .record_channel(&schedule, target, failure(&result))
+                .await;
+        })),
+        shift_dms::send_shift_dms(&schedule, &shift_changes, &dm_members, &dm_chat_providers),
+        async {
+            if let Some(admin_channel) = &shared.config.admin_channel {
+                unmapped::warn_admins(&schedule, admin_channel, shared.config.dry_run).await;
+            }
+        }
+    );
+}
+
+/// Targets are shared by schedules, e.g. a channel notified about several schedules. This finds
+/// the other schedules' targets matching each of the given ones, keyed by the given target's ID.
+/// `attached` picks the kind of target from an entry. Targets only attached to this schedule are
+/// left out.
+fn shared_targets<T: SharedTarget + Clone>(
+    schedules: &HashMap<ScheduleKey, ScheduleEntry>,
+    schedule: &ScheduleKey,
+    targets: &[T],
+    attached: impl Fn(&ScheduleEntry) -> &HashSet<T>,
+) -> HashMap<i32, Vec<(ScheduleKey, T)>> {
+    targets
+        .iter()
+        .filter_map(|target| {
+            let siblings: Vec<_> = schedules
+                .iter()
+                .filter(|(other, _)| *other != schedule)
+                .flat_map(|(other, entry)| {
+                    attached(entry)
+                        .iter()
+                        .filter(|other_target| other_target.same_target(target))
+                        .map(move |other_target| (other.clone(), other_target.clone()))
+                })
+                .collect();
+            if siblings.is_empty() {
+                None
+            } else {
+                Some((target.id(), siblings))
+            }
+        })
+        .collect()
+}
+
+/// What several schedules can share.
+trait SharedTarget {
+    fn id(&self) -> i32;
+    fn same_target(&self, other: &Self) -> bool;
+}
+
+impl SharedTarget for UserGroupTarget {
+    fn id(&self) -> i32 {
+        self.oncall_sync_id
+    }
+
+    fn same_target(&self, other: &Self) -> bool {
+        self.same_user_group(other)
+    }
+}
+
+impl SharedTarget for ChannelTarget {
+    fn id(&self) -> i32 {
+        self.notification_id
+    }
+
+    fn same_target(&self, other: &Self) -> bool {
+        self.same_channel(other)
+    }
+}
+
+/// The current oncalls of every schedule synced with the user group, in the order the syncs were
+/// added. Live groups leave out schedules only previewed in them. None while a schedule syncing
+/// the group hasn't been fetched yet, its own reconcile syncs the group once it has.
+async fn user_group_oncalls(
+    shared: &Shared,
+    own: &UserGroupTarget,
+    own_users: &[String],
+    siblings: &[(ScheduleKey, UserGroupTarget)],
+) -> Option<Vec<Vec<String>>> {
+    let latest = shared.latest.lock().await;
+    let mut oncalls = vec![(own.oncall_sync_id, own_users.to_vec())];
+    for (schedule, target) in siblings {
+        if target.dry_run && !shared.config.dry_run {
+            continue;
+        }
+        let users = latest
+            .get(schedule)?
+            .members
+            .iter()
+            .map(|member| member.user_id.clone())
+            .collect();
+        oncalls.push((target.oncall_sync_id, users));
+    }
+    oncalls.sort_by_key(|(oncall_sync_id, _)| *oncall_sync_id);
+    Some(oncalls.into_iter().map(|(_, users)| users).collect())
+}
+
+/// Everything shown in the channel, in the order the notifications were added. Live channels
+/// leave out schedules that are only previewed there, previews show what the channel would look
+/// like with the notification live. None while a schedule sharing the channel hasn't been
+/// fetched yet, its own reconcile updates the channel once it has.
+async fn channel_schedules(
+    shared: &Shared,
+    own: &ChannelSchedule,
+    siblings: &[(ScheduleKey, ChannelTarget)],
+) -> Option<Vec<ChannelSchedule>> {
+    let latest = shared.latest.lock().await;
+    let mut schedules = vec![own.clone()];
+    for (schedule, target) in siblings {
+        if target.dry_run && !shared.config.dry_run {
+            continue;
+        }
+        schedules.push(ChannelSchedule {
+            schedule: schedule.clone(),
+            target: target.clone(),
+            info: latest.get(schedule)?.clone(),
+        });
+    }
+    schedules.sort_by_key(|part| part.target.notification_id);
+    Some(schedules)
+}
+
+/// The chat workspaces the schedule's syncs and notifications actually change, as opposed to
+/// only previewing. DMs about the schedule go out through these.
+fn live_chat_providers(
+    config: &SchedulerConfig,
+    user_groups: &[UserGroupTarget],
+    channels: &[ChannelTarget],
+) -> Vec<ChatProviderKind> {
+    let mut chat_providers = vec![];
+    if config.dry_run {
+        return chat_providers;
+    }
+    let live = user_groups
+        .iter()
+        .filter(|target| !target.dry_run)
+        .map(|target| target.chat_provider)
+        .chain(
+            channels
+                .iter()
+                .filter(|target| !target.dry_run)
+                .map(|target| target.chat_provider),
+        );
+    for chat_provider in live {
+        if !chat_providers.contains(&chat_provider) {
+            chat_providers.push(chat_provider);
+        }
+    }
+    chat_providers
+}
+
+fn failure(result: &chat_provider::Result) -> Option<Failure> {
+    result.as_ref().err().map(Failure::from)
+}
+
+/// Gathers what channels can show about the schedule besides its current oncalls, which are
+/// given with chat user IDs. The next oncall and the schedule's name are only fetched if asked
+/// for.
+pub async fn schedule_info(
+    schedule: &ScheduleKey,
+    members: Vec<OncallMember>,
+    with_next: bool,
+    with_name: bool,
+    user_groups: Vec<UserGroupTarget>,
+) -> ScheduleInfo {
+    let next = async {
+        if with_next {
+            fetch_next_oncall(schedule).await
+        } else {
+            None
+        }
+    };
+    let name = async {
+        if with_name {
+            fetch_schedule_name(schedule).await
+        } else {
+            None
+        }
+    };
+    let (next, name) = join!(next, name);
+    ScheduleInfo {
+        members,
+        next,
+        name,
+        user_groups,
+    }
+}
+
+async fn fetch_schedule_name(schedule: &ScheduleKey) -> Option<String> {
+    match schedule
+        .oncall_provider
+        .provider()
+        .get_oncall_name(&schedule.oncall_id)
+        .await
+    {
+        Err(e) => {
+            warn!(
+                "Error fetching name of {} oncall {}: {}",
+                schedule.oncall_provider, schedule.oncall_id, e
+            );
+            None
+        }
+        Ok(name) => Some(name),
+    }
+}
+
+/// Fetches who is on call next and when they take over. Channels only show this as extra
+/// information, so failures are logged rather than failing the reconcile.
+async fn fetch_next_oncall(schedule: &ScheduleKey) -> Option<NextOncall> {
+    let provider = schedule.oncall_provider.provider();
+    let (next_oncalls, handoff_at) = join!(
+        provider.get_next_oncalls(&schedule.oncall_id),
+        provider.get_handoff_time(&schedule.oncall_id)
+    );
+    match (next_oncalls, handoff_at) {
+        (Ok(next_oncalls), Ok(handoff_at)) => Some(NextOncall {
+            members: map_to_slack_users(schedule.oncall_provider, next_oncalls).await,
+            handoff_at,
+        }),
+        (Err(e), _) | (_, Err(e)) => {
+            warn!(
+                "Error fetching next oncall data for {} oncall {}: {}",
+                schedule.oncall_provider, schedule.oncall_id, e
+            );
+            None
+        }
+    }
+}
+
+/// Opens and closes shifts in the DB to match who is on call right now. Returns what changed, or
+/// nothing if it couldn't be recorded.
+async fn record_shifts(schedule: &ScheduleKey, user_ids: &[String]) -> db::ShiftChanges {
+    let schedule_clone = schedule.clone();
+    let user_ids = user_ids.to_vec();
+    let result = tokio::task::spawn_blocking(move || {
+        let connection = db::connection();
+        db::update_shifts(
+            &connection,
+            schedule_clone.oncall_provider,
+            &schedule_clone.oncall_id,
+            &user_ids,
+            audit::now(),
+        )
+    })
+    .await;
+    match result {
+        Err(e) => warn!("Error recording shifts for {:?}: {}", schedule, e),
+        Ok(Err(e)) => warn!("Error recording shifts for {:?}: {}", schedule, e),
+        Ok(Ok(changes)) => return changes,
+    }
+    db::ShiftChanges::default()
+}
+
+/// Maps oncall provider user IDs to chat user IDs, keeping how each user is on call. This filters
+/// out any users we don't have a mapping for.
+pub async fn map_to_slack_users(
+    oncall_provider: OncallProviderKind,
+    oncall_members: Vec<OncallMember>,
+) -> Vec<OncallMember> {
+    let tasks = oncall_members.into_iter().map(|member| {
+        tokio::spawn(async move {
+            let connection = db::connection();
+            db::get_opsgenie_user_mapping(&connection, oncall_provider, &member.user_id).map(
+                |user_mapping| {
+                    user_mapping.map(|user_mapping| OncallMember {
+                        user_id: user_mapping.slack_id,
+                        via: member.via,
+                    })
+                },
+            )
+        })
+    });
+    join_all(tasks)
+        .await
+        .into_iter()
+        .filter_map(|user_mapping| match user_mapping {
+            Err(e) => {
+                warn!("Error fetching user mapping: {}", e);
+                None
+            }
+            Ok(Err(e)) => {
+                warn!("Error fetching user mapping: {}", e);
+                None
+            }
+            Ok(Ok(member)) => member,
+        })
+        .collect()
+}
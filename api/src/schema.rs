@@ -1,8 +1,46 @@
+table! {
+    api_tokens (id) {
+        id -> Integer,
+        name -> Text,
+        token_hash -> Text,
+        role -> Text,
+        created_at -> BigInt,
+    }
+}
+
+table! {
+    audit_events (id) {
+        id -> Integer,
+        created_at -> BigInt,
+        actor -> Text,
+        action -> Text,
+        outcome -> Text,
+        error -> Nullable<Text>,
+        oncall_sync_id -> Nullable<Integer>,
+        notification_id -> Nullable<Integer>,
+        oncall_provider -> Nullable<Text>,
+        oncall_id -> Nullable<Text>,
+        chat_provider -> Nullable<Text>,
+        user_group_id -> Nullable<Text>,
+        slack_channel_id -> Nullable<Text>,
+        previous_value -> Nullable<Text>,
+        new_value -> Nullable<Text>,
+    }
+}
+
 table! {
     notified_slack_channel (id) {
         id -> Integer,
         oncall_id -> Text,
         slack_channel_id -> Text,
+        oncall_provider -> Text,
+        chat_provider -> Text,
+        dry_run -> Bool,
+        show_next_oncall -> Bool,
+        topic_template -> Nullable<Text>,
+        message_template -> Nullable<Text>,
+        out_of_hours_template -> Nullable<Text>,
+        rich_messages -> Bool,
     }
 }
 
@@ -11,6 +49,35 @@ table! {
         id -> Integer,
         oncall_id -> Text,
         user_group_id -> Text,
+        oncall_provider -> Text,
+        chat_provider -> Text,
+        dry_run -> Bool,
+    }
+}
+
+table! {
+    shift_reminders (id) {
+        id -> Integer,
+        oncall_provider -> Text,
+        oncall_id -> Text,
+        chat_provider -> Text,
+        user_id -> Text,
+        shift_start -> BigInt,
+        is_override -> Bool,
+        remind_at -> BigInt,
+        sent_at -> Nullable<BigInt>,
+    }
+}
+
+table! {
+    shifts (id) {
+        id -> Integer,
+        oncall_provider -> Text,
+        oncall_id -> Text,
+        user_id -> Text,
+        started_at -> BigInt,
+        ended_at -> Nullable<BigInt>,
+        unmapped_warned_at -> Nullable<BigInt>,
     }
 }
 
@@ -19,11 +86,17 @@ table! {
         id -> Integer,
         opsgenie_id -> Text,
         slack_id -> Text,
+        shift_dms_opt_out -> Bool,
+        oncall_provider -> Text,
     }
 }
 
 allow_tables_to_appear_in_same_query!(
+    api_tokens,
+    audit_events,
     notified_slack_channel,
     oncall_syncs,
+    shift_reminders,
+    shifts,
     user_mapping,
 );
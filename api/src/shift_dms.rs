@@ -0,0 +1,165 @@
+//! Direct messages to the people whose shift just started or ended. Enabled with `SHIFT_DMS`,
+//! users can opt out through their user mapping.
+
+use crate::{
+    chat_provider::ChatProviderKind,
+    db::{self, ShiftChanges},
+    models::UserMapping,
+    notifier,
+    oncall_provider::{OncallMember, OncallProviderKind},
+    scheduler::ScheduleKey,
+};
+use log::{info, warn};
+use tokio::join;
+
+/// Welcomes the users that came on call with what they're on call for, until when and how many
+/// alerts are open, and sends the users that went off call a summary of the handoff. `current` are
+/// the oncalls now, with chat user IDs. Users without a mapping or that opted out are skipped, and
+/// failures are only logged.
+pub async fn send_shift_dms(
+    schedule: &ScheduleKey,
+    changes: &ShiftChanges,
+    current: &[OncallMember],
+    chat_providers: &[ChatProviderKind],
+) {
+    // The first shifts of a schedule are only the bot noticing who is on call, not handoffs
+    if changes.is_empty() || changes.first_seen || chat_providers.is_empty() {
+        return;
+    }
+
+    let provider = schedule.oncall_provider.provider();
+    let (name, handoff_at, open_alerts, started, ended) = join!(
+        provider.get_oncall_name(&schedule.oncall_id),
+        provider.get_handoff_time(&schedule.oncall_id),
+        provider.count_open_alerts(&schedule.oncall_id),
+        recipients(schedule.oncall_provider, changes.started.clone()),
+        recipients(
+            schedule.oncall_provider,
+            changes
+                .ended
+                .iter()
+                .map(|shift| shift.user_id.clone())
+                .collect()
+        ),
+    );
+    let name = name.unwrap_or_else(|e| {
+        warn!(
+            "Error fetching name of {} oncall {}: {}",
+            schedule.oncall_provider, schedule.oncall_id, e
+        );
+        schedule.oncall_id.clone()
+    });
+    let handoff_at = handoff_at.unwrap_or_else(|e| {
+        warn!(
+            "Error fetching handoff time of {} oncall {}: {}",
+            schedule.oncall_provider, schedule.oncall_id, e
+        );
+        None
+    });
+    let open_alerts = open_alerts.unwrap_or_else(|e| {
+        warn!(
+            "Error counting open alerts of {} oncall {}: {}",
+            schedule.oncall_provider, schedule.oncall_id, e
+        );
+        None
+    });
+    let alerts_suffix = match open_alerts {
+        Some(open_alerts) => format!("; open alerts: {}", open_alerts),
+        None => String::new(),
+    };
+
+    for &chat_provider in chat_providers {
+        let chat = chat_provider.provider();
+        let until = match handoff_at {
+            Some(handoff_at) => format!(" until {}", chat.format_time(handoff_at)),
+            None => String::new(),
+        };
+        let started_message = format!(
+            "You're now on call for *{}*{}{}",
+            name, until, alerts_suffix
+        );
+        let took_over = match notifier::format_mentions(chat_provider, current).await {
+            Ok(mentions) => notifier::join_mentions(&mentions),
+            Err(e) => {
+                warn!("Error formatting user mentions: {}", e);
+                "the next oncall".to_string()
+            }
+        };
+
+        let started_dms = started
+            .iter()
+            .map(|mapping| (mapping, started_message.clone()));
+        let ended_dms = changes.ended.iter().filter_map(|shift| {
+            let mapping = ended
+                .iter()
+                .find(|mapping| mapping.opsgenie_id == shift.user_id)?;
+            let length = shift.ended_at.unwrap_or(shift.started_at) - shift.started_at;
+            Some((
+                mapping,
+                format!(
+                    "Your shift on *{}* has ended after {}. Handed over to {}{}",
+                    name,
+                    format_duration(length),
+                    took_over,
+                    alerts_suffix
+                ),
+            ))
+        });
+        // There are only ever a few, so they're sent one at a time, incoming oncalls first
+        for (mapping, message) in started_dms.chain(ended_dms) {
+            match chat.post_direct_message(&mapping.slack_id, &message).await {
+                Err(e) => warn!(
+                    "Error sending shift DM to {} user {}: {}",
+                    chat_provider, mapping.slack_id, e
+                ),
+                Ok(()) => info!(
+                    "Sent shift DM for {} oncall {} to {} user {}",
+                    schedule.oncall_provider, schedule.oncall_id, chat_provider, mapping.slack_id
+                ),
+            }
+        }
+    }
+}
+
+/// Looks up the mappings of the oncall provider users that should get a DM.
+async fn recipients(
+    oncall_provider: OncallProviderKind,
+    user_ids: Vec<String>,
+) -> Vec<UserMapping> {
+    if user_ids.is_empty() {
+        return vec![];
+    }
+    let result = tokio::task::spawn_blocking(move || {
+        let connection = db::connection();
+        user_ids
+            .iter()
+            .map(|user_id| db::get_opsgenie_user_mapping(&connection, oncall_provider, user_id))
+            .collect::<db::Result<Vec<_>>>()
+    })
+    .await;
+    match result {
+        Err(e) => warn!("Error fetching user mappings for shift DMs: {}", e),
+        Ok(Err(e)) => warn!("Error fetching user mappings for shift DMs: {}", e),
+        Ok(Ok(mappings)) => {
+            return mappings
+                .into_iter()
+                .flatten()
+                .filter(|mapping| !mapping.shift_dms_opt_out)
+                .collect()
+        }
+    }
+    vec![]
+}
+
+/// Formats a number of seconds as e.g. `2d 4h`, `3h 20m`, `1h` or `45m`.
+pub fn format_duration(seconds: i64) -> String {
+    let minutes = seconds.max(0) / 60;
+    let (days, hours, minutes) = (minutes / (24 * 60), minutes / 60 % 24, minutes % 60);
+    match (days, hours, minutes) {
+        (0, 0, minutes) => format!("{}m", minutes),
+        (0, hours, 0) => format!("{}h", hours),
+        (0, hours, minutes) => format!("{}h {}m", hours, minutes),
+        (days, 0, _) => format!("{}d", days),
+        (days, hours, _) => format!("{}d {}h", days, hours),
+    }
+}
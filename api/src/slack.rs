@@ -1,8 +1,57 @@
+use crate::{
+    api_client::{ApiClient, RateLimit},
+    chat_provider::{self, Announcement, ChatProvider, ChatProviderKind},
+    oncall_provider::OncallProviderKind,
+};
+use futures::future::BoxFuture;
+use hmac::{Hmac, Mac};
 use reqwest::{header::AUTHORIZATION, StatusCode, Url};
-use serde::{Deserialize, Serialize};
-use std::env;
+use serde::{
+    de::{DeserializeOwned, IgnoredAny},
+    Deserialize, Serialize,
+};
+use serde_json::{json, Value};
+use sha2::Sha256;
+use std::{
+    env,
+    sync::OnceLock,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+/// Requests signed longer ago than this are rejected to prevent replays.
+const MAX_REQUEST_AGE_SECS: u64 = 60 * 5;
+
+/// Action IDs of the announcement buttons, see [`announcement_blocks`].
+pub const PAGE_ONCALL_ACTION: &str = "page_oncall";
+pub const TAKE_OVER_ACTION: &str = "take_over";
+const VIEW_SCHEDULE_ACTION: &str = "view_schedule";
+/// Context blocks hold at most 10 elements, so only this many oncalls get their avatar shown.
+const MAX_ANNOUNCED_USERS: usize = 4;
+
+/// Rate limit tiers from https://api.slack.com/docs/rate-limits
+const TIER_2: RateLimit = RateLimit::per_minute(20);
+const TIER_3: RateLimit = RateLimit::per_minute(50);
+const TIER_4: RateLimit = RateLimit::per_minute(100);
+/// The tier of every Web API method we call. Slack limits each method separately.
+const METHOD_RATE_LIMITS: [(&str, RateLimit); 10] = [
+    ("usergroups.list", TIER_2),
+    ("usergroups.users.list", TIER_2),
+    ("usergroups.users.update", TIER_2),
+    ("users.list", TIER_2),
+    ("users.info", TIER_4),
+    ("users.lookupByEmail", TIER_3),
+    ("conversations.list", TIER_2),
+    ("conversations.info", TIER_3),
+    ("conversations.setTopic", TIER_2),
+    // Slack allows about one message per second per channel, with short bursts. We post at most
+    // once per channel per rotation, so a global limit of the same rate is plenty.
+    ("chat.postMessage", RateLimit::per_minute(60)),
+];
+
+static CLIENT: OnceLock<ApiClient> = OnceLock::new();
 
 #[derive(thiserror::Error, Debug)]
+#[allow(clippy::enum_variant_names)]
 pub enum Error {
     #[error("error making request")]
     RequestError(#[from] reqwest::Error),
@@ -12,6 +61,69 @@ pub enum Error {
     UserGroupNotFound,
     #[error("could not parse url")]
     UrlParseError(#[from] url::ParseError),
+    #[error("could not parse response")]
+    ResponseParseError(#[from] serde_json::Error),
+    #[error("the bot is not a member of the channel (not_in_channel)")]
+    NotInChannel,
+    #[error("rate limited by slack (ratelimited)")]
+    RateLimited,
+    #[error("the slack token is invalid or was revoked ({0})")]
+    InvalidAuth(String),
+    #[error("the slack token is missing the {needed} scope (missing_scope)")]
+    MissingScope { needed: String },
+    #[error("user group does not exist (no_such_subteam)")]
+    NoSuchSubteam,
+    #[error("channel does not exist (channel_not_found)")]
+    ChannelNotFound,
+    #[error("user does not exist (user_not_found)")]
+    UserNotFound,
+    #[error("slack returned {0}")]
+    ApiError(String),
+}
+
+impl Error {
+    /// Maps the `error` code of an `ok: false` response to its variant. `needed` is the missing
+    /// scope Slack includes with `missing_scope` errors.
+    fn from_api_error(code: String, needed: Option<String>) -> Error {
+        match &code[..] {
+            "not_in_channel" => Error::NotInChannel,
+            "ratelimited" => Error::RateLimited,
+            "invalid_auth" | "not_authed" | "account_inactive" | "token_revoked"
+            | "token_expired" => Error::InvalidAuth(code),
+            "missing_scope" => Error::MissingScope {
+                needed: needed.unwrap_or_else(|| "unknown".into()),
+            },
+            "no_such_subteam" => Error::NoSuchSubteam,
+            "channel_not_found" => Error::ChannelNotFound,
+            "user_not_found" | "users_not_found" => Error::UserNotFound,
+            _ => Error::ApiError(code),
+        }
+    }
+
+    /// Slack's error code, if the error came from an `ok: false` response.
+    pub fn code(&self) -> Option<&str> {
+        match self {
+            Error::NotInChannel => Some("not_in_channel"),
+            Error::RateLimited => Some("ratelimited"),
+            Error::InvalidAuth(code) | Error::ApiError(code) => Some(code),
+            Error::MissingScope { needed: _ } => Some("missing_scope"),
+            Error::NoSuchSubteam => Some("no_such_subteam"),
+            Error::ChannelNotFound => Some("channel_not_found"),
+            Error::UserNotFound => Some("user_not_found"),
+            _ => None,
+        }
+    }
+
+    /// Returns true if Slack told us the requested resource does not exist.
+    pub fn is_not_found(&self) -> bool {
+        matches!(
+            self,
+            Error::UserGroupNotFound
+                | Error::NoSuchSubteam
+                | Error::ChannelNotFound
+                | Error::UserNotFound
+        )
+    }
 }
 
 pub type Result<T = (), E = Error> = std::result::Result<T, E>;
@@ -29,6 +141,16 @@ pub struct User {
     pub name: String,
     pub real_name: Option<String>,
     pub is_bot: bool,
+    #[serde(default)]
+    pub profile: Profile,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct Profile {
+    /// URL of the user's 48x48 avatar
+    pub image_48: Option<String>,
+    /// Only returned when the app has the `users:read.email` scope
+    pub email: Option<String>,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
@@ -45,6 +167,112 @@ pub struct Channel {
     pub topic: ChannelTopic,
 }
 
+/// Form body Slack sends for slash commands.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct SlashCommand {
+    pub command: String,
+    #[serde(default)]
+    pub text: String,
+    pub channel_id: String,
+    pub user_id: String,
+    #[serde(default)]
+    pub response_url: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct SlashCommandResponse {
+    pub response_type: String,
+    pub text: String,
+}
+
+impl SlashCommandResponse {
+    /// A reply only visible to the user that ran the command.
+    pub fn ephemeral(text: String) -> SlashCommandResponse {
+        SlashCommandResponse {
+            response_type: "ephemeral".into(),
+            text,
+        }
+    }
+}
+
+/// Envelope for requests Slack sends to the Events API endpoint.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum EventRequest {
+    /// Handshake sent when the request URL gets configured. We must echo back the challenge.
+    UrlVerification {
+        challenge: String,
+    },
+    EventCallback {
+        event_id: String,
+        event: Event,
+    },
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Event {
+    AppMention {
+        channel: String,
+        user: String,
+        text: String,
+        ts: String,
+        thread_ts: Option<String>,
+    },
+    #[serde(other)]
+    Other,
+}
+
+/// Form body of requests to the interactivity endpoint. The payload itself is JSON.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct InteractionRequest {
+    pub payload: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Interaction {
+    /// A button in a message was clicked
+    BlockActions {
+        user: InteractionUser,
+        channel: Option<InteractionChannel>,
+        message: Option<InteractionMessage>,
+        actions: Vec<BlockAction>,
+    },
+    #[serde(other)]
+    Other,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct InteractionUser {
+    pub id: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct InteractionChannel {
+    pub id: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct InteractionMessage {
+    pub ts: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct BlockAction {
+    pub action_id: String,
+    pub value: Option<String>,
+}
+
+/// Fields shared by every Slack Web API response. Slack answers most failures with HTTP 200 and
+/// `ok: false`, so this has to be checked on every call.
+#[derive(Deserialize, Debug, Clone)]
+struct ResponseEnvelope {
+    ok: bool,
+    error: Option<String>,
+    needed: Option<String>,
+}
+
 #[derive(Serialize, Debug, Clone)]
 pub struct UserGroupUpdateRequest<'a> {
     usergroup: &'a str,
@@ -57,10 +285,15 @@ pub struct ConversationSetTopicRequest<'a> {
     topic: &'a str,
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone)]
+#[derive(Serialize, Debug, Clone)]
 pub struct PostMessageRequest<'a> {
     channel: &'a str,
     text: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    thread_ts: Option<&'a str>,
+    /// Block Kit layout. `text` is still shown in notifications and by clients without blocks.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    blocks: Option<&'a [Value]>,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
@@ -78,6 +311,12 @@ pub struct UserGroupsListResponse {
     pub ok: bool,
     pub usergroups: Vec<UserGroup>,
 }
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct UserGroupUsersListResponse {
+    pub ok: bool,
+    pub users: Vec<String>,
+}
+
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct ConversationListResponseMetadata {
     pub next_cursor: Option<String>,
@@ -104,20 +343,23 @@ pub struct ConversationSetTopicResponse {
 
 pub async fn list_user_groups() -> Result<Vec<UserGroup>> {
     let slack_oauth_token = slack_oauth_token();
-    let client = reqwest::Client::new();
+    let client = client();
     let usergroups_response = client
-        .get("https://slack.com/api/usergroups.list")
-        .header(AUTHORIZATION, format!("Bearer {}", slack_oauth_token))
-        .send()
+        .send(
+            "usergroups.list",
+            true,
+            client
+                .http()
+                .get(format!("{}/usergroups.list", slack_api_url()))
+                .header(AUTHORIZATION, format!("Bearer {}", slack_oauth_token)),
+        )
         .await?;
 
-    match usergroups_response.status() {
-        reqwest::StatusCode::OK => Ok(usergroups_response
-            .json::<UserGroupsListResponse>()
+    Ok(
+        parse_response::<UserGroupsListResponse>(usergroups_response)
             .await?
-            .usergroups),
-        error_code => Err(Error::HttpErrorCode(error_code)),
-    }
+            .usergroups,
+    )
 }
 
 pub async fn get_user_group(id: &str) -> Result<UserGroup> {
@@ -128,67 +370,117 @@ pub async fn get_user_group(id: &str) -> Result<UserGroup> {
         .ok_or_else(|| Error::UserGroupNotFound)
 }
 
+pub async fn get_user_group_members(id: &str) -> Result<Vec<String>> {
+    let slack_oauth_token = slack_oauth_token();
+    let client = client();
+    let users_response = client
+        .send(
+            "usergroups.users.list",
+            true,
+            client
+                .http()
+                .get(format!("{}/usergroups.users.list", slack_api_url()))
+                .query(&[("usergroup", id)])
+                .header(AUTHORIZATION, format!("Bearer {}", slack_oauth_token)),
+        )
+        .await?;
+
+    Ok(parse_response::<UserGroupUsersListResponse>(users_response)
+        .await?
+        .users)
+}
+
 pub async fn set_user_group(id: &str, users: &[String]) -> Result {
     let slack_oauth_token = slack_oauth_token();
-    let client = reqwest::Client::new();
+    let client = client();
     let usergroups_response = client
-        .post("https://slack.com/api/usergroups.users.update")
-        .header(AUTHORIZATION, format!("Bearer {}", slack_oauth_token))
-        .json(&UserGroupUpdateRequest {
-            usergroup: &id,
-            users,
-        })
-        .send()
+        .send(
+            "usergroups.users.update",
+            true,
+            client
+                .http()
+                .post(format!("{}/usergroups.users.update", slack_api_url()))
+                .header(AUTHORIZATION, format!("Bearer {}", slack_oauth_token))
+                .json(&UserGroupUpdateRequest {
+                    usergroup: id,
+                    users,
+                }),
+        )
         .await?;
 
-    match usergroups_response.status() {
-        reqwest::StatusCode::OK => Ok(()),
-        error_code => Err(Error::HttpErrorCode(error_code)),
-    }
+    parse_response::<IgnoredAny>(usergroups_response).await?;
+    Ok(())
 }
 
 pub async fn list_users() -> Result<Vec<User>> {
     let slack_oauth_token = slack_oauth_token();
-    let client = reqwest::Client::new();
+    let client = client();
     let users_response = client
-        .get("https://slack.com/api/users.list")
-        .header(AUTHORIZATION, format!("Bearer {}", slack_oauth_token))
-        .send()
+        .send(
+            "users.list",
+            true,
+            client
+                .http()
+                .get(format!("{}/users.list", slack_api_url()))
+                .header(AUTHORIZATION, format!("Bearer {}", slack_oauth_token)),
+        )
         .await?;
 
-    match users_response.status() {
-        reqwest::StatusCode::OK => Ok(users_response
-            .json::<ListUsersResponse>()
-            .await?
-            .members
-            .into_iter()
-            .filter(|u| !u.is_bot)
-            .collect()),
-        error_code => Err(Error::HttpErrorCode(error_code)),
-    }
+    Ok(parse_response::<ListUsersResponse>(users_response)
+        .await?
+        .members
+        .into_iter()
+        .filter(|u| !u.is_bot)
+        .collect())
 }
 
 pub async fn get_user(id: &str) -> Result<User> {
     let slack_oauth_token = slack_oauth_token();
-    let client = reqwest::Client::new();
+    let client = client();
     let users_response = client
-        .get(Url::parse_with_params(
-            "https://slack.com/api/users.info",
-            &[("user", id)],
-        )?)
-        .header(AUTHORIZATION, format!("Bearer {}", slack_oauth_token))
-        .send()
+        .send(
+            "users.info",
+            true,
+            client
+                .http()
+                .get(Url::parse_with_params(
+                    &format!("{}/users.info", slack_api_url()),
+                    &[("user", id)],
+                )?)
+                .header(AUTHORIZATION, format!("Bearer {}", slack_oauth_token)),
+        )
         .await?;
 
-    match users_response.status() {
-        reqwest::StatusCode::OK => Ok(users_response.json::<GetUserResponse>().await?.user),
-        error_code => Err(Error::HttpErrorCode(error_code)),
-    }
+    Ok(parse_response::<GetUserResponse>(users_response)
+        .await?
+        .user)
+}
+
+pub async fn lookup_user_by_email(email: &str) -> Result<User> {
+    let slack_oauth_token = slack_oauth_token();
+    let client = client();
+    let users_response = client
+        .send(
+            "users.lookupByEmail",
+            true,
+            client
+                .http()
+                .get(Url::parse_with_params(
+                    &format!("{}/users.lookupByEmail", slack_api_url()),
+                    &[("email", email)],
+                )?)
+                .header(AUTHORIZATION, format!("Bearer {}", slack_oauth_token)),
+        )
+        .await?;
+
+    Ok(parse_response::<GetUserResponse>(users_response)
+        .await?
+        .user)
 }
 
 pub async fn list_channels() -> Result<Vec<Channel>> {
     let slack_oauth_token = slack_oauth_token();
-    let client = reqwest::Client::new();
+    let client = client();
     let mut channel_list = vec![];
     let mut cursor: Option<String> = None;
 
@@ -198,22 +490,21 @@ pub async fn list_channels() -> Result<Vec<Channel>> {
             None => vec![("types", "public_channel"), ("limit", "1000")],
         };
         let conversations_response = client
-            .get(Url::parse_with_params(
-                "https://slack.com/api/conversations.list",
-                &params,
-            )?)
-            .header(AUTHORIZATION, format!("Bearer {}", slack_oauth_token))
-            .send()
+            .send(
+                "conversations.list",
+                true,
+                client
+                    .http()
+                    .get(Url::parse_with_params(
+                        &format!("{}/conversations.list", slack_api_url()),
+                        &params,
+                    )?)
+                    .header(AUTHORIZATION, format!("Bearer {}", slack_oauth_token)),
+            )
             .await?;
 
-        let mut conversations = match conversations_response.status() {
-            reqwest::StatusCode::OK => {
-                conversations_response
-                    .json::<ConversationsListResponse>()
-                    .await?
-            }
-            error_code => return Err(Error::HttpErrorCode(error_code)),
-        };
+        let mut conversations =
+            parse_response::<ConversationsListResponse>(conversations_response).await?;
 
         channel_list.append(&mut conversations.channels);
 
@@ -222,7 +513,7 @@ pub async fn list_channels() -> Result<Vec<Channel>> {
             .map(|metadata| metadata.next_cursor)
         {
             Some(Some(next_cursor)) => {
-                if next_cursor == "" {
+                if next_cursor.is_empty() {
                     break;
                 }
                 cursor = Some(next_cursor);
@@ -238,71 +529,500 @@ pub async fn list_channels() -> Result<Vec<Channel>> {
 
 pub async fn get_channel(id: &str) -> Result<Channel> {
     let slack_oauth_token = slack_oauth_token();
-    let client = reqwest::Client::new();
+    let client = client();
     let conversations_response = client
-        .get(Url::parse_with_params(
-            "https://slack.com/api/conversations.info",
-            &[("channel", id)],
-        )?)
-        .header(AUTHORIZATION, format!("Bearer {}", slack_oauth_token))
-        .send()
+        .send(
+            "conversations.info",
+            true,
+            client
+                .http()
+                .get(Url::parse_with_params(
+                    &format!("{}/conversations.info", slack_api_url()),
+                    &[("channel", id)],
+                )?)
+                .header(AUTHORIZATION, format!("Bearer {}", slack_oauth_token)),
+        )
         .await?;
 
-    let conversation = match conversations_response.status() {
-        reqwest::StatusCode::OK => {
-            conversations_response
-                .json::<ConversationInfoResponse>()
-                .await?
-        }
-        error_code => return Err(Error::HttpErrorCode(error_code)),
-    };
+    let conversation = parse_response::<ConversationInfoResponse>(conversations_response).await?;
 
     Ok(conversation.channel)
 }
 
 pub async fn set_channel_topic(channel_id: &str, topic: &str) -> Result<Channel> {
     let slack_oauth_token = slack_oauth_token();
-    let client = reqwest::Client::new();
+    let client = client();
 
     let set_topic_response = client
-        .post("https://slack.com/api/conversations.setTopic")
-        .header(AUTHORIZATION, format!("Bearer {}", slack_oauth_token))
-        .json(&ConversationSetTopicRequest {
-            channel: channel_id,
-            topic,
-        })
-        .send()
+        .send(
+            "conversations.setTopic",
+            true,
+            client
+                .http()
+                .post(format!("{}/conversations.setTopic", slack_api_url()))
+                .header(AUTHORIZATION, format!("Bearer {}", slack_oauth_token))
+                .json(&ConversationSetTopicRequest {
+                    channel: channel_id,
+                    topic,
+                }),
+        )
         .await?;
 
-    match set_topic_response.status() {
-        reqwest::StatusCode::OK => Ok(set_topic_response
-            .json::<ConversationSetTopicResponse>()
+    Ok(
+        parse_response::<ConversationSetTopicResponse>(set_topic_response)
             .await?
-            .channel),
-        error_code => Err(Error::HttpErrorCode(error_code)),
-    }
+            .channel,
+    )
 }
 
 pub async fn post_message(channel_id: &str, message: &str) -> Result<()> {
+    send_message(channel_id, None, message, None).await
+}
+
+/// Posts a Block Kit message, with `message` as the fallback text.
+pub async fn post_blocks(channel_id: &str, message: &str, blocks: &[Value]) -> Result<()> {
+    send_message(channel_id, None, message, Some(blocks)).await
+}
+
+/// Replies in the thread of the message with timestamp `thread_ts`.
+pub async fn post_thread_reply(channel_id: &str, thread_ts: &str, message: &str) -> Result<()> {
+    send_message(channel_id, Some(thread_ts), message, None).await
+}
+
+async fn send_message(
+    channel_id: &str,
+    thread_ts: Option<&str>,
+    message: &str,
+    blocks: Option<&[Value]>,
+) -> Result<()> {
     let slack_oauth_token = slack_oauth_token();
-    let client = reqwest::Client::new();
+    let client = client();
 
-    let set_topic_response = client
-        .post("https://slack.com/api/chat.postMessage")
-        .header(AUTHORIZATION, format!("Bearer {}", slack_oauth_token))
-        .json(&PostMessageRequest {
-            channel: channel_id,
-            text: message,
-        })
-        .send()
+    let post_message_response = client
+        .send(
+            "chat.postMessage",
+            false,
+            client
+                .http()
+                .post(format!("{}/chat.postMessage", slack_api_url()))
+                .header(AUTHORIZATION, format!("Bearer {}", slack_oauth_token))
+                .json(&PostMessageRequest {
+                    channel: channel_id,
+                    text: message,
+                    thread_ts,
+                    blocks,
+                }),
+        )
         .await?;
 
-    match set_topic_response.status() {
-        reqwest::StatusCode::OK => Ok(()),
-        error_code => Err(Error::HttpErrorCode(error_code)),
+    parse_response::<IgnoredAny>(post_message_response).await?;
+    Ok(())
+}
+
+/// Renders an announcement as Block Kit: the message, who is on call with their avatars, the
+/// schedule and buttons to page the oncall, open the schedule or take over the shift. `users` are
+/// the oncalls that could be looked up.
+pub fn announcement_blocks(announcement: &Announcement, users: &[User]) -> Vec<Value> {
+    let mut context = vec![];
+    for user in users.iter().take(MAX_ANNOUNCED_USERS) {
+        let name = user.real_name.as_deref().unwrap_or(&user.name);
+        if let Some(image) = &user.profile.image_48 {
+            context.push(json!({"type": "image", "image_url": image, "alt_text": name}));
+        }
+        context.push(json!({"type": "mrkdwn", "text": format!("*{}*", name)}));
     }
+    if let Some(schedule_name) = announcement.schedule_name {
+        context.push(json!({"type": "mrkdwn", "text": format!("Schedule: *{}*", schedule_name)}));
+    }
+    if let Some(until) = announcement.until {
+        context.push(json!({"type": "mrkdwn", "text": format!("Until {}", format_date(until))}));
+    }
+
+    let value = action_value(announcement.oncall_provider, announcement.oncall_id);
+    let mut buttons = vec![];
+    // Paging opens an alert, which only OpsGenie supports
+    if announcement.oncall_provider == OncallProviderKind::Opsgenie {
+        buttons.push(json!({
+            "type": "button",
+            "action_id": PAGE_ONCALL_ACTION,
+            "text": {"type": "plain_text", "text": "Page on-call"},
+            "style": "danger",
+            "value": value,
+        }));
+    }
+    if let Some(url) = announcement
+        .oncall_provider
+        .provider()
+        .schedule_url(announcement.oncall_id)
+    {
+        buttons.push(json!({
+            "type": "button",
+            "action_id": VIEW_SCHEDULE_ACTION,
+            "text": {
+                "type": "plain_text",
+                "text": format!("View schedule in {}", announcement.oncall_provider.display_name()),
+            },
+            "url": url,
+        }));
+    }
+    buttons.push(json!({
+        "type": "button",
+        "action_id": TAKE_OVER_ACTION,
+        "text": {"type": "plain_text", "text": "Take over"},
+        "value": value,
+    }));
+
+    let mut blocks = vec![json!({
+        "type": "section",
+        "text": {"type": "mrkdwn", "text": announcement.text},
+    })];
+    if !context.is_empty() {
+        blocks.push(json!({"type": "context", "elements": context}));
+    }
+    blocks.push(json!({"type": "actions", "elements": buttons}));
+    blocks
+}
+
+/// Identifies the schedule a button acts on, e.g. `opsgenie:<schedule ID>`.
+fn action_value(oncall_provider: OncallProviderKind, oncall_id: &str) -> String {
+    format!("{}:{}", oncall_provider, oncall_id)
+}
+
+/// The reverse of [`action_value`].
+pub fn parse_action_value(value: &str) -> Option<(OncallProviderKind, String)> {
+    let (oncall_provider, oncall_id) = value.split_once(':')?;
+    Some((oncall_provider.parse().ok()?, oncall_id.to_string()))
+}
+
+/// Shows the timestamp in the reader's time zone, falling back to UTC for clients that can't.
+pub fn format_date(timestamp: i64) -> String {
+    format!(
+        "<!date^{}^{{date_short_pretty}} at {{time}}|{}>",
+        timestamp,
+        chat_provider::format_utc(timestamp)
+    )
+}
+
+/// Checks the HTTP status and the `ok` flag of a Web API response before parsing its body.
+async fn parse_response<T: DeserializeOwned>(response: reqwest::Response) -> Result<T> {
+    match response.status() {
+        StatusCode::OK => {}
+        StatusCode::TOO_MANY_REQUESTS => return Err(Error::RateLimited),
+        error_code => return Err(Error::HttpErrorCode(error_code)),
+    }
+    let body = response.bytes().await?;
+    let envelope = serde_json::from_slice::<ResponseEnvelope>(&body)?;
+    if !envelope.ok {
+        return Err(Error::from_api_error(
+            envelope.error.unwrap_or_default(),
+            envelope.needed,
+        ));
+    }
+    Ok(serde_json::from_slice(&body)?)
+}
+
+/// [`ChatProvider`] implementation backed by the free functions in this module.
+pub struct Slack;
+
+impl ChatProvider for Slack {
+    fn kind(&self) -> ChatProviderKind {
+        ChatProviderKind::Slack
+    }
+
+    fn is_configured(&self) -> bool {
+        env::var("SLACK_OAUTH_TOKEN").is_ok()
+    }
+
+    fn list_user_groups(
+        &self,
+    ) -> BoxFuture<'_, chat_provider::Result<Vec<chat_provider::UserGroup>>> {
+        Box::pin(async move {
+            Ok(list_user_groups()
+                .await?
+                .into_iter()
+                .map(UserGroup::into)
+                .collect())
+        })
+    }
+
+    fn get_user_group<'a>(
+        &'a self,
+        id: &'a str,
+    ) -> BoxFuture<'a, chat_provider::Result<chat_provider::UserGroup>> {
+        Box::pin(async move { Ok(get_user_group(id).await?.into()) })
+    }
+
+    fn get_user_group_members<'a>(
+        &'a self,
+        id: &'a str,
+    ) -> BoxFuture<'a, chat_provider::Result<Vec<String>>> {
+        Box::pin(async move { Ok(get_user_group_members(id).await?) })
+    }
+
+    fn set_user_group<'a>(
+        &'a self,
+        id: &'a str,
+        users: &'a [String],
+    ) -> BoxFuture<'a, chat_provider::Result> {
+        Box::pin(async move { Ok(set_user_group(id, users).await?) })
+    }
+
+    fn list_users(&self) -> BoxFuture<'_, chat_provider::Result<Vec<chat_provider::User>>> {
+        Box::pin(async move { Ok(list_users().await?.into_iter().map(User::into).collect()) })
+    }
+
+    fn get_user<'a>(
+        &'a self,
+        id: &'a str,
+    ) -> BoxFuture<'a, chat_provider::Result<chat_provider::User>> {
+        Box::pin(async move { Ok(get_user(id).await?.into()) })
+    }
+
+    fn find_user_by_email<'a>(
+        &'a self,
+        email: &'a str,
+    ) -> BoxFuture<'a, chat_provider::Result<Option<chat_provider::User>>> {
+        Box::pin(async move {
+            match lookup_user_by_email(email).await {
+                Ok(user) => Ok(Some(user.into())),
+                Err(Error::UserNotFound) => Ok(None),
+                Err(e) => Err(e.into()),
+            }
+        })
+    }
+
+    fn list_channels(&self) -> BoxFuture<'_, chat_provider::Result<Vec<chat_provider::Channel>>> {
+        Box::pin(async move {
+            Ok(list_channels()
+                .await?
+                .into_iter()
+                .map(Channel::into)
+                .collect())
+        })
+    }
+
+    fn get_channel<'a>(
+        &'a self,
+        id: &'a str,
+    ) -> BoxFuture<'a, chat_provider::Result<chat_provider::Channel>> {
+        Box::pin(async move { Ok(get_channel(id).await?.into()) })
+    }
+
+    fn set_channel_topic<'a>(
+        &'a self,
+        channel_id: &'a str,
+        topic: &'a str,
+    ) -> BoxFuture<'a, chat_provider::Result> {
+        Box::pin(async move {
+            set_channel_topic(channel_id, topic).await?;
+            Ok(())
+        })
+    }
+
+    fn post_message<'a>(
+        &'a self,
+        channel_id: &'a str,
+        message: &'a str,
+    ) -> BoxFuture<'a, chat_provider::Result> {
+        Box::pin(async move { Ok(post_message(channel_id, message).await?) })
+    }
+
+    fn post_direct_message<'a>(
+        &'a self,
+        user_id: &'a str,
+        message: &'a str,
+    ) -> BoxFuture<'a, chat_provider::Result> {
+        // Posting to a user ID lands in their DM with the app
+        Box::pin(async move { Ok(post_message(user_id, message).await?) })
+    }
+
+    fn format_mention<'a>(
+        &'a self,
+        user_id: &'a str,
+    ) -> BoxFuture<'a, chat_provider::Result<String>> {
+        Box::pin(async move { Ok(format!("<@{}>", user_id)) })
+    }
+
+    fn format_user_group_mention<'a>(
+        &'a self,
+        user_group_id: &'a str,
+    ) -> BoxFuture<'a, chat_provider::Result<String>> {
+        Box::pin(async move { Ok(format!("<!subteam^{}>", user_group_id)) })
+    }
+
+    fn format_time(&self, timestamp: i64) -> String {
+        format_date(timestamp)
+    }
+
+    fn post_announcement<'a>(
+        &'a self,
+        channel_id: &'a str,
+        announcement: &'a Announcement<'a>,
+    ) -> BoxFuture<'a, chat_provider::Result> {
+        Box::pin(async move {
+            // The announcement is still worth posting without avatars
+            let users: Vec<User> = futures::future::join_all(
+                announcement
+                    .user_ids
+                    .iter()
+                    .take(MAX_ANNOUNCED_USERS)
+                    .map(|user_id| get_user(user_id)),
+            )
+            .await
+            .into_iter()
+            .filter_map(|user| user.ok())
+            .collect();
+            let blocks = announcement_blocks(announcement, &users);
+            Ok(post_blocks(channel_id, announcement.text, &blocks).await?)
+        })
+    }
+}
+
+impl From<UserGroup> for chat_provider::UserGroup {
+    fn from(user_group: UserGroup) -> chat_provider::UserGroup {
+        chat_provider::UserGroup {
+            id: user_group.id,
+            name: user_group.name,
+            handle: user_group.handle,
+            provider: ChatProviderKind::Slack,
+        }
+    }
+}
+
+impl From<User> for chat_provider::User {
+    fn from(user: User) -> chat_provider::User {
+        chat_provider::User {
+            id: user.id,
+            name: user.name,
+            real_name: user.real_name,
+            is_bot: user.is_bot,
+            provider: ChatProviderKind::Slack,
+        }
+    }
+}
+
+impl From<Channel> for chat_provider::Channel {
+    fn from(channel: Channel) -> chat_provider::Channel {
+        chat_provider::Channel {
+            id: channel.id,
+            name: channel.name,
+            topic: chat_provider::ChannelTopic {
+                value: channel.topic.value,
+                creator: channel.topic.creator,
+                last_set: channel.topic.last_set,
+            },
+            provider: ChatProviderKind::Slack,
+        }
+    }
+}
+
+/// Checks the `X-Slack-Signature` of an incoming request against `SLACK_SIGNING_SECRET`, as
+/// described in https://api.slack.com/authentication/verifying-requests-from-slack
+pub fn verify_request_signature(timestamp: &str, signature: &str, body: &[u8]) -> bool {
+    let signing_secret = match env::var("SLACK_SIGNING_SECRET") {
+        Ok(signing_secret) => signing_secret,
+        Err(_) => return false,
+    };
+    let timestamp_secs = match timestamp.parse::<u64>() {
+        Ok(timestamp_secs) => timestamp_secs,
+        Err(_) => return false,
+    };
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .expect("system time is before the unix epoch")
+        .as_secs();
+    if now.abs_diff(timestamp_secs) > MAX_REQUEST_AGE_SECS {
+        return false;
+    }
+    let signature = match signature
+        .strip_prefix("v0=")
+        .and_then(|signature| hex::decode(signature).ok())
+    {
+        Some(signature) => signature,
+        None => return false,
+    };
+
+    let mut mac = Hmac::<Sha256>::new_from_slice(signing_secret.as_bytes())
+        .expect("HMAC can take a key of any size");
+    mac.update(format!("v0:{}:", timestamp).as_bytes());
+    mac.update(body);
+    mac.verify_slice(&signature).is_ok()
+}
+
+fn client() -> &'static ApiClient {
+    CLIENT.get_or_init(|| ApiClient::new("slack", TIER_2, &METHOD_RATE_LIMITS))
+}
+
+/// Base URL of the Web API, without a trailing slash. Can be overridden with `SLACK_API_URL`, e.g.
+/// to go through an Enterprise Grid proxy or use a local fake.
+fn slack_api_url() -> String {
+    env::var("SLACK_API_URL")
+        .map(|url| url.trim_end_matches('/').to_string())
+        .unwrap_or_else(|_| "https://slack.com/api".into())
 }
 
 fn slack_oauth_token() -> String {
     env::var("SLACK_OAUTH_TOKEN").expect("SLACK_OAUTH_TOKEN must be set")
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SIGNING_SECRET: &str = "8f742231b10e8888abcd99yyyzzz85a5";
+    const BODY: &[u8] = b"token=xyzz0WbapA4vBCDEFasx0q6G&team_id=T1DC2JH3J&command=%2Foncall";
+
+    fn now() -> u64 {
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs()
+    }
+
+    fn sign(timestamp: &str, body: &[u8]) -> String {
+        let mut mac = Hmac::<Sha256>::new_from_slice(SIGNING_SECRET.as_bytes()).unwrap();
+        mac.update(format!("v0:{}:", timestamp).as_bytes());
+        mac.update(body);
+        format!("v0={}", hex::encode(mac.finalize().into_bytes()))
+    }
+
+    #[test]
+    fn accepts_a_fresh_valid_signature() {
+        env::set_var("SLACK_SIGNING_SECRET", SIGNING_SECRET);
+        let timestamp = now().to_string();
+        assert!(verify_request_signature(
+            &timestamp,
+            &sign(&timestamp, BODY),
+            BODY
+        ));
+    }
+
+    #[test]
+    fn rejects_an_expired_timestamp() {
+        env::set_var("SLACK_SIGNING_SECRET", SIGNING_SECRET);
+        let timestamp = (now() - MAX_REQUEST_AGE_SECS - 1).to_string();
+        assert!(!verify_request_signature(
+            &timestamp,
+            &sign(&timestamp, BODY),
+            BODY
+        ));
+    }
+
+    #[test]
+    fn rejects_a_bad_hmac() {
+        env::set_var("SLACK_SIGNING_SECRET", SIGNING_SECRET);
+        let timestamp = now().to_string();
+        let signature = sign(&timestamp, BODY);
+        assert!(!verify_request_signature(
+            &timestamp,
+            &signature,
+            b"token=xyzz0WbapA4vBCDEFasx0q6G&team_id=T1DC2JH3J&command=%2Fpage"
+        ));
+        assert!(!verify_request_signature(
+            &timestamp,
+            &sign(&(now() - 1).to_string(), BODY),
+            BODY
+        ));
+        assert!(!verify_request_signature(&timestamp, "v0=not-hex", BODY));
+    }
+}
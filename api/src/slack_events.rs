@@ -0,0 +1,190 @@
+use crate::{
+    db,
+    oncall_provider::OncallProviderKind,
+    opsgenie,
+    scheduler::ScheduleKey,
+    slack::{self, Event},
+    slash_command, AppState,
+};
+use actix_web::web;
+use futures::future::join_all;
+use log::{info, warn};
+use std::{collections::HashMap, sync::Arc, time::Duration};
+use tokio::{sync::Mutex, time::Instant};
+
+/// Slack retries events for a while if we don't answer in time, so remember IDs for this long.
+const SEEN_EVENT_TTL: Duration = Duration::from_secs(60 * 60);
+/// OpsGenie rejects alert messages longer than this.
+const MAX_ALERT_MESSAGE_LEN: usize = 130;
+
+/// Event IDs we already handled, so retried deliveries don't page twice.
+pub struct SeenEvents {
+    events: Mutex<HashMap<String, Instant>>,
+}
+
+impl SeenEvents {
+    pub fn new() -> SeenEvents {
+        SeenEvents {
+            events: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Records the event and returns true if we hadn't seen it before.
+    pub async fn first_seen(&self, event_id: &str) -> bool {
+        let now = Instant::now();
+        let mut events = self.events.lock().await;
+        events.retain(|_, seen_at| now - *seen_at < SEEN_EVENT_TTL);
+        events.insert(event_id.to_string(), now).is_none()
+    }
+}
+
+pub async fn handle_event(event: Event, data: Arc<AppState>) {
+    match event {
+        Event::AppMention {
+            channel,
+            user,
+            text,
+            ts,
+            thread_ts,
+        } => {
+            let thread_ts = thread_ts.unwrap_or(ts);
+            let reply = match page_oncall(&channel, &user, &text, None, &data).await {
+                Ok(reply) => reply,
+                Err(e) => {
+                    warn!("Error paging oncall for channel {}: {}", channel, e);
+                    format!("I couldn't page the on-call: {}", e)
+                }
+            };
+            if let Err(e) = slack::post_thread_reply(&channel, &thread_ts, &reply).await {
+                warn!("Error replying to mention in channel {}: {}", channel, e);
+            }
+        }
+        Event::Other => {}
+    }
+}
+
+/// Opens an OpsGenie alert for a schedule notified in the channel and returns the reply to post
+/// in the thread. Buttons say which schedule they page. Mentions in a channel notified about
+/// several schedules have to start with the name of the one to page.
+pub async fn page_oncall(
+    channel_id: &str,
+    user_id: &str,
+    text: &str,
+    schedule: Option<&ScheduleKey>,
+    data: &AppState,
+) -> anyhow::Result<String> {
+    let message = strip_mention(text);
+    let (schedule, message) = match schedule {
+        Some(schedule) => (schedule.clone(), message),
+        None => {
+            let channel_id_clone = channel_id.to_string();
+            let notifications = web::block(move || {
+                let conn = db::connection();
+                db::get_oncalls_notified_in_channel(&conn, &channel_id_clone)
+            })
+            .await??;
+            let mut schedules = notifications
+                .into_iter()
+                .map(|notification| ScheduleKey {
+                    oncall_provider: notification.oncall_provider,
+                    oncall_id: notification.oncall_id,
+                })
+                .collect::<Vec<_>>();
+            match schedules.len() {
+                0 => return Ok(
+                    "This channel isn't linked to an on-call schedule, so I don't know who to page."
+                        .into(),
+                ),
+                1 => (schedules.remove(0), message),
+                _ => match pick_schedule(schedules, message, data).await? {
+                    Ok(picked) => picked,
+                    Err(reply) => return Ok(reply),
+                },
+            }
+        }
+    };
+    if schedule.oncall_provider != OncallProviderKind::Opsgenie {
+        return Ok("Paging is only supported for OpsGenie schedules.".into());
+    }
+
+    let message = if message.is_empty() {
+        "Paged from Slack".to_string()
+    } else {
+        message.chars().take(MAX_ALERT_MESSAGE_LEN).collect()
+    };
+    let requester = match slack::get_user(user_id).await {
+        Ok(user) => user.real_name.unwrap_or(user.name),
+        Err(_) => user_id.to_string(),
+    };
+    let description = format!("Opened from Slack by {}:\n\n{}", requester, text);
+
+    let alert = opsgenie::create_alert(&schedule.oncall_id, &message, &description).await?;
+    info!(
+        "Opened alert {} for oncall {} from channel {}",
+        alert.id, schedule.oncall_id, channel_id
+    );
+
+    let oncall_name = match data.oncall_cache.get(&schedule.oncall_id).await? {
+        Some(oncall) => oncall.name,
+        None => schedule.oncall_id.clone(),
+    };
+    let responders = slash_command::format_users(
+        schedule.oncall_provider,
+        schedule
+            .oncall_provider
+            .provider()
+            .get_current_oncalls(&schedule.oncall_id)
+            .await?,
+    )
+    .await;
+
+    Ok(format!(
+        ":rotating_light: Paged *{}*: <{}|view alert>\nResponders: {}",
+        oncall_name, alert.url, responders
+    ))
+}
+
+/// Finds the schedule whose name the message starts with, returning the rest of the message as
+/// the alert's. If there's none, the error is the reply asking which schedule to page.
+async fn pick_schedule<'a>(
+    schedules: Vec<ScheduleKey>,
+    message: &'a str,
+    data: &AppState,
+) -> anyhow::Result<Result<(ScheduleKey, &'a str), String>> {
+    let names = join_all(schedules.iter().map(|schedule| async move {
+        Ok::<_, anyhow::Error>(match data.oncall_cache.get(&schedule.oncall_id).await? {
+            Some(oncall) => oncall.name,
+            None => schedule.oncall_id.clone(),
+        })
+    }))
+    .await
+    .into_iter()
+    .collect::<anyhow::Result<Vec<_>>>()?;
+
+    for (schedule, name) in schedules.iter().zip(&names) {
+        if let Some(rest) = message
+            .get(..name.len())
+            .filter(|start| start.eq_ignore_ascii_case(name))
+            .map(|_| &message[name.len()..])
+        {
+            let rest = rest.trim_start_matches(':').trim();
+            return Ok(Ok((schedule.clone(), rest)));
+        }
+    }
+    Ok(Err(format!(
+        "This channel is linked to several on-call schedules: {}. Start your message with the name of the one to page.",
+        names.join(", ")
+    )))
+}
+
+/// Removes the leading `<@BOT_ID>` mention from the message.
+fn strip_mention(text: &str) -> &str {
+    let text = text.trim();
+    match text
+        .strip_prefix("<@")
+        .and_then(|rest| rest.split_once('>'))
+    {
+        Some((_, rest)) => rest.trim(),
+        None => text,
+    }
+}
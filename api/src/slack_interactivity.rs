@@ -0,0 +1,136 @@
+use crate::{
+    audit, db,
+    models::NewAuditEvent,
+    scheduler::ScheduleKey,
+    slack::{self, BlockAction, Interaction},
+    slack_events, AppState,
+};
+use actix_web::web;
+use log::{info, warn};
+use std::sync::Arc;
+
+/// Handles clicks on the buttons of rich announcements. Replies go in the announcement's thread.
+pub async fn handle_interaction(interaction: Interaction, data: Arc<AppState>) {
+    let (user, channel, message, actions) = match interaction {
+        Interaction::BlockActions {
+            user,
+            channel: Some(channel),
+            message: Some(message),
+            actions,
+        } => (user, channel, message, actions),
+        _ => return,
+    };
+
+    for action in actions {
+        let reply = match run_action(&action, &channel.id, &user.id, &data).await {
+            Ok(Some(reply)) => reply,
+            Ok(None) => continue,
+            Err(e) => {
+                warn!(
+                    "Error handling {} from {} in channel {}: {}",
+                    action.action_id, user.id, channel.id, e
+                );
+                format!("Something went wrong: {}", e)
+            }
+        };
+        if let Err(e) = slack::post_thread_reply(&channel.id, &message.ts, &reply).await {
+            warn!(
+                "Error replying to {} in channel {}: {}",
+                action.action_id, channel.id, e
+            );
+        }
+    }
+}
+
+/// Returns the reply to post, or None for actions that need no answer (e.g. link buttons).
+async fn run_action(
+    action: &BlockAction,
+    channel_id: &str,
+    user_id: &str,
+    data: &AppState,
+) -> anyhow::Result<Option<String>> {
+    let schedule = action
+        .value
+        .as_deref()
+        .and_then(slack::parse_action_value)
+        .map(|(oncall_provider, oncall_id)| ScheduleKey {
+            oncall_provider,
+            oncall_id,
+        });
+    match (action.action_id.as_str(), schedule) {
+        (slack::PAGE_ONCALL_ACTION, Some(schedule)) => Ok(Some(
+            slack_events::page_oncall(channel_id, user_id, "", Some(&schedule), data).await?,
+        )),
+        (slack::TAKE_OVER_ACTION, Some(schedule)) => {
+            Ok(Some(take_over(&schedule, user_id, data).await?))
+        }
+        _ => Ok(None),
+    }
+}
+
+/// Puts the Slack user on call for the schedule until the end of the current shift.
+async fn take_over(
+    schedule: &ScheduleKey,
+    user_id: &str,
+    data: &AppState,
+) -> anyhow::Result<String> {
+    let oncall_provider = schedule.oncall_provider;
+    let slack_user_id = user_id.to_string();
+    let mapping = match web::block(move || {
+        let conn = db::connection();
+        db::get_slack_user_mapping(&conn, &slack_user_id)
+    })
+    .await??
+    {
+        // Mappings to another provider's account can't be put on call in this schedule
+        Some(mapping) if mapping.oncall_provider == oncall_provider => mapping,
+        _ => {
+            return Ok(format!(
+                "<@{}> isn't mapped to a {} user, so I can't put them on call.",
+                user_id,
+                oncall_provider.display_name()
+            ))
+        }
+    };
+
+    let provider = oncall_provider.provider();
+    let until = match provider.get_handoff_time(&schedule.oncall_id).await? {
+        Some(until) => until,
+        None => {
+            return Ok(
+                "The schedule has no upcoming handoff, so I don't know when the shift ends.".into(),
+            )
+        }
+    };
+    let result = provider
+        .add_override(
+            &schedule.oncall_id,
+            &mapping.opsgenie_id,
+            audit::now(),
+            until,
+        )
+        .await;
+    audit::record(
+        NewAuditEvent {
+            oncall_provider: Some(oncall_provider),
+            oncall_id: Some(schedule.oncall_id.clone()),
+            new_value: Some(mapping.opsgenie_id.clone()),
+            ..NewAuditEvent::new(&format!("slack:{}", user_id), "take_over")
+        }
+        .with_result(&result),
+    )
+    .await;
+    result?;
+    info!(
+        "{} took over {} oncall {} until {}",
+        user_id, oncall_provider, schedule.oncall_id, until
+    );
+
+    // Update the user group and topic now rather than on the next poll
+    data.scheduler.trigger(schedule).await;
+    Ok(format!(
+        "<@{}> took over the shift until {}",
+        user_id,
+        slack::format_date(until)
+    ))
+}
@@ -0,0 +1,316 @@
+use crate::{
+    audit, db, format_user_mapping,
+    models::{NewAuditEvent, UserMapping},
+    oncall_provider::{self, OncallMember, OncallProviderKind},
+    slack::{self, SlashCommand},
+    AppState,
+};
+use actix_web::web;
+use futures::future::join_all;
+use log::info;
+use tokio::join;
+
+/// Answers `/oncall [schedule name]` with the current and next people on call. Without a schedule
+/// name we use the schedules notified in the channel the command was run from. `/oncall link`,
+/// `/oncall unlink` and `/oncall whoami` let users manage their own user mapping.
+pub async fn run(command: &SlashCommand, data: &AppState) -> anyhow::Result<String> {
+    let text = command.text.trim();
+    let (subcommand, argument) = match text.split_once(char::is_whitespace) {
+        Some((subcommand, argument)) => (subcommand, argument.trim()),
+        None => (text, ""),
+    };
+    match subcommand {
+        "link" => return link(command, argument).await,
+        "unlink" => return unlink(command).await,
+        "whoami" => return whoami(command).await,
+        _ => {}
+    }
+
+    let schedule_name = text;
+    let schedules = if schedule_name.is_empty() {
+        let channel_id = command.channel_id.clone();
+        let notifications = web::block(move || {
+            let conn = db::connection();
+            db::get_oncalls_notified_in_channel(&conn, &channel_id)
+        })
+        .await??;
+        if notifications.is_empty() {
+            return Ok(format!(
+                "There's no on-call schedule set up for this channel. Try `{} <schedule name>`.",
+                command.command
+            ));
+        }
+        notifications
+            .into_iter()
+            .map(|notification| (notification.oncall_provider, notification.oncall_id))
+            .collect()
+    } else {
+        match data
+            .oncall_cache
+            .get_all()
+            .await?
+            .into_values()
+            .find(|oncall| {
+                oncall.id == schedule_name || oncall.name.eq_ignore_ascii_case(schedule_name)
+            }) {
+            Some(oncall) => vec![(oncall.provider, oncall.id)],
+            None => {
+                return Ok(format!(
+                    "I couldn't find an on-call schedule called `{}`.",
+                    schedule_name
+                ))
+            }
+        }
+    };
+
+    let answers = join_all(
+        schedules
+            .into_iter()
+            .map(|(oncall_provider, oncall_id)| describe(oncall_provider, oncall_id, data)),
+    )
+    .await
+    .into_iter()
+    .collect::<anyhow::Result<Vec<_>>>()?;
+    Ok(answers.join("\n\n"))
+}
+
+/// Who is on call for the schedule now and next.
+async fn describe(
+    oncall_provider: OncallProviderKind,
+    oncall_id: String,
+    data: &AppState,
+) -> anyhow::Result<String> {
+    let provider = oncall_provider.provider();
+    let (oncall_name, current_oncalls, next_oncalls) = join!(
+        data.oncall_cache.get(&oncall_id),
+        provider.get_current_oncalls(&oncall_id),
+        provider.get_next_oncalls(&oncall_id)
+    );
+    let oncall_name = match oncall_name? {
+        Some(oncall) => oncall.name,
+        None => oncall_id.clone(),
+    };
+    let current_oncalls = format_users(oncall_provider, current_oncalls?).await;
+    let next_oncalls = format_users(oncall_provider, next_oncalls?).await;
+
+    Ok(format!(
+        "*{}*\nOn call now: {}\nNext up: {}",
+        oncall_name, current_oncalls, next_oncalls
+    ))
+}
+
+/// Mentions every user that has a Slack mapping, and falls back to their name in the oncall
+/// provider otherwise. Users on call through a team or escalation are marked as such.
+pub async fn format_users(
+    oncall_provider: OncallProviderKind,
+    members: Vec<OncallMember>,
+) -> String {
+    if members.is_empty() {
+        return "nobody".into();
+    }
+
+    join_all(members.into_iter().map(|member| async move {
+        let user = format_user(oncall_provider, member.user_id).await;
+        match member.via {
+            Some(via) => format!("{} (via {})", user, via),
+            None => user,
+        }
+    }))
+    .await
+    .join(", ")
+}
+
+async fn format_user(oncall_provider: OncallProviderKind, user_id: String) -> String {
+    let user_id_clone = user_id.clone();
+    let user_mapping = web::block(move || {
+        let conn = db::connection();
+        db::get_opsgenie_user_mapping(&conn, oncall_provider, &user_id_clone)
+    })
+    .await;
+    if let Ok(Ok(Some(user_mapping))) = user_mapping {
+        return format!("<@{}>", user_mapping.slack_id);
+    }
+
+    match oncall_provider.provider().get_user(&user_id).await {
+        Ok(user) => user.full_name,
+        Err(_) => user_id,
+    }
+}
+
+/// Maps the Slack user to the oncall user with the same email. Only accounts with their email can be
+/// linked, so users can't claim someone else's shifts. When several providers have an account with
+/// their email, they name the one to link, e.g. by provider.
+async fn link(command: &SlashCommand, account: &str) -> anyhow::Result<String> {
+    if let Some(mapping) = get_slack_user_mapping(&command.user_id).await? {
+        return Ok(format!(
+            "You're already linked to {}. Run `{} unlink` first to link another account.",
+            describe_account(&mapping.opsgenie_id).await,
+            command.command
+        ));
+    }
+
+    let email = match slack::get_user(&command.user_id).await?.profile.email {
+        Some(email) => email,
+        None => {
+            return Ok(
+                "I can't see your email address in Slack, so I can't tell which on-call \
+                       account is yours. Ask an admin to link you."
+                    .to_string(),
+            )
+        }
+    };
+    let users = list_oncall_users().await?;
+    let candidates: Vec<&oncall_provider::User> = users
+        .iter()
+        .filter(|user| user.username.eq_ignore_ascii_case(&email))
+        .filter(|user| {
+            account.is_empty()
+                || user.id == account
+                || user.full_name.eq_ignore_ascii_case(account)
+                || user.provider.as_str().eq_ignore_ascii_case(account)
+                || user.provider.display_name().eq_ignore_ascii_case(account)
+        })
+        .collect();
+    let user = match candidates.as_slice() {
+        [] if account.is_empty() => {
+            return Ok(format!(
+                "I couldn't find an on-call account with your email {}. Ask an admin to link you \
+                 if you sign in to the on-call schedules with another one.",
+                email
+            ))
+        }
+        [] => {
+            return Ok(format!(
+                "None of the on-call accounts with your email {} is `{}`. Only accounts with your \
+                 email can be linked, ask an admin to link you to another one.",
+                email, account
+            ))
+        }
+        [user] => *user,
+        _ => {
+            return Ok(format!(
+                "Several on-call accounts have your email, run `{} link <provider>` with yours: {}",
+                command.command,
+                candidates
+                    .iter()
+                    .map(|user| format_account(user))
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ))
+        }
+    };
+
+    let opsgenie_id = user.id.clone();
+    let oncall_provider = user.provider;
+    let existing = web::block(move || {
+        let conn = db::connection();
+        db::get_opsgenie_user_mapping(&conn, oncall_provider, &opsgenie_id)
+    })
+    .await??;
+    if let Some(existing) = existing {
+        return Ok(format!(
+            "{} is already linked to <@{}>.",
+            format_account(user),
+            existing.slack_id
+        ));
+    }
+
+    let opsgenie_id = user.id.clone();
+    let slack_id = command.user_id.clone();
+    let oncall_provider = user.provider;
+    let user_mapping = web::block(move || {
+        let conn = db::connection();
+        db::add_user_mapping(&conn, oncall_provider, &opsgenie_id, &slack_id)
+    })
+    .await??;
+    audit::record(NewAuditEvent {
+        oncall_provider: Some(user.provider),
+        new_value: Some(format_user_mapping(&user_mapping)),
+        ..NewAuditEvent::new(&format!("slack:{}", command.user_id), "add_user_mapping")
+    })
+    .await;
+    info!(
+        "{} linked themselves to {} user {}",
+        command.user_id, user.provider, user.id
+    );
+    Ok(format!("Linked you to {}.", format_account(user)))
+}
+
+/// Removes the Slack user's mapping.
+async fn unlink(command: &SlashCommand) -> anyhow::Result<String> {
+    let mapping = match get_slack_user_mapping(&command.user_id).await? {
+        Some(mapping) => mapping,
+        None => return Ok("You aren't linked to an on-call account.".into()),
+    };
+    let account = describe_account(&mapping.opsgenie_id).await;
+    let removed = web::block(move || {
+        let conn = db::connection();
+        db::remove_user_mapping(&conn, mapping.id)
+    })
+    .await??;
+    audit::record(NewAuditEvent {
+        previous_value: Some(format_user_mapping(&removed)),
+        ..NewAuditEvent::new(&format!("slack:{}", command.user_id), "remove_user_mapping")
+    })
+    .await;
+    info!(
+        "{} unlinked themselves from {}",
+        command.user_id, removed.opsgenie_id
+    );
+    Ok(format!("Unlinked you from {}.", account))
+}
+
+/// Tells the Slack user which oncall user they're mapped to.
+async fn whoami(command: &SlashCommand) -> anyhow::Result<String> {
+    match get_slack_user_mapping(&command.user_id).await? {
+        Some(mapping) => Ok(format!(
+            "You're linked to {}.",
+            describe_account(&mapping.opsgenie_id).await
+        )),
+        None => Ok(format!(
+            "You aren't linked to an on-call account. Run `{} link` to link yours.",
+            command.command
+        )),
+    }
+}
+
+async fn get_slack_user_mapping(slack_id: &str) -> anyhow::Result<Option<UserMapping>> {
+    let slack_id = slack_id.to_string();
+    Ok(web::block(move || {
+        let conn = db::connection();
+        db::get_slack_user_mapping(&conn, &slack_id)
+    })
+    .await??)
+}
+
+/// Users of every configured oncall provider.
+async fn list_oncall_users() -> anyhow::Result<Vec<oncall_provider::User>> {
+    let users = join_all(
+        OncallProviderKind::configured()
+            .into_iter()
+            .map(|kind| kind.provider().list_users()),
+    )
+    .await
+    .into_iter()
+    .collect::<Result<Vec<_>, _>>()?;
+    Ok(users.into_iter().flatten().collect())
+}
+
+/// The mapped oncall user's name, or just their ID if no provider knows them anymore.
+async fn describe_account(user_id: &str) -> String {
+    for kind in OncallProviderKind::configured() {
+        if let Ok(user) = kind.provider().get_user(user_id).await {
+            return format_account(&user);
+        }
+    }
+    format!("`{}`", user_id)
+}
+
+fn format_account(user: &oncall_provider::User) -> String {
+    format!(
+        "*{}* ({}) in {}",
+        user.full_name,
+        user.username,
+        user.provider.display_name()
+    )
+}
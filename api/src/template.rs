@@ -0,0 +1,232 @@
+//! Per-notification templates for channel topics and announcements. Templates are plain text with
+//! `{placeholder}`s that are filled in on every reconcile, e.g. `Current oncall: {users}`.
+
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+pub const DEFAULT_TOPIC: &str = "Current oncall: {users}";
+pub const DEFAULT_MESSAGE: &str = "There's a new oncall! Please direct all questions to {users}";
+pub const DEFAULT_OUT_OF_HOURS: &str =
+    "This channel's oncall is out of hours. Please wait for the next oncall for urgent requests.";
+
+/// Separates the bot's part of a channel topic from whatever else is in it.
+pub const TOPIC_SEPARATOR: &str = " | ";
+
+/// Filled in for placeholders whose value couldn't be worked out, e.g. `{next}` when the
+/// provider failed to return the next oncall.
+const UNKNOWN: &str = "unknown";
+
+#[derive(thiserror::Error, Debug)]
+pub enum Error {
+    #[error("template is empty")]
+    Empty,
+    #[error("unknown placeholder {{{0}}}, expected one of {{users}}, {{schedule}}, {{next}}, {{until}} or {{user_group}}")]
+    UnknownPlaceholder(String),
+    #[error("\"{{\" without a closing \"}}\"")]
+    Unclosed,
+    #[error("\"}}\" without an opening \"{{\"")]
+    Unopened,
+    #[error("topic templates must start with text before the first placeholder, so the bot can find its part of the topic")]
+    MissingTopicPrefix,
+    #[error("topic templates can't contain \"{}\"", TOPIC_SEPARATOR)]
+    ContainsSeparator,
+}
+
+pub type Result<T = (), E = Error> = std::result::Result<T, E>;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Placeholder {
+    /// Mentions of the current oncalls
+    Users,
+    /// Name of the schedule
+    Schedule,
+    /// Mentions of the next oncalls
+    Next,
+    /// When the current shift ends
+    Until,
+    /// Mention of a user group synced with the schedule
+    UserGroup,
+}
+
+impl Placeholder {
+    pub const ALL: [Placeholder; 5] = [
+        Placeholder::Users,
+        Placeholder::Schedule,
+        Placeholder::Next,
+        Placeholder::Until,
+        Placeholder::UserGroup,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Placeholder::Users => "users",
+            Placeholder::Schedule => "schedule",
+            Placeholder::Next => "next",
+            Placeholder::Until => "until",
+            Placeholder::UserGroup => "user_group",
+        }
+    }
+}
+
+impl fmt::Display for Placeholder {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{{{}}}", self.as_str())
+    }
+}
+
+enum Part<'a> {
+    Text(&'a str),
+    Placeholder(Placeholder),
+}
+
+fn parse(template: &str) -> Result<Vec<Part<'_>>> {
+    let mut parts = vec![];
+    let mut rest = template;
+    while !rest.is_empty() {
+        match rest.find(['{', '}']) {
+            None => {
+                parts.push(Part::Text(rest));
+                break;
+            }
+            Some(start) if rest[start..].starts_with('}') => return Err(Error::Unopened),
+            Some(start) => {
+                if start > 0 {
+                    parts.push(Part::Text(&rest[..start]));
+                }
+                let end = start + rest[start..].find('}').ok_or(Error::Unclosed)?;
+                let name = &rest[start + 1..end];
+                let placeholder = Placeholder::ALL
+                    .into_iter()
+                    .find(|placeholder| placeholder.as_str() == name)
+                    .ok_or_else(|| Error::UnknownPlaceholder(name.to_string()))?;
+                parts.push(Part::Placeholder(placeholder));
+                rest = &rest[end + 1..];
+            }
+        }
+    }
+    Ok(parts)
+}
+
+/// Checks a message template.
+pub fn validate(template: &str) -> Result {
+    if template.trim().is_empty() {
+        return Err(Error::Empty);
+    }
+    parse(template).map(|_| ())
+}
+
+/// Checks a topic template. On top of being a valid template, the text before the first
+/// placeholder is how the bot recognises its part of the topic on the next reconcile.
+pub fn validate_topic(template: &str) -> Result {
+    validate(template)?;
+    if template.contains(TOPIC_SEPARATOR) {
+        return Err(Error::ContainsSeparator);
+    }
+    if topic_prefix(template).trim().is_empty() {
+        return Err(Error::MissingTopicPrefix);
+    }
+    Ok(())
+}
+
+/// The fixed text a topic template's part of the topic starts with.
+pub fn topic_prefix(template: &str) -> &str {
+    template.split('{').next().unwrap_or_default()
+}
+
+/// Whether the template contains the placeholder, so that values that are expensive to look up
+/// are only fetched when needed.
+pub fn uses(template: &str, placeholder: Placeholder) -> bool {
+    template.contains(&placeholder.to_string())
+}
+
+/// Values to fill placeholders with. Missing values are rendered as "unknown".
+#[derive(Debug, Clone, Default)]
+pub struct Values {
+    pub users: String,
+    pub schedule: Option<String>,
+    pub next: Option<String>,
+    pub until: Option<String>,
+    pub user_group: Option<String>,
+}
+
+impl Values {
+    fn get(&self, placeholder: Placeholder) -> &str {
+        match placeholder {
+            Placeholder::Users => &self.users,
+            Placeholder::Schedule => self.schedule.as_deref().unwrap_or(UNKNOWN),
+            Placeholder::Next => self.next.as_deref().unwrap_or(UNKNOWN),
+            Placeholder::Until => self.until.as_deref().unwrap_or(UNKNOWN),
+            Placeholder::UserGroup => self.user_group.as_deref().unwrap_or(UNKNOWN),
+        }
+    }
+}
+
+/// Fills in the template's placeholders. Values are inserted as-is rather than being parsed as
+/// templates themselves. Templates are validated when they're saved, but an invalid one is
+/// rendered verbatim rather than failing the notification.
+pub fn render(template: &str, values: &Values) -> String {
+    match parse(template) {
+        Ok(parts) => parts
+            .into_iter()
+            .map(|part| match part {
+                Part::Text(text) => text,
+                Part::Placeholder(placeholder) => values.get(placeholder),
+            })
+            .collect(),
+        Err(_) => template.to_string(),
+    }
+}
+
+/// The templates a notification uses. Unset templates fall back to the defaults above.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct Templates {
+    #[serde(default)]
+    pub topic_template: Option<String>,
+    #[serde(default)]
+    pub message_template: Option<String>,
+    #[serde(default)]
+    pub out_of_hours_template: Option<String>,
+}
+
+impl Templates {
+    pub fn topic(&self) -> &str {
+        self.topic_template.as_deref().unwrap_or(DEFAULT_TOPIC)
+    }
+
+    pub fn message(&self) -> &str {
+        self.message_template.as_deref().unwrap_or(DEFAULT_MESSAGE)
+    }
+
+    pub fn out_of_hours(&self) -> &str {
+        self.out_of_hours_template
+            .as_deref()
+            .unwrap_or(DEFAULT_OUT_OF_HOURS)
+    }
+
+    /// Whether any of the templates contains the placeholder.
+    pub fn uses(&self, placeholder: Placeholder) -> bool {
+        [self.topic(), self.message(), self.out_of_hours()]
+            .into_iter()
+            .any(|template| uses(template, placeholder))
+    }
+
+    /// Checks every template that's set, naming the one that's invalid.
+    pub fn validate(&self) -> Result<(), String> {
+        let fields = [
+            ("topic_template", &self.topic_template, true),
+            ("message_template", &self.message_template, false),
+            ("out_of_hours_template", &self.out_of_hours_template, false),
+        ];
+        for (field, template, is_topic) in fields {
+            if let Some(template) = template {
+                let result = if is_topic {
+                    validate_topic(template)
+                } else {
+                    validate(template)
+                };
+                result.map_err(|e| format!("invalid {}: {}", field, e))?;
+            }
+        }
+        Ok(())
+    }
+}
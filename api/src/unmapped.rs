@@ -0,0 +1,144 @@
+//! Warnings about people on call who have no user mapping. They're silently left out of user
+//! groups and channel topics, so admins get told in the channel set with `ADMIN_CHANNEL_ID`, once
+//! per person per shift.
+
+use crate::{
+    audit, chat_provider::ChatProviderKind, db, oncall_provider::OncallProvider,
+    scheduler::ScheduleKey,
+};
+use futures::future::join_all;
+use log::{info, warn};
+use std::env;
+
+/// Where admin warnings are posted.
+#[derive(Debug, Clone)]
+pub struct AdminChannel {
+    pub chat_provider: ChatProviderKind,
+    pub channel_id: String,
+}
+
+impl AdminChannel {
+    /// Reads the channel from `ADMIN_CHANNEL_ID`, in the chat provider named by
+    /// `ADMIN_CHAT_PROVIDER` (Slack by default). None when no channel is set.
+    pub fn from_env() -> Option<AdminChannel> {
+        let channel_id = env::var("ADMIN_CHANNEL_ID").ok()?;
+        let chat_provider = env::var("ADMIN_CHAT_PROVIDER")
+            .map(|chat_provider| {
+                chat_provider
+                    .parse()
+                    .expect("unable to parse ADMIN_CHAT_PROVIDER")
+            })
+            .unwrap_or_default();
+        Some(AdminChannel {
+            chat_provider,
+            channel_id,
+        })
+    }
+}
+
+/// Warns the admin channel about the schedule's current oncalls that have no user mapping, unless
+/// they were already warned about this shift. Relies on the shifts having been recorded first.
+/// Failures are only logged, and retried on the next poll.
+pub async fn warn_admins(schedule: &ScheduleKey, admin_channel: &AdminChannel, dry_run: bool) {
+    let schedule_clone = schedule.clone();
+    let shifts = match tokio::task::spawn_blocking(move || {
+        let connection = db::connection();
+        db::list_unmapped_shifts(
+            &connection,
+            Some((schedule_clone.oncall_provider, &schedule_clone.oncall_id)),
+        )
+    })
+    .await
+    {
+        Err(e) => {
+            warn!("Error listing unmapped oncalls of {:?}: {}", schedule, e);
+            return;
+        }
+        Ok(Err(e)) => {
+            warn!("Error listing unmapped oncalls of {:?}: {}", schedule, e);
+            return;
+        }
+        Ok(Ok(shifts)) => shifts,
+    };
+    let shifts: Vec<_> = shifts
+        .into_iter()
+        .filter(|shift| shift.unmapped_warned_at.is_none())
+        .collect();
+    if shifts.is_empty() {
+        return;
+    }
+
+    let provider = schedule.oncall_provider.provider();
+    let name = provider
+        .get_oncall_name(&schedule.oncall_id)
+        .await
+        .unwrap_or_else(|_| schedule.oncall_id.clone());
+    let users = join_all(
+        shifts
+            .iter()
+            .map(|shift| describe_user(provider, &shift.user_id)),
+    )
+    .await;
+    let (verb, has) = if users.len() == 1 {
+        ("is", "has")
+    } else {
+        ("are", "have")
+    };
+    let message = format!(
+        ":warning: {} {} on call for *{}* in {} but {} no user mapping, so they're left out of \
+         its user groups and channel topics. Map them with `/add_user_map` or ask them to run \
+         `/oncall link`.",
+        users.join(", "),
+        verb,
+        name,
+        schedule.oncall_provider.display_name(),
+        has
+    );
+    warn!(
+        "Unmapped oncalls for {} oncall {}: {}",
+        schedule.oncall_provider,
+        schedule.oncall_id,
+        users.join(", ")
+    );
+
+    if dry_run {
+        // Not recorded as warned, so the warning goes out once dry-run mode is turned off
+        info!(
+            "Dry run: not warning {} channel {}: {}",
+            admin_channel.chat_provider, admin_channel.channel_id, message
+        );
+        return;
+    }
+    if let Err(e) = admin_channel
+        .chat_provider
+        .provider()
+        .post_message(&admin_channel.channel_id, &message)
+        .await
+    {
+        warn!(
+            "Error warning {} channel {} about unmapped oncalls: {}",
+            admin_channel.chat_provider, admin_channel.channel_id, e
+        );
+        return;
+    }
+
+    let shift_ids: Vec<i32> = shifts.iter().map(|shift| shift.id).collect();
+    let result = tokio::task::spawn_blocking(move || {
+        let connection = db::connection();
+        db::set_unmapped_warned(&connection, &shift_ids, audit::now())
+    })
+    .await;
+    match result {
+        Err(e) => warn!("Error recording unmapped warning for {:?}: {}", schedule, e),
+        Ok(Err(e)) => warn!("Error recording unmapped warning for {:?}: {}", schedule, e),
+        Ok(Ok(())) => {}
+    }
+}
+
+/// The user's name and username in the oncall provider, or just their ID if they can't be found.
+async fn describe_user(provider: &dyn OncallProvider, user_id: &str) -> String {
+    match provider.get_user(user_id).await {
+        Ok(user) => format!("*{}* ({})", user.full_name, user.username),
+        Err(_) => format!("`{}`", user_id),
+    }
+}
@@ -1,116 +1,102 @@
-use crate::{db, opsgenie, slack};
-use log::{info, warn};
-use std::time::Duration;
-use tokio::{
-    sync::oneshot::{self, error::TryRecvError, Receiver, Sender},
-    time::sleep,
+use crate::{
+    audit, chat_provider,
+    models::NewAuditEvent,
+    preview::{Previews, UserGroupPreview},
+    scheduler::{ScheduleKey, UserGroupTarget},
 };
+use log::{info, warn};
 
-#[derive(Debug)]
-pub struct UserGroupSyncer {
-    oncall_id: String,
-    user_group_id: String,
-    stop_tx: Option<Sender<()>>,
-}
-
-impl UserGroupSyncer {
-    pub fn new(oncall_id: String, user_group_id: String) -> UserGroupSyncer {
-        let (stop_tx, stop_rx) = oneshot::channel();
-        let oncall_id_clone = oncall_id.clone();
-        let user_group_id_clone = user_group_id.clone();
-        tokio::spawn(async move {
-            user_group_sync(oncall_id_clone, user_group_id_clone, stop_rx).await
-        });
-        UserGroupSyncer {
-            stop_tx: Some(stop_tx),
-            user_group_id,
-            oncall_id,
-        }
-    }
-}
-
-impl Drop for UserGroupSyncer {
-    fn drop(&mut self) {
-        // Extract out `stop_tx` (which should never be none)
-        match std::mem::take(&mut self.stop_tx) {
-            Some(stop_tx) => {
-                // Sending a stop will be sufficient to stop the oncall syncer on the next iteration
-                if let Err(_) = stop_tx.send(()) {
-                    warn!("Syncer for oncall ID \"{}\" and user group ID \"{}\" failed to send stop. It's likely something went wrong with the syncer task.", self.oncall_id, self.user_group_id);
-                }
-            }
-            None => {
-                warn!("self.stop_tx for syncer with oncall ID \"{}\" and user group ID \"{}\" was none. Did you double-drop?", self.oncall_id, self.user_group_id);
-            }
-        }
-    }
-}
-
-async fn user_group_sync(oncall_id: String, user_group_id: String, mut stop_rx: Receiver<()>) {
-    let sleep_time = Duration::from_secs(60);
-    let mut first_iter = true;
-
-    loop {
-        // While putting the sleep at the end gets rid of this if, putting it here allows us to use
-        // continues to break flow cleanly and avoid relentless retries if there's an issue
-        if first_iter {
-            first_iter = false;
-        } else {
-            sleep(sleep_time).await;
-        }
+/// Sets the members of a user group to exactly the oncall users of the schedules backing it,
+/// recording the change in the audit log. `oncalls` has the current oncalls of each of those
+/// schedules, with chat user IDs. In dry-run mode the change is only stored in `previews`.
+/// Returns the error that kept the group from being synced, if any.
+pub async fn sync_user_group(
+    schedule: &ScheduleKey,
+    target: &UserGroupTarget,
+    oncalls: &[Vec<String>],
+    dry_run: bool,
+    previews: &Previews,
+) -> chat_provider::Result {
+    info!(
+        "Updating {} user_group_id {}",
+        target.chat_provider, target.user_group_id
+    );
+    let slack_users = &union(oncalls)[..];
+    let chat = target.chat_provider.provider();
 
-        // First, make sure we haven't gotten a stop message
-        match stop_rx.try_recv() {
-            // We've been asked to stop, or the process above is dead. Stop, so return immediately
-            Ok(_) | Err(TryRecvError::Closed) => {
-                return;
+    let (previous_users, members_result) =
+        match chat.get_user_group_members(&target.user_group_id).await {
+            Ok(mut previous_users) => {
+                previous_users.sort();
+                (Some(previous_users), Ok(()))
             }
-            // Do nothing if empty
-            Err(TryRecvError::Empty) => {}
-        };
-
-        info!(
-            "Updating oncall_id {} and user_group_id {}",
-            oncall_id, user_group_id
-        );
-
-        let current_oncalls = match opsgenie::get_current_oncalls(&oncall_id).await {
             Err(e) => {
                 warn!(
-                    "Error fetching current oncall data for {}: {}",
-                    oncall_id, e
+                    "Failed to fetch members of user group {}: {}",
+                    target.user_group_id, e
                 );
-                continue;
+                (None, Err(e))
             }
-            Ok(oncalls) => oncalls,
         };
 
-        // This filters out any users we don't have a mapping for
-        let tasks = current_oncalls.into_iter().map(|opsgenie_user_id| {
-            tokio::spawn(async move {
-                let connection = db::connection();
-                db::get_opsgenie_user_mapping(&connection, &opsgenie_user_id)
-            })
-        });
-        let slack_users = futures::future::join_all(tasks)
-            .await
-            .into_iter()
-            .filter_map(|user_mapping| match user_mapping {
-                Err(e) => {
-                    warn!("Error fetching user mapping: {}", e);
-                    None
-                }
-                Ok(Err(e)) => {
-                    warn!("Error fetching user mapping: {}", e);
-                    None
-                }
-                Ok(Ok(user_mapping)) => user_mapping.map(|user_mapping| user_mapping.slack_id),
+    if dry_run {
+        previews
+            .set_user_group(UserGroupPreview {
+                oncall_sync_id: target.oncall_sync_id,
+                oncall_provider: schedule.oncall_provider,
+                oncall_id: schedule.oncall_id.clone(),
+                chat_provider: target.chat_provider,
+                user_group_id: target.user_group_id.clone(),
+                current_users: previous_users,
+                new_users: slack_users.to_vec(),
+                computed_at: audit::now(),
             })
-            .collect::<Vec<_>>();
+            .await;
+        return members_result;
+    }
+
+    // Skip the update (and the audit entry) if the group is already in sync
+    let mut new_users = slack_users.to_vec();
+    new_users.sort();
+    if previous_users.as_ref() == Some(&new_users) {
+        return Ok(());
+    }
+
+    let result = chat
+        .set_user_group(&target.user_group_id, slack_users)
+        .await;
+    if let Err(e) = &result {
+        warn!(
+            "Failed to update user group {}: {}",
+            target.user_group_id, e
+        );
+    }
+
+    audit::record(
+        NewAuditEvent {
+            oncall_sync_id: Some(target.oncall_sync_id),
+            oncall_provider: Some(schedule.oncall_provider),
+            oncall_id: Some(schedule.oncall_id.clone()),
+            chat_provider: Some(target.chat_provider),
+            user_group_id: Some(target.user_group_id.clone()),
+            previous_value: previous_users.map(|users| audit::format_users(&users)),
+            new_value: Some(audit::format_users(slack_users)),
+            ..NewAuditEvent::new(audit::SYSTEM_ACTOR, "set_user_group")
+        }
+        .with_result(&result),
+    )
+    .await;
+    result
+}
 
-        // Finally, update slack's user group with the users that are left
-        if let Err(e) = slack::set_user_group(&user_group_id, &slack_users).await {
-            warn!("Failed to update user group {}: {}", user_group_id, e);
+/// Everyone on call for any of the schedules, in order and without duplicates, as the same person
+/// can be on call for several of them (e.g. handing over between follow-the-sun schedules).
+fn union(oncalls: &[Vec<String>]) -> Vec<String> {
+    let mut users: Vec<String> = vec![];
+    for user in oncalls.iter().flatten() {
+        if !users.contains(user) {
+            users.push(user.clone());
         }
     }
+    users
 }
@@ -0,0 +1,214 @@
+//! Harness booting the real app against a scratch SQLite DB and the fake Slack and OpsGenie APIs.
+//! Configuration is read from the environment, which is global to the process, so tests in the
+//! same binary take turns through a lock.
+
+#![allow(dead_code)]
+
+use actix_web::{http::StatusCode, test};
+use diesel::{connection::SimpleConnection, Connection, SqliteConnection};
+use hmac::{Hmac, Mac};
+use oncall_bot::{fake_apis::FakeApis, AppState};
+use serde_json::Value;
+use sha2::Sha256;
+use std::{
+    env, fs,
+    path::PathBuf,
+    process,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, OnceLock,
+    },
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+use tokio::sync::{Mutex, MutexGuard};
+
+pub const ADMIN_TOKEN: &str = "test-admin-token";
+pub const SLACK_SIGNING_SECRET: &str = "test-signing-secret";
+
+pub const SCHEDULE_ID: &str = "schedule-primary";
+pub const SCHEDULE_NAME: &str = "Primary";
+pub const OPSGENIE_USER_ID: &str = "opsgenie-alice";
+pub const SLACK_USER_ID: &str = "U0ALICE";
+pub const USER_GROUP_ID: &str = "S0ONCALL";
+pub const CHANNEL_ID: &str = "C0OPS";
+
+static LOCK: OnceLock<Mutex<()>> = OnceLock::new();
+static DB_COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+pub struct TestApp {
+    pub fake_apis: FakeApis,
+    state: Arc<AppState>,
+    db_path: PathBuf,
+    _lock: MutexGuard<'static, ()>,
+}
+
+impl TestApp {
+    /// Starts the app with an empty DB. The fake APIs know about one schedule, user, user group
+    /// and channel, see the constants above.
+    pub async fn start() -> TestApp {
+        let lock = LOCK.get_or_init(|| Mutex::new(())).lock().await;
+
+        let fake_apis = FakeApis::start().expect("error starting fake APIs");
+        fake_apis.add_schedule(SCHEDULE_ID, SCHEDULE_NAME);
+        fake_apis.add_opsgenie_user(OPSGENIE_USER_ID, "alice@example.com", "Alice");
+        fake_apis.set_oncalls(SCHEDULE_ID, &[OPSGENIE_USER_ID]);
+        fake_apis.add_slack_user(SLACK_USER_ID, "alice", "Alice");
+        fake_apis.add_user_group(USER_GROUP_ID, "On call", "oncall", &[]);
+        fake_apis.add_channel(CHANNEL_ID, "ops", "");
+
+        let db_path = env::temp_dir().join(format!(
+            "oncall_bot_test_{}_{}.db",
+            process::id(),
+            DB_COUNTER.fetch_add(1, Ordering::SeqCst)
+        ));
+        let _ = fs::remove_file(&db_path);
+        let database_url = db_path.to_str().expect("temp dir is not UTF-8").to_string();
+        migrate(&database_url);
+
+        env::set_var("DATABASE_URL", &database_url);
+        env::set_var("ADMIN_API_TOKEN", ADMIN_TOKEN);
+        env::set_var("SLACK_OAUTH_TOKEN", "xoxb-test");
+        env::set_var("SLACK_SIGNING_SECRET", SLACK_SIGNING_SECRET);
+        env::set_var("SLACK_API_URL", fake_apis.slack_url());
+        env::set_var("OPSGENIE_API_KEY", "test");
+        env::set_var("OPSGENIE_API_URL", fake_apis.opsgenie_url());
+
+        let state = Arc::new(AppState::new().await.expect("error creating app state"));
+        TestApp {
+            fake_apis,
+            state,
+            db_path,
+            _lock: lock,
+        }
+    }
+
+    /// Direct access to the DB, e.g. to break it.
+    pub fn db(&self) -> SqliteConnection {
+        SqliteConnection::establish(self.db_path.to_str().unwrap()).expect("error opening test DB")
+    }
+
+    pub async fn get(&self, uri: &str) -> (StatusCode, Value) {
+        self.send(test::TestRequest::get().uri(uri)).await
+    }
+
+    pub async fn post(&self, uri: &str, body: Value) -> (StatusCode, Value) {
+        self.send(test::TestRequest::post().uri(uri).set_json(body))
+            .await
+    }
+
+    /// Sends a form-encoded request the way Slack does, signed with `SLACK_SIGNING_SECRET` unless
+    /// `signed` is false.
+    pub async fn post_from_slack(&self, uri: &str, body: &str, signed: bool) -> StatusCode {
+        self.send_from_slack(uri, body, signed).await.0
+    }
+
+    /// Runs `/oncall <text>` in the test channel as the given Slack user and returns the reply.
+    pub async fn slash_command(&self, user_id: &str, text: &str) -> String {
+        let body = serde_urlencoded::to_string([
+            ("command", "/oncall"),
+            ("text", text),
+            ("channel_id", CHANNEL_ID),
+            ("user_id", user_id),
+        ])
+        .unwrap();
+        let (status, response) = self.send_from_slack("/slack/commands", &body, true).await;
+        assert_eq!(status, StatusCode::OK, "{}", response);
+        response["text"].as_str().unwrap_or_default().to_string()
+    }
+
+    async fn send_from_slack(&self, uri: &str, body: &str, signed: bool) -> (StatusCode, Value) {
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs()
+            .to_string();
+        let mut mac = Hmac::<Sha256>::new_from_slice(SLACK_SIGNING_SECRET.as_bytes()).unwrap();
+        mac.update(format!("v0:{}:{}", timestamp, body).as_bytes());
+        let signature = if signed {
+            format!("v0={}", hex::encode(mac.finalize().into_bytes()))
+        } else {
+            "v0=00".to_string()
+        };
+
+        let app = test::init_service(oncall_bot::app(self.state.clone())).await;
+        let req = test::TestRequest::post()
+            .uri(uri)
+            .insert_header(("Content-Type", "application/x-www-form-urlencoded"))
+            .insert_header(("X-Slack-Request-Timestamp", timestamp))
+            .insert_header(("X-Slack-Signature", signature))
+            .set_payload(body.to_string())
+            .to_request();
+        let res = test::call_service(&app, req).await;
+        let status = res.status();
+        let body = test::read_body(res).await;
+        (status, serde_json::from_slice(&body).unwrap_or(Value::Null))
+    }
+
+    async fn send(&self, req: test::TestRequest) -> (StatusCode, Value) {
+        let app = test::init_service(oncall_bot::app(self.state.clone())).await;
+        let res = test::call_service(
+            &app,
+            req.insert_header(("Authorization", format!("Bearer {}", ADMIN_TOKEN)))
+                .to_request(),
+        )
+        .await;
+        let status = res.status();
+        let body = test::read_body(res).await;
+        let body = if body.is_empty() {
+            Value::Null
+        } else {
+            serde_json::from_slice(&body).unwrap_or_else(|_| {
+                panic!("response is not JSON: {}", String::from_utf8_lossy(&body))
+            })
+        };
+        (status, body)
+    }
+}
+
+impl Drop for TestApp {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.db_path);
+    }
+}
+
+/// Applies every migration in order, the way `diesel migration run` would.
+fn migrate(database_url: &str) {
+    let conn = SqliteConnection::establish(database_url).expect("error creating test DB");
+    let mut migrations: Vec<_> = fs::read_dir(concat!(env!("CARGO_MANIFEST_DIR"), "/migrations"))
+        .expect("error reading migrations")
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.join("up.sql").exists())
+        .collect();
+    migrations.sort();
+    for migration in migrations {
+        let sql = fs::read_to_string(migration.join("up.sql")).unwrap();
+        conn.batch_execute(&sql)
+            .unwrap_or_else(|e| panic!("error applying {}: {}", migration.display(), e));
+    }
+}
+
+/// Waits for work done in the background, e.g. by the scheduler, to show up in the fake APIs.
+pub async fn eventually(what: &str, mut condition: impl FnMut() -> bool) {
+    for _ in 0..100 {
+        if condition() {
+            return;
+        }
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+    panic!("timed out waiting for {}", what);
+}
+
+/// The `error` of an `ErrorResponse`.
+pub fn error(body: &Value) -> &str {
+    body["error"].as_str().unwrap_or_default()
+}
+
+/// Text of the messages posted to the channel, or DMed to the user, in the fake chat API.
+pub fn messages_to(app: &TestApp, channel: &str) -> Vec<String> {
+    app.fake_apis
+        .messages()
+        .into_iter()
+        .filter(|message| message.channel == channel)
+        .map(|message| message.text)
+        .collect()
+}
@@ -0,0 +1,167 @@
+mod common;
+
+use actix_web::http::StatusCode;
+use common::{
+    eventually, TestApp, CHANNEL_ID, OPSGENIE_USER_ID, SCHEDULE_ID, SCHEDULE_NAME, SLACK_USER_ID,
+};
+use serde_json::{json, Value};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+const BOB_OPSGENIE_ID: &str = "opsgenie-bob";
+const BOB_SLACK_ID: &str = "U0BOB";
+
+async fn add_rich_notification(app: &TestApp) {
+    let (status, _) = app
+        .post(
+            "/add_user_map",
+            json!({"opsgenie_id": OPSGENIE_USER_ID, "slack_id": SLACK_USER_ID}),
+        )
+        .await;
+    assert_eq!(status, StatusCode::OK);
+    let (status, body) = app
+        .post(
+            "/notifications/add",
+            json!({
+                "oncall_id": SCHEDULE_ID,
+                "slack_channel_id": CHANNEL_ID,
+                "rich_messages": true,
+            }),
+        )
+        .await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+    assert_eq!(body["notification"]["rich_messages"], true);
+}
+
+fn click(action_id: &str, user_id: &str) -> String {
+    let payload = json!({
+        "type": "block_actions",
+        "user": {"id": user_id},
+        "channel": {"id": CHANNEL_ID},
+        "message": {"ts": "1.000000"},
+        "actions": [{
+            "action_id": action_id,
+            "value": format!("opsgenie:{}", SCHEDULE_ID),
+        }],
+    });
+    serde_urlencoded::to_string([("payload", payload.to_string())]).unwrap()
+}
+
+#[actix_web::test]
+async fn rich_announcement_has_avatars_and_buttons() {
+    let app = TestApp::start().await;
+    app.fake_apis
+        .set_slack_avatar(SLACK_USER_ID, "https://avatars.example.com/alice.png");
+    add_rich_notification(&app).await;
+
+    eventually("the announcement", || !app.fake_apis.messages().is_empty()).await;
+    let message = &app.fake_apis.messages()[0];
+    assert_eq!(
+        message.text,
+        format!(
+            "There's a new oncall! Please direct all questions to <@{}>",
+            SLACK_USER_ID
+        )
+    );
+    let blocks = message.blocks.as_ref().expect("announcement has no blocks");
+    let context = &blocks[1]["elements"];
+    assert_eq!(
+        context[0]["image_url"],
+        "https://avatars.example.com/alice.png"
+    );
+    assert_eq!(context[1]["text"], "*Alice*");
+    assert_eq!(context[2]["text"], format!("Schedule: *{}*", SCHEDULE_NAME));
+    let action_ids: Vec<&Value> = blocks[2]["elements"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|button| &button["action_id"])
+        .collect();
+    assert_eq!(
+        action_ids,
+        [
+            &json!("page_oncall"),
+            &json!("view_schedule"),
+            &json!("take_over")
+        ]
+    );
+}
+
+#[actix_web::test]
+async fn take_over_button_overrides_schedule_until_handoff() {
+    let app = TestApp::start().await;
+    app.fake_apis
+        .add_opsgenie_user(BOB_OPSGENIE_ID, "bob@example.com", "Bob");
+    app.fake_apis.add_slack_user(BOB_SLACK_ID, "bob", "Bob");
+    let handoff_at = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_secs() as i64
+        + 60 * 60;
+    app.fake_apis.set_handoff(SCHEDULE_ID, handoff_at);
+    let (status, _) = app
+        .post(
+            "/add_user_map",
+            json!({"opsgenie_id": BOB_OPSGENIE_ID, "slack_id": BOB_SLACK_ID}),
+        )
+        .await;
+    assert_eq!(status, StatusCode::OK);
+    add_rich_notification(&app).await;
+    eventually("the announcement", || !app.fake_apis.messages().is_empty()).await;
+
+    let status = app
+        .post_from_slack(
+            "/slack/interactivity",
+            &click("take_over", BOB_SLACK_ID),
+            true,
+        )
+        .await;
+    assert_eq!(status, StatusCode::OK);
+
+    eventually("the reply in the thread", || {
+        app.fake_apis
+            .messages()
+            .iter()
+            .any(|message| message.thread_ts.as_deref() == Some("1.000000"))
+    })
+    .await;
+    let overrides = app.fake_apis.overrides(SCHEDULE_ID);
+    assert_eq!(overrides.len(), 1);
+    assert_eq!(overrides[0]["user"]["id"], BOB_OPSGENIE_ID);
+    let reply = app
+        .fake_apis
+        .messages()
+        .into_iter()
+        .find(|message| message.thread_ts.is_some())
+        .unwrap();
+    assert!(
+        reply.text.starts_with(&format!(
+            "<@{}> took over the shift until <!date^{}^",
+            BOB_SLACK_ID, handoff_at
+        )),
+        "{}",
+        reply.text
+    );
+
+    // The override is picked up straight away
+    eventually("the new oncall to be announced", || {
+        app.fake_apis
+            .channel_topic(CHANNEL_ID)
+            .is_some_and(|topic| topic == format!("Current oncall: <@{}>", BOB_SLACK_ID))
+    })
+    .await;
+}
+
+#[actix_web::test]
+async fn unsigned_interactions_are_rejected() {
+    let app = TestApp::start().await;
+
+    let status = app
+        .post_from_slack(
+            "/slack/interactivity",
+            &click("take_over", SLACK_USER_ID),
+            false,
+        )
+        .await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+    assert!(app.fake_apis.overrides(SCHEDULE_ID).is_empty());
+}
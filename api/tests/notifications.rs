@@ -0,0 +1,435 @@
+mod common;
+
+use actix_web::http::StatusCode;
+use common::{
+    error, eventually, TestApp, CHANNEL_ID, OPSGENIE_USER_ID, SCHEDULE_ID, SCHEDULE_NAME,
+    SLACK_USER_ID,
+};
+use serde_json::json;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+#[actix_web::test]
+async fn add_list_and_remove_notification() {
+    let app = TestApp::start().await;
+
+    let (status, body) = app
+        .post(
+            "/notifications/add",
+            json!({"oncall_id": SCHEDULE_ID, "slack_channel_id": CHANNEL_ID}),
+        )
+        .await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+    let notification = &body["notification"];
+    assert_eq!(notification["oncall_name"], SCHEDULE_NAME);
+    assert_eq!(notification["slack_channel_name"], "ops");
+
+    let (status, list) = app.get("/notification/list").await;
+    assert_eq!(status, StatusCode::OK, "{}", list);
+    let notifications = list["notifications"].as_array().unwrap();
+    assert_eq!(notifications.len(), 1);
+    assert_eq!(notifications[0]["id"], notification["id"]);
+
+    let (_, body) = app
+        .get(&format!("/notifications/oncall?oncall_id={}", SCHEDULE_ID))
+        .await;
+    assert_eq!(
+        body["notifications"].as_array().unwrap().len(),
+        1,
+        "{}",
+        body
+    );
+    let (_, body) = app
+        .get(&format!(
+            "/notifications/slack?slack_channel_id={}",
+            CHANNEL_ID
+        ))
+        .await;
+    assert_eq!(body["notifications"][0]["id"], notification["id"]);
+
+    let (status, removed) = app
+        .post(
+            "/notifications/remove",
+            json!({"notification_id": notification["id"]}),
+        )
+        .await;
+    assert_eq!(status, StatusCode::OK, "{}", removed);
+
+    let (_, list) = app.get("/notification/list").await;
+    assert_eq!(list["notifications"], json!([]));
+}
+
+#[actix_web::test]
+async fn notification_sets_channel_topic() {
+    let app = TestApp::start().await;
+    let (status, _) = app
+        .post(
+            "/notifications/add",
+            json!({"oncall_id": SCHEDULE_ID, "slack_channel_id": CHANNEL_ID}),
+        )
+        .await;
+    assert_eq!(status, StatusCode::OK);
+
+    eventually("the channel topic to be set", || {
+        app.fake_apis
+            .channel_topic(CHANNEL_ID)
+            .is_some_and(|topic| !topic.is_empty())
+    })
+    .await;
+}
+
+#[actix_web::test]
+async fn announcement_names_group_in_rotation() {
+    let app = TestApp::start().await;
+    app.fake_apis.set_oncall_participants(
+        SCHEDULE_ID,
+        vec![json!({
+            "id": "escalation-critical",
+            "name": "Critical",
+            "type": "escalation",
+            "participants": [
+                {
+                    "id": "team-payments",
+                    "name": "Payments",
+                    "type": "team",
+                    "participants": [{"id": OPSGENIE_USER_ID, "type": "user"}],
+                },
+            ],
+        })],
+    );
+    let (status, _) = app
+        .post(
+            "/add_user_map",
+            json!({"opsgenie_id": OPSGENIE_USER_ID, "slack_id": SLACK_USER_ID}),
+        )
+        .await;
+    assert_eq!(status, StatusCode::OK);
+
+    let (status, _) = app
+        .post(
+            "/notifications/add",
+            json!({"oncall_id": SCHEDULE_ID, "slack_channel_id": CHANNEL_ID}),
+        )
+        .await;
+    assert_eq!(status, StatusCode::OK);
+
+    eventually("the new oncall to be announced", || {
+        !app.fake_apis.messages().is_empty()
+            && app
+                .fake_apis
+                .channel_topic(CHANNEL_ID)
+                .is_some_and(|topic| !topic.is_empty())
+    })
+    .await;
+    let message = &app.fake_apis.messages()[0];
+    assert_eq!(
+        message.text,
+        format!(
+            "There's a new oncall! Please direct all questions to <@{}> (via escalation Critical)",
+            SLACK_USER_ID
+        )
+    );
+    assert_eq!(
+        app.fake_apis.channel_topic(CHANNEL_ID).unwrap(),
+        format!("Current oncall: <@{}>", SLACK_USER_ID)
+    );
+}
+
+#[actix_web::test]
+async fn duplicate_notification_is_rejected() {
+    let app = TestApp::start().await;
+    let request = json!({"oncall_id": SCHEDULE_ID, "slack_channel_id": CHANNEL_ID});
+    let (status, _) = app.post("/notifications/add", request.clone()).await;
+    assert_eq!(status, StatusCode::OK);
+
+    let (status, body) = app.post("/notifications/add", request).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST, "{}", body);
+    assert_eq!(
+        error(&body),
+        format!(
+            "channel with ID {} is already notified for oncall {}",
+            CHANNEL_ID, SCHEDULE_ID
+        )
+    );
+}
+
+#[actix_web::test]
+async fn channel_shows_every_schedule_notified_in_it() {
+    let app = TestApp::start().await;
+    app.fake_apis.add_schedule("schedule-search", "Search");
+    app.fake_apis
+        .add_opsgenie_user("opsgenie-bob", "bob@example.com", "Bob");
+    app.fake_apis.add_slack_user("U0BOB", "bob", "Bob");
+    app.fake_apis
+        .set_oncalls("schedule-search", &["opsgenie-bob"]);
+    for (opsgenie_id, slack_id) in [(OPSGENIE_USER_ID, SLACK_USER_ID), ("opsgenie-bob", "U0BOB")] {
+        let (status, _) = app
+            .post(
+                "/add_user_map",
+                json!({"opsgenie_id": opsgenie_id, "slack_id": slack_id}),
+            )
+            .await;
+        assert_eq!(status, StatusCode::OK);
+    }
+
+    for oncall_id in [SCHEDULE_ID, "schedule-search"] {
+        let (status, body) = app
+            .post(
+                "/notifications/add",
+                json!({"oncall_id": oncall_id, "slack_channel_id": CHANNEL_ID}),
+            )
+            .await;
+        assert_eq!(status, StatusCode::OK, "{}", body);
+    }
+    let (_, body) = app
+        .get(&format!(
+            "/notifications/slack?slack_channel_id={}",
+            CHANNEL_ID
+        ))
+        .await;
+    let notifications = body["notifications"].as_array().unwrap();
+    assert_eq!(notifications.len(), 2, "{}", body);
+    assert_eq!(notifications[0]["oncall_id"], SCHEDULE_ID);
+    assert_eq!(notifications[1]["oncall_id"], "schedule-search");
+
+    let topic = format!(
+        "Current oncall: {}: <@{}>; Search: <@U0BOB>",
+        SCHEDULE_NAME, SLACK_USER_ID
+    );
+    eventually("both schedules to be in the topic", || {
+        app.fake_apis.channel_topic(CHANNEL_ID) == Some(topic.clone())
+    })
+    .await;
+    let messages = app.fake_apis.messages();
+    assert_eq!(
+        messages.last().unwrap().text,
+        format!(
+            "*{}*: There's a new oncall! Please direct all questions to <@{}>\n\
+             *Search*: There's a new oncall! Please direct all questions to <@U0BOB>",
+            SCHEDULE_NAME, SLACK_USER_ID
+        )
+    );
+
+    // Only the schedules whose part of the topic changed are announced
+    app.fake_apis.add_schedule("schedule-payments", "Payments");
+    let (status, body) = app
+        .post(
+            "/notifications/add",
+            json!({"oncall_id": "schedule-payments", "slack_channel_id": CHANNEL_ID}),
+        )
+        .await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+    let topic = format!("{}; Payments: nobody", topic);
+    eventually("the third schedule to be in the topic", || {
+        app.fake_apis.channel_topic(CHANNEL_ID) == Some(topic.clone())
+    })
+    .await;
+    assert_eq!(app.fake_apis.messages().len(), messages.len() + 1);
+    assert_eq!(
+        app.fake_apis.messages().last().unwrap().text,
+        "*Payments*: This channel's oncall is out of hours. Please wait for the next oncall for \
+         urgent requests."
+    );
+}
+
+#[actix_web::test]
+async fn removing_missing_notification_is_rejected() {
+    let app = TestApp::start().await;
+    let (status, body) = app
+        .post("/notifications/remove", json!({"notification_id": 42}))
+        .await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert_eq!(error(&body), "channel notification ID 42 does not exist");
+}
+
+#[actix_web::test]
+async fn topic_shows_next_oncall_and_handoff() {
+    let app = TestApp::start().await;
+    app.fake_apis
+        .add_opsgenie_user("opsgenie-bob", "bob@example.com", "Bob");
+    app.fake_apis.add_slack_user("U0BOB", "bob", "Bob");
+    app.fake_apis
+        .set_next_oncalls(SCHEDULE_ID, &["opsgenie-bob"]);
+    let handoff_at = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_secs() as i64
+        + 24 * 60 * 60;
+    app.fake_apis.set_handoff(SCHEDULE_ID, handoff_at);
+    for (opsgenie_id, slack_id) in [(OPSGENIE_USER_ID, SLACK_USER_ID), ("opsgenie-bob", "U0BOB")] {
+        let (status, _) = app
+            .post(
+                "/add_user_map",
+                json!({"opsgenie_id": opsgenie_id, "slack_id": slack_id}),
+            )
+            .await;
+        assert_eq!(status, StatusCode::OK);
+    }
+
+    let (status, body) = app
+        .post(
+            "/notifications/add",
+            json!({
+                "oncall_id": SCHEDULE_ID,
+                "slack_channel_id": CHANNEL_ID,
+                "show_next_oncall": true,
+            }),
+        )
+        .await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+    assert_eq!(body["notification"]["show_next_oncall"], true);
+
+    eventually("the next oncall to be shown", || {
+        app.fake_apis
+            .channel_topic(CHANNEL_ID)
+            .is_some_and(|topic| topic.contains("Next oncall: "))
+    })
+    .await;
+    let topic = app.fake_apis.channel_topic(CHANNEL_ID).unwrap();
+    assert!(
+        topic.starts_with(&format!(
+            "Current oncall: <@{}> | Next oncall: <@U0BOB> from <!date^{}^",
+            SLACK_USER_ID, handoff_at
+        )),
+        "{}",
+        topic
+    );
+}
+
+#[actix_web::test]
+async fn notification_uses_its_templates() {
+    let app = TestApp::start().await;
+    app.fake_apis.add_channel("C0OTHER", "other", "Welcome");
+    let (status, _) = app
+        .post(
+            "/add_user_map",
+            json!({"opsgenie_id": OPSGENIE_USER_ID, "slack_id": SLACK_USER_ID}),
+        )
+        .await;
+    assert_eq!(status, StatusCode::OK);
+
+    let (status, body) = app
+        .post(
+            "/notifications/add",
+            json!({
+                "oncall_id": SCHEDULE_ID,
+                "slack_channel_id": "C0OTHER",
+                "topic_template": "On call for {schedule}: {users}",
+                "message_template": "{users} is now on call for {schedule}",
+            }),
+        )
+        .await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+    assert_eq!(
+        body["notification"]["topic_template"],
+        "On call for {schedule}: {users}"
+    );
+    assert_eq!(body["notification"]["out_of_hours_template"], json!(null));
+
+    eventually("the templated announcement", || {
+        !app.fake_apis.messages().is_empty()
+            && app
+                .fake_apis
+                .channel_topic("C0OTHER")
+                .is_some_and(|topic| topic != "Welcome")
+    })
+    .await;
+    assert_eq!(
+        app.fake_apis.messages()[0].text,
+        format!("<@{}> is now on call for {}", SLACK_USER_ID, SCHEDULE_NAME)
+    );
+    assert_eq!(
+        app.fake_apis.channel_topic("C0OTHER").unwrap(),
+        format!(
+            "Welcome | On call for {}: <@{}>",
+            SCHEDULE_NAME, SLACK_USER_ID
+        )
+    );
+}
+
+#[actix_web::test]
+async fn invalid_templates_are_rejected() {
+    let app = TestApp::start().await;
+
+    for (field, template, message) in [
+        (
+            "topic_template",
+            "{users} are on call",
+            "invalid topic_template: topic templates must start with text before the first placeholder, so the bot can find its part of the topic",
+        ),
+        (
+            "topic_template",
+            "Oncall: {users} | more",
+            "invalid topic_template: topic templates can't contain \" | \"",
+        ),
+        (
+            "message_template",
+            "Hello {team}",
+            "invalid message_template: unknown placeholder {team}, expected one of {users}, {schedule}, {next}, {until} or {user_group}",
+        ),
+        (
+            "out_of_hours_template",
+            "Back at {until",
+            "invalid out_of_hours_template: \"{\" without a closing \"}\"",
+        ),
+    ] {
+        let (status, body) = app
+            .post(
+                "/notifications/add",
+                json!({
+                    "oncall_id": SCHEDULE_ID,
+                    "slack_channel_id": CHANNEL_ID,
+                    field: template,
+                }),
+            )
+            .await;
+        assert_eq!(status, StatusCode::BAD_REQUEST, "{}", body);
+        assert_eq!(error(&body), message);
+    }
+
+    let (_, list) = app.get("/notification/list").await;
+    assert_eq!(list["notifications"], json!([]));
+}
+
+#[actix_web::test]
+async fn templates_can_be_previewed() {
+    let app = TestApp::start().await;
+    let (status, _) = app
+        .post(
+            "/add_user_map",
+            json!({"opsgenie_id": OPSGENIE_USER_ID, "slack_id": SLACK_USER_ID}),
+        )
+        .await;
+    assert_eq!(status, StatusCode::OK);
+
+    let (status, body) = app
+        .post(
+            "/notifications/preview_templates",
+            json!({
+                "oncall_id": SCHEDULE_ID,
+                "topic_template": "Oncall for {schedule}: {users}",
+                "out_of_hours_template": "Nobody is on call for {schedule}",
+            }),
+        )
+        .await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+    assert_eq!(
+        body,
+        json!({
+            "topic": format!("Oncall for Primary: <@{}>", SLACK_USER_ID),
+            "message": format!(
+                "There's a new oncall! Please direct all questions to <@{}>",
+                SLACK_USER_ID
+            ),
+            "out_of_hours_message": "Nobody is on call for Primary",
+        })
+    );
+    assert!(app.fake_apis.messages().is_empty());
+
+    let (status, body) = app
+        .post(
+            "/notifications/preview_templates",
+            json!({"oncall_id": SCHEDULE_ID, "topic_template": "{users}"}),
+        )
+        .await;
+    assert_eq!(status, StatusCode::BAD_REQUEST, "{}", body);
+}
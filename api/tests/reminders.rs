@@ -0,0 +1,75 @@
+mod common;
+
+use actix_web::http::StatusCode;
+use common::{eventually, messages_to, TestApp, CHANNEL_ID, SCHEDULE_ID, SCHEDULE_NAME};
+use serde_json::json;
+use std::{
+    env,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+const BOB_OPSGENIE_ID: &str = "opsgenie-bob";
+const BOB_SLACK_ID: &str = "U0BOB";
+
+#[actix_web::test]
+async fn upcoming_shift_is_reminded_once() {
+    // Remind an hour ahead, checking every second
+    env::set_var("SHIFT_REMINDERS", "1h");
+    env::set_var("REMINDER_INTERVAL_SECS", "1");
+    let app = TestApp::start().await;
+    app.fake_apis
+        .add_opsgenie_user(BOB_OPSGENIE_ID, "bob@example.com", "Bob");
+    app.fake_apis.add_slack_user(BOB_SLACK_ID, "bob", "Bob");
+    let (status, _) = app
+        .post(
+            "/add_user_map",
+            json!({"opsgenie_id": BOB_OPSGENIE_ID, "slack_id": BOB_SLACK_ID}),
+        )
+        .await;
+    assert_eq!(status, StatusCode::OK);
+
+    // Bob's override starts just over an hour from now, so the reminder is due in a few seconds
+    let start = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_secs() as i64
+        + 60 * 60
+        + 3;
+    app.fake_apis
+        .add_timeline_period(SCHEDULE_ID, BOB_OPSGENIE_ID, start, start + 60 * 60, true);
+    let (status, body) = app
+        .post(
+            "/notifications/add",
+            json!({"oncall_id": SCHEDULE_ID, "slack_channel_id": CHANNEL_ID}),
+        )
+        .await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+
+    eventually("the reminder", || {
+        !messages_to(&app, BOB_SLACK_ID).is_empty()
+    })
+    .await;
+    let reminder = &messages_to(&app, BOB_SLACK_ID)[0];
+    assert!(
+        reminder.starts_with(&format!(
+            "Heads up: your shift on *{}* starts in ",
+            SCHEDULE_NAME
+        )),
+        "{}",
+        reminder
+    );
+    assert!(
+        reminder.contains(&format!(", at <!date^{}^", start)),
+        "{}",
+        reminder
+    );
+    assert!(
+        reminder.ends_with("It's an override, not part of the regular rotation."),
+        "{}",
+        reminder
+    );
+
+    // Later runs find the reminder already sent
+    tokio::time::sleep(Duration::from_secs(3)).await;
+    assert_eq!(messages_to(&app, BOB_SLACK_ID).len(), 1);
+}
@@ -0,0 +1,120 @@
+mod common;
+
+use actix_web::http::StatusCode;
+use common::{
+    eventually, messages_to, TestApp, CHANNEL_ID, OPSGENIE_USER_ID, SCHEDULE_ID, SCHEDULE_NAME,
+    SLACK_USER_ID,
+};
+use serde_json::{json, Value};
+use std::{
+    env,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+const BOB_OPSGENIE_ID: &str = "opsgenie-bob";
+const BOB_SLACK_ID: &str = "U0BOB";
+
+/// Turns on shift DMs and polls every second so handoffs are noticed quickly. Every test in this
+/// binary wants the same settings, so they can be set before taking the harness lock.
+async fn start() -> TestApp {
+    env::set_var("SHIFT_DMS", "true");
+    env::set_var("POLL_INTERVAL_SECS", "1");
+    env::set_var("POLL_JITTER_SECS", "0");
+    let app = TestApp::start().await;
+    app.fake_apis
+        .add_opsgenie_user(BOB_OPSGENIE_ID, "bob@example.com", "Bob");
+    app.fake_apis.add_slack_user(BOB_SLACK_ID, "bob", "Bob");
+    app
+}
+
+async fn add_user_map(app: &TestApp, opsgenie_id: &str, slack_id: &str) -> Value {
+    let (status, body) = app
+        .post(
+            "/add_user_map",
+            json!({"opsgenie_id": opsgenie_id, "slack_id": slack_id}),
+        )
+        .await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+    body
+}
+
+/// Starts notifying the channel and waits for the first reconcile to see Alice on call.
+async fn watch_schedule(app: &TestApp) {
+    let (status, body) = app
+        .post(
+            "/notifications/add",
+            json!({"oncall_id": SCHEDULE_ID, "slack_channel_id": CHANNEL_ID}),
+        )
+        .await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+    eventually("the channel topic to be set", || {
+        app.fake_apis.channel_topic(CHANNEL_ID).as_deref()
+            == Some(&format!("Current oncall: <@{}>", SLACK_USER_ID)[..])
+    })
+    .await;
+}
+
+#[actix_web::test]
+async fn handoff_sends_shift_dms() {
+    let app = start().await;
+    add_user_map(&app, OPSGENIE_USER_ID, SLACK_USER_ID).await;
+    add_user_map(&app, BOB_OPSGENIE_ID, BOB_SLACK_ID).await;
+    watch_schedule(&app).await;
+
+    let handoff_at = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_secs() as i64
+        + 7 * 24 * 60 * 60;
+    app.fake_apis.set_handoff(SCHEDULE_ID, handoff_at);
+    app.fake_apis.set_oncalls(SCHEDULE_ID, &[BOB_OPSGENIE_ID]);
+
+    eventually("the outgoing oncall's DM", || {
+        !messages_to(&app, SLACK_USER_ID).is_empty()
+    })
+    .await;
+    let welcome = messages_to(&app, BOB_SLACK_ID);
+    assert_eq!(welcome.len(), 1);
+    assert!(
+        welcome[0].starts_with(&format!(
+            "You're now on call for *{}* until <!date^{}^",
+            SCHEDULE_NAME, handoff_at
+        )),
+        "{}",
+        welcome[0]
+    );
+    assert!(welcome[0].ends_with("; open alerts: 0"), "{}", welcome[0]);
+    // Alice was already on call when the schedule was first seen, so she was never welcomed
+    assert_eq!(
+        messages_to(&app, SLACK_USER_ID),
+        [format!(
+            "Your shift on *{}* has ended after 0m. Handed over to <@{}>; open alerts: 0",
+            SCHEDULE_NAME, BOB_SLACK_ID
+        )]
+    );
+}
+
+#[actix_web::test]
+async fn opted_out_users_get_no_shift_dms() {
+    let app = start().await;
+    add_user_map(&app, OPSGENIE_USER_ID, SLACK_USER_ID).await;
+    let bob = add_user_map(&app, BOB_OPSGENIE_ID, BOB_SLACK_ID).await;
+    let (status, body) = app
+        .post(
+            "/set_user_map_shift_dms_opt_out",
+            json!({"user_mapping_id": bob["id"], "shift_dms_opt_out": true}),
+        )
+        .await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+    assert_eq!(body["shift_dms_opt_out"], true);
+    watch_schedule(&app).await;
+
+    app.fake_apis.set_oncalls(SCHEDULE_ID, &[BOB_OPSGENIE_ID]);
+
+    // Incoming oncalls are messaged first, so Bob would have had his DM by now
+    eventually("the outgoing oncall's DM", || {
+        !messages_to(&app, SLACK_USER_ID).is_empty()
+    })
+    .await;
+    assert!(messages_to(&app, BOB_SLACK_ID).is_empty());
+}
@@ -0,0 +1,198 @@
+mod common;
+
+use actix_web::http::StatusCode;
+use common::{
+    error, eventually, TestApp, OPSGENIE_USER_ID, SCHEDULE_ID, SCHEDULE_NAME, SLACK_USER_ID,
+    USER_GROUP_ID,
+};
+use serde_json::json;
+
+#[actix_web::test]
+async fn add_list_and_remove_sync() {
+    let app = TestApp::start().await;
+
+    let (status, user_group) = app
+        .post(
+            "/add_sync",
+            json!({"oncall_ids": [SCHEDULE_ID], "user_group_id": USER_GROUP_ID}),
+        )
+        .await;
+    assert_eq!(status, StatusCode::OK, "{}", user_group);
+    assert_eq!(user_group["user_group_id"], USER_GROUP_ID);
+    assert_eq!(user_group["chat_provider"], "slack");
+    let sync = &user_group["syncs"][0];
+    assert_eq!(sync["oncall_id"], SCHEDULE_ID);
+    assert_eq!(sync["oncall_provider"], "opsgenie");
+
+    let (status, list) = app.get("/list_syncs").await;
+    assert_eq!(status, StatusCode::OK, "{}", list);
+    let user_groups = list["user_groups"].as_array().unwrap();
+    assert_eq!(user_groups.len(), 1);
+    assert_eq!(user_groups[0]["user_group_handle"], "oncall");
+    let syncs = user_groups[0]["syncs"].as_array().unwrap();
+    assert_eq!(syncs.len(), 1);
+    assert_eq!(syncs[0]["id"], sync["id"]);
+    assert_eq!(syncs[0]["oncall_name"], SCHEDULE_NAME);
+
+    let (status, removed) = app
+        .post("/remove_sync", json!({"oncall_sync_id": sync["id"]}))
+        .await;
+    assert_eq!(status, StatusCode::OK, "{}", removed);
+    assert_eq!(removed["id"], sync["id"]);
+
+    let (_, list) = app.get("/list_syncs").await;
+    assert_eq!(list["user_groups"], json!([]));
+}
+
+#[actix_web::test]
+async fn sync_updates_user_group() {
+    let app = TestApp::start().await;
+    let (status, _) = app
+        .post(
+            "/add_user_map",
+            json!({"opsgenie_id": OPSGENIE_USER_ID, "slack_id": SLACK_USER_ID}),
+        )
+        .await;
+    assert_eq!(status, StatusCode::OK);
+
+    let (status, _) = app
+        .post(
+            "/add_sync",
+            json!({"oncall_ids": [SCHEDULE_ID], "user_group_id": USER_GROUP_ID}),
+        )
+        .await;
+    assert_eq!(status, StatusCode::OK);
+
+    eventually("the user group to be updated", || {
+        app.fake_apis.user_group_members(USER_GROUP_ID) == Some(vec![SLACK_USER_ID.to_string()])
+    })
+    .await;
+}
+
+#[actix_web::test]
+async fn user_group_gets_everyone_on_call_for_its_schedules() {
+    let app = TestApp::start().await;
+    app.fake_apis.add_schedule("schedule-us", "US");
+    app.fake_apis
+        .add_opsgenie_user("opsgenie-bob", "bob@example.com", "Bob");
+    app.fake_apis.add_slack_user("U0BOB", "bob", "Bob");
+    app.fake_apis
+        .set_oncalls("schedule-us", &["opsgenie-bob", OPSGENIE_USER_ID]);
+    for (opsgenie_id, slack_id) in [(OPSGENIE_USER_ID, SLACK_USER_ID), ("opsgenie-bob", "U0BOB")] {
+        let (status, _) = app
+            .post(
+                "/add_user_map",
+                json!({"opsgenie_id": opsgenie_id, "slack_id": slack_id}),
+            )
+            .await;
+        assert_eq!(status, StatusCode::OK);
+    }
+
+    let (status, user_group) = app
+        .post(
+            "/add_sync",
+            json!({"oncall_ids": [SCHEDULE_ID, "schedule-us"], "user_group_id": USER_GROUP_ID}),
+        )
+        .await;
+    assert_eq!(status, StatusCode::OK, "{}", user_group);
+    let syncs = user_group["syncs"].as_array().unwrap();
+    assert_eq!(syncs.len(), 2, "{}", user_group);
+
+    eventually("both schedules' oncalls to be synced", || {
+        app.fake_apis.user_group_members(USER_GROUP_ID)
+            == Some(vec![SLACK_USER_ID.to_string(), "U0BOB".to_string()])
+    })
+    .await;
+
+    let (status, _) = app
+        .post("/remove_sync", json!({"oncall_sync_id": syncs[1]["id"]}))
+        .await;
+    assert_eq!(status, StatusCode::OK);
+    eventually("the removed schedule's oncalls to leave", || {
+        app.fake_apis.user_group_members(USER_GROUP_ID) == Some(vec![SLACK_USER_ID.to_string()])
+    })
+    .await;
+}
+
+#[actix_web::test]
+async fn sync_expands_teams_in_rotation() {
+    let app = TestApp::start().await;
+    app.fake_apis
+        .add_team("team-payments", "Payments", &[OPSGENIE_USER_ID]);
+    app.fake_apis.set_oncall_participants(
+        SCHEDULE_ID,
+        vec![json!({"id": "team-payments", "name": "Payments", "type": "team"})],
+    );
+    let (status, _) = app
+        .post(
+            "/add_user_map",
+            json!({"opsgenie_id": OPSGENIE_USER_ID, "slack_id": SLACK_USER_ID}),
+        )
+        .await;
+    assert_eq!(status, StatusCode::OK);
+
+    let (status, _) = app
+        .post(
+            "/add_sync",
+            json!({"oncall_ids": [SCHEDULE_ID], "user_group_id": USER_GROUP_ID}),
+        )
+        .await;
+    assert_eq!(status, StatusCode::OK);
+
+    eventually("the team's members to be synced", || {
+        app.fake_apis.user_group_members(USER_GROUP_ID) == Some(vec![SLACK_USER_ID.to_string()])
+    })
+    .await;
+}
+
+#[actix_web::test]
+async fn duplicate_sync_is_rejected() {
+    let app = TestApp::start().await;
+    let request = json!({"oncall_ids": [SCHEDULE_ID], "user_group_id": USER_GROUP_ID});
+    let (status, _) = app.post("/add_sync", request.clone()).await;
+    assert_eq!(status, StatusCode::OK);
+
+    let (status, body) = app.post("/add_sync", request).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert!(error(&body).contains("already exists"), "{}", body);
+
+    let (_, list) = app.get("/list_syncs").await;
+    assert_eq!(list["user_groups"][0]["syncs"].as_array().unwrap().len(), 1);
+}
+
+#[actix_web::test]
+async fn removing_missing_sync_is_rejected() {
+    let app = TestApp::start().await;
+    let (status, body) = app
+        .post("/remove_sync", json!({"oncall_sync_id": 42}))
+        .await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert_eq!(error(&body), "Oncall sync with ID 42 does not exist");
+}
+
+#[actix_web::test]
+async fn sync_with_unknown_schedule_or_user_group_is_not_found() {
+    let app = TestApp::start().await;
+
+    let (status, body) = app
+        .post(
+            "/add_sync",
+            json!({"oncall_ids": ["no-such-schedule"], "user_group_id": USER_GROUP_ID}),
+        )
+        .await;
+    assert_eq!(status, StatusCode::NOT_FOUND, "{}", body);
+
+    let (status, body) = app
+        .post(
+            "/add_sync",
+            json!({"oncall_ids": [SCHEDULE_ID], "user_group_id": "S0MISSING"}),
+        )
+        .await;
+    assert_eq!(status, StatusCode::NOT_FOUND, "{}", body);
+
+    let (_, list) = app.get("/list_syncs").await;
+    assert_eq!(list["user_groups"], json!([]));
+
+    let (status, body) = app.get("/synced_with?oncall_id=no-such-schedule").await;
+    assert_eq!(status, StatusCode::NOT_FOUND, "{}", body);
+}
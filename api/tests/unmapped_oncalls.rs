@@ -0,0 +1,62 @@
+mod common;
+
+use actix_web::http::StatusCode;
+use common::{
+    eventually, messages_to, TestApp, CHANNEL_ID, OPSGENIE_USER_ID, SCHEDULE_ID, SLACK_USER_ID,
+};
+use serde_json::json;
+use std::{env, time::Duration};
+
+const ADMIN_CHANNEL_ID: &str = "C0ADMINS";
+
+#[actix_web::test]
+async fn unmapped_oncall_is_listed_and_admins_are_warned_once() {
+    env::set_var("ADMIN_CHANNEL_ID", ADMIN_CHANNEL_ID);
+    env::set_var("POLL_INTERVAL_SECS", "1");
+    env::set_var("POLL_JITTER_SECS", "0");
+    let app = TestApp::start().await;
+    app.fake_apis
+        .add_channel(ADMIN_CHANNEL_ID, "oncall-admins", "");
+
+    let (status, body) = app
+        .post(
+            "/notifications/add",
+            json!({"oncall_id": SCHEDULE_ID, "slack_channel_id": CHANNEL_ID}),
+        )
+        .await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+
+    eventually("the admin warning", || {
+        !messages_to(&app, ADMIN_CHANNEL_ID).is_empty()
+    })
+    .await;
+    let warning = &messages_to(&app, ADMIN_CHANNEL_ID)[0];
+    assert!(
+        warning.contains("*Alice* (alice@example.com) is on call for *Primary*"),
+        "{}",
+        warning
+    );
+
+    let (status, list) = app.get("/unmapped_oncalls").await;
+    assert_eq!(status, StatusCode::OK, "{}", list);
+    let unmapped = list["unmapped_oncalls"].as_array().unwrap();
+    assert_eq!(unmapped.len(), 1, "{}", list);
+    assert_eq!(unmapped[0]["user_id"], OPSGENIE_USER_ID);
+    assert_eq!(unmapped[0]["user_name"], "Alice");
+    assert_eq!(unmapped[0]["oncall_name"], "Primary");
+    assert!(unmapped[0]["warned_at"].is_i64(), "{}", list);
+
+    // Later polls during the same shift don't warn again
+    tokio::time::sleep(Duration::from_secs(3)).await;
+    assert_eq!(messages_to(&app, ADMIN_CHANNEL_ID).len(), 1);
+
+    let (status, _) = app
+        .post(
+            "/add_user_map",
+            json!({"opsgenie_id": OPSGENIE_USER_ID, "slack_id": SLACK_USER_ID}),
+        )
+        .await;
+    assert_eq!(status, StatusCode::OK);
+    let (_, list) = app.get("/unmapped_oncalls").await;
+    assert_eq!(list["unmapped_oncalls"], json!([]));
+}
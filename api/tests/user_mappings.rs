@@ -0,0 +1,234 @@
+mod common;
+
+use actix_web::http::StatusCode;
+use common::{error, TestApp, OPSGENIE_USER_ID, SLACK_USER_ID};
+use diesel::connection::SimpleConnection;
+use serde_json::json;
+
+#[actix_web::test]
+async fn add_list_and_remove_user_mapping() {
+    let app = TestApp::start().await;
+
+    let (status, user_mapping) = app
+        .post(
+            "/add_user_map",
+            json!({"opsgenie_id": OPSGENIE_USER_ID, "slack_id": SLACK_USER_ID}),
+        )
+        .await;
+    assert_eq!(status, StatusCode::OK, "{}", user_mapping);
+    assert_eq!(user_mapping["opsgenie_id"], OPSGENIE_USER_ID);
+    assert_eq!(user_mapping["slack_id"], SLACK_USER_ID);
+
+    let (status, list) = app.get("/list_user_mappings").await;
+    assert_eq!(status, StatusCode::OK, "{}", list);
+    let user_mappings = list["user_mappings"].as_array().unwrap();
+    assert_eq!(user_mappings.len(), 1);
+    assert_eq!(user_mappings[0]["id"], user_mapping["id"]);
+    assert_eq!(user_mappings[0]["oncall_provider"], "opsgenie");
+
+    let (status, body) = app
+        .get(&format!(
+            "/get_slack_user_mapping?slack_user_id={}",
+            SLACK_USER_ID
+        ))
+        .await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+    assert_eq!(body["user_mapping"]["id"], user_mapping["id"]);
+
+    let (status, removed) = app
+        .post(
+            "/remove_user_map",
+            json!({"user_mapping_id": user_mapping["id"]}),
+        )
+        .await;
+    assert_eq!(status, StatusCode::OK, "{}", removed);
+
+    let (_, list) = app.get("/list_user_mappings").await;
+    assert_eq!(list["user_mappings"], json!([]));
+}
+
+#[actix_web::test]
+async fn duplicate_user_mapping_is_rejected() {
+    let app = TestApp::start().await;
+    let request = json!({"opsgenie_id": OPSGENIE_USER_ID, "slack_id": SLACK_USER_ID});
+    let (status, _) = app.post("/add_user_map", request.clone()).await;
+    assert_eq!(status, StatusCode::OK);
+
+    let (status, body) = app.post("/add_user_map", request).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert!(error(&body).contains("already"), "{}", body);
+}
+
+#[actix_web::test]
+async fn removing_missing_user_mapping_is_rejected() {
+    let app = TestApp::start().await;
+    let (status, body) = app
+        .post("/remove_user_map", json!({"user_mapping_id": 42}))
+        .await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert_eq!(error(&body), "user mapping with ID 42 does not exist");
+}
+
+#[actix_web::test]
+async fn user_mapping_for_unknown_slack_user_is_not_found() {
+    let app = TestApp::start().await;
+    let (status, body) = app
+        .post(
+            "/add_user_map",
+            json!({"opsgenie_id": OPSGENIE_USER_ID, "slack_id": "U0MISSING"}),
+        )
+        .await;
+    assert_eq!(status, StatusCode::NOT_FOUND, "{}", body);
+}
+
+#[actix_web::test]
+async fn query_errors_are_internal_server_errors() {
+    let app = TestApp::start().await;
+    app.db().batch_execute("DROP TABLE user_mapping").unwrap();
+
+    let (status, body) = app.get("/list_user_mappings").await;
+    assert_eq!(status, StatusCode::INTERNAL_SERVER_ERROR);
+    assert_eq!(error(&body), "error making a query");
+}
+
+#[actix_web::test]
+async fn auto_mapping_dry_run_reports_without_mapping() {
+    let app = TestApp::start().await;
+    app.fake_apis
+        .add_opsgenie_user("opsgenie-carol", "carol@example.com", "Carol");
+
+    let (status, report) = app
+        .post("/user_mappings/auto", json!({"dry_run": true}))
+        .await;
+    assert_eq!(status, StatusCode::OK, "{}", report);
+    assert_eq!(report["dry_run"], true);
+    let created = report["created"].as_array().unwrap();
+    assert_eq!(created.len(), 1, "{}", report);
+    assert_eq!(created[0]["opsgenie_id"], OPSGENIE_USER_ID);
+    assert_eq!(created[0]["slack_id"], SLACK_USER_ID);
+    assert_eq!(created[0]["email"], "alice@example.com");
+    assert_eq!(report["unmatched"][0]["id"], "opsgenie-carol");
+
+    let (_, list) = app.get("/list_user_mappings").await;
+    assert_eq!(list["user_mappings"], json!([]));
+}
+
+#[actix_web::test]
+async fn auto_mapping_creates_missing_mappings_once() {
+    let app = TestApp::start().await;
+    app.fake_apis
+        .add_opsgenie_user("opsgenie-bob", "Bob@Example.com", "Bob");
+    app.fake_apis.add_slack_user("U0BOB", "bob", "Bob");
+
+    let (status, report) = app.post("/user_mappings/auto", json!({})).await;
+    assert_eq!(status, StatusCode::OK, "{}", report);
+    assert_eq!(report["created"].as_array().unwrap().len(), 2, "{}", report);
+    assert_eq!(report["errors"], json!([]));
+
+    let (_, list) = app.get("/list_user_mappings").await;
+    let mut mapped: Vec<(String, String)> = list["user_mappings"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|mapping| {
+            (
+                mapping["opsgenie_user_id"].as_str().unwrap().to_string(),
+                mapping["slack_user_id"].as_str().unwrap().to_string(),
+            )
+        })
+        .collect();
+    mapped.sort();
+    assert_eq!(
+        mapped,
+        vec![
+            (OPSGENIE_USER_ID.to_string(), SLACK_USER_ID.to_string()),
+            ("opsgenie-bob".to_string(), "U0BOB".to_string()),
+        ]
+    );
+
+    let (status, report) = app.post("/user_mappings/auto", json!({})).await;
+    assert_eq!(status, StatusCode::OK, "{}", report);
+    assert_eq!(report["created"], json!([]));
+}
+
+#[actix_web::test]
+async fn auto_mapping_flags_chat_users_mapped_to_someone_else() {
+    let app = TestApp::start().await;
+    app.fake_apis
+        .add_opsgenie_user("opsgenie-alice-old", "alice.old@example.com", "Alice");
+    let (status, _) = app
+        .post(
+            "/add_user_map",
+            json!({"opsgenie_id": "opsgenie-alice-old", "slack_id": SLACK_USER_ID}),
+        )
+        .await;
+    assert_eq!(status, StatusCode::OK);
+
+    let (status, report) = app.post("/user_mappings/auto", json!({})).await;
+    assert_eq!(status, StatusCode::OK, "{}", report);
+    assert_eq!(report["created"], json!([]));
+    let conflict = &report["conflicts"][0];
+    assert_eq!(conflict["opsgenie_id"], OPSGENIE_USER_ID);
+    assert_eq!(conflict["slack_id"], SLACK_USER_ID);
+    assert_eq!(conflict["mapped_to"], "opsgenie-alice-old");
+
+    let (_, list) = app.get("/list_user_mappings").await;
+    assert_eq!(list["user_mappings"].as_array().unwrap().len(), 1);
+}
+
+#[actix_web::test]
+async fn slack_users_link_themselves_by_email() {
+    let app = TestApp::start().await;
+
+    let reply = app.slash_command(SLACK_USER_ID, "whoami").await;
+    assert!(reply.contains("aren't linked"), "{}", reply);
+
+    let reply = app.slash_command(SLACK_USER_ID, "link").await;
+    assert!(reply.starts_with("Linked you to *Alice*"), "{}", reply);
+    let (_, body) = app
+        .get(&format!(
+            "/get_slack_user_mapping?slack_user_id={}",
+            SLACK_USER_ID
+        ))
+        .await;
+    assert_eq!(body["user_mapping"]["opsgenie_user_id"], OPSGENIE_USER_ID);
+
+    let reply = app.slash_command(SLACK_USER_ID, "whoami").await;
+    assert!(reply.contains("alice@example.com"), "{}", reply);
+
+    let reply = app.slash_command(SLACK_USER_ID, "unlink").await;
+    assert!(reply.starts_with("Unlinked you"), "{}", reply);
+    let (_, list) = app.get("/list_user_mappings").await;
+    assert_eq!(list["user_mappings"], json!([]));
+}
+
+#[actix_web::test]
+async fn slack_users_only_link_accounts_with_their_email() {
+    let app = TestApp::start().await;
+    app.fake_apis.add_slack_user("U0BOB", "bob", "Bob");
+    app.fake_apis
+        .add_opsgenie_user("opsgenie-bob", "robert@example.com", "Bob");
+
+    let reply = app.slash_command("U0BOB", "link").await;
+    assert!(reply.contains("couldn't find"), "{}", reply);
+
+    // Naming an account doesn't get around the email check
+    let reply = app.slash_command("U0BOB", "link opsgenie-bob").await;
+    assert!(
+        reply.starts_with("None of the on-call accounts"),
+        "{}",
+        reply
+    );
+    let (_, list) = app.get("/list_user_mappings").await;
+    assert_eq!(list["user_mappings"], json!([]));
+
+    app.fake_apis.set_slack_email("U0BOB", "robert@example.com");
+    let reply = app.slash_command("U0BOB", "link opsgenie").await;
+    assert!(reply.starts_with("Linked you"), "{}", reply);
+
+    // Accounts linked to someone else can't be taken over
+    app.fake_apis
+        .set_slack_email(SLACK_USER_ID, "robert@example.com");
+    let reply = app.slash_command(SLACK_USER_ID, "link").await;
+    assert!(reply.contains("already linked to <@U0BOB>"), "{}", reply);
+}
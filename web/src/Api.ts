@@ -31,6 +31,7 @@ export interface UserMapping {
   id: number;
   opsgenie_user_id: string;
   slack_user_id: string;
+  oncall_provider: string;
 }
 
 export interface Oncall {
@@ -47,6 +48,13 @@ export interface OncallSync {
   user_group_handle: string;
 }
 
+export interface SyncedUserGroup {
+  user_group_id: string;
+  user_group_name: string;
+  user_group_handle: string;
+  syncs: OncallSync[];
+}
+
 export interface UserGroup {
   id: string;
   name: string;
@@ -92,10 +100,25 @@ export interface ListUserGroupsResponse {
 }
 
 export interface ListSyncsResponse {
-  syncs?: OncallSync[] | null;
+  user_groups?: SyncedUserGroup[] | null;
   error?: string | null;
 }
 
+export interface UnmappedOncall {
+  oncall_provider: string;
+  oncall_id: string;
+  oncall_name?: string | null;
+  user_id: string;
+  user_name?: string | null;
+  since: number;
+  warned_at?: number | null;
+}
+
+export interface ListUnmappedOncallsResponse {
+  unmapped_oncalls?: UnmappedOncall[];
+  error?: string;
+}
+
 export interface ListNotificationsResponse {
   notifications?: Notification[];
   error?: string;
@@ -106,7 +129,7 @@ export interface GetSlackUserMappingResponse {
 }
 
 export interface GetNotificationForSlackChannelResponse {
-  notification?: Notification | null;
+  notifications?: Notification[];
   error?: string;
 }
 
@@ -121,15 +144,35 @@ export interface AddUserMapResponse {
   error?: string | null;
 }
 
+export interface AutoMappedUser {
+  oncall_provider: string;
+  opsgenie_id: string;
+  chat_provider: string;
+  slack_id: string;
+  email: string;
+}
+
+export interface AutoMappingConflict extends AutoMappedUser {
+  mapped_to: string;
+}
+
+export interface AutoMapUsersResponse {
+  dry_run?: boolean;
+  created?: AutoMappedUser[];
+  conflicts?: AutoMappingConflict[];
+  unmatched?: OpsgenieUser[];
+  errors?: string[];
+  error?: string | null;
+}
+
 export interface SyncedWithResponse {
   syncs?: OncallSync[] | null;
   error?: string | null;
 }
 
 export interface AddSyncResponse {
-  id?: number | null;
-  oncall_id?: string | null;
   user_group_id?: string | null;
+  syncs?: OncallSync[] | null;
   error?: string | null;
 }
 
@@ -156,34 +199,67 @@ export interface RemoveNotificationResponse {
   error?: string | null;
 }
 
+const API_TOKEN_KEY = "api_token";
+
+/**
+ * fetch() with the API token stored in the browser. When the API rejects the
+ * token, the user is asked for a new one and the request is retried once.
+ */
+function apiFetch(
+  input: string,
+  init: RequestInit = {},
+  retry: boolean = true
+): Promise<Response> {
+  const token = localStorage.getItem(API_TOKEN_KEY);
+  const headers = new Headers(init.headers);
+  if (token) {
+    headers.set("Authorization", `Bearer ${token}`);
+  }
+  return fetch(input, { ...init, headers }).then((res) => {
+    if (res.status !== 401 || !retry) {
+      return res;
+    }
+    const newToken = window.prompt("Enter your oncall bot API token");
+    if (!newToken) {
+      return res;
+    }
+    localStorage.setItem(API_TOKEN_KEY, newToken.trim());
+    return apiFetch(input, init, false);
+  });
+}
+
 export function ListOpsgenieUsers(): Promise<ListOpsgenieUsersResponse> {
-  return fetch("/api/list_opsgenie_users").then((res) => res.json());
+  return apiFetch("/api/list_opsgenie_users").then((res) => res.json());
 }
 
 export function ListSlackUsers(): Promise<ListSlackUsersResponse> {
-  return fetch("/api/list_slack_users").then((res) => res.json());
+  return apiFetch("/api/list_slack_users").then((res) => res.json());
 }
 
 export function ListSlackChannels(): Promise<ListSlackChannelsResponse> {
-  return fetch("/api/list_slack_channels").then((res) => res.json());
+  return apiFetch("/api/list_slack_channels").then((res) => res.json());
 }
 
 export function ListUserMappings(): Promise<ListUserMappingsResponse> {
-  return fetch("/api/list_user_mappings").then((res) => res.json());
+  return apiFetch("/api/list_user_mappings").then((res) => res.json());
 }
 
 export function ListOncalls(): Promise<ListOncallsResponse> {
-  return fetch("/api/list_oncalls").then((res) => res.json());
+  return apiFetch("/api/list_oncalls").then((res) => res.json());
+}
+
+export function ListUnmappedOncalls(): Promise<ListUnmappedOncallsResponse> {
+  return apiFetch("/api/unmapped_oncalls").then((res) => res.json());
 }
 
 export function ListNotifications(): Promise<ListNotificationsResponse> {
-  return fetch("/api/notification/list").then((res) => res.json());
+  return apiFetch("/api/notification/list").then((res) => res.json());
 }
 
 export function GetSlackUserMapping(
   slack_user_id: string
 ): Promise<GetSlackUserMappingResponse> {
-  return fetch(
+  return apiFetch(
     `/api/get_slack_user_mapping?slack_user_id=${encodeURIComponent(
       slack_user_id
     )}`
@@ -193,7 +269,7 @@ export function GetSlackUserMapping(
 export function GetNotificationForSlackChannel(
   slack_channel_id: string
 ): Promise<GetNotificationForSlackChannelResponse> {
-  return fetch(
+  return apiFetch(
     `/api/notifications/slack?slack_channel_id=${encodeURIComponent(
       slack_channel_id
     )}`
@@ -203,7 +279,7 @@ export function GetNotificationForSlackChannel(
 export function GetNotificationForOncall(
   oncall_id: string
 ): Promise<GetNotificationForOncallResponse> {
-  return fetch(
+  return apiFetch(
     `/api/notifications/oncall?oncall_id=${encodeURIComponent(oncall_id)}`
   ).then((res) => res.json());
 }
@@ -212,7 +288,7 @@ export function AddUserMap(
   slack_user_id: string,
   opsgenie_user_id: string
 ): Promise<AddUserMapResponse> {
-  return fetch("/api/add_user_map", {
+  return apiFetch("/api/add_user_map", {
     method: "POST",
     headers: { "Content-Type": "application/json" },
     body: JSON.stringify({
@@ -222,25 +298,37 @@ export function AddUserMap(
   }).then((res) => res.json());
 }
 
+export function AutoMapUsers(
+  dry_run: boolean
+): Promise<AutoMapUsersResponse> {
+  return apiFetch("/api/user_mappings/auto", {
+    method: "POST",
+    headers: { "Content-Type": "application/json" },
+    body: JSON.stringify({
+      dry_run,
+    }),
+  }).then((res) => res.json());
+}
+
 export function SyncedWith(oncall_id: string): Promise<SyncedWithResponse> {
-  return fetch(
+  return apiFetch(
     `/api/synced_with?oncall_id=${encodeURIComponent(oncall_id)}`
   ).then((res) => res.json());
 }
 
 export function ListSyncs(): Promise<ListSyncsResponse> {
-  return fetch("/api/list_syncs").then((res) => res.json());
+  return apiFetch("/api/list_syncs").then((res) => res.json());
 }
 
 export function AddSync(
-  oncall_id: string,
+  oncall_ids: string[],
   user_group_id: string
 ): Promise<AddSyncResponse> {
-  return fetch("/api/add_sync", {
+  return apiFetch("/api/add_sync", {
     method: "POST",
     headers: { "Content-Type": "application/json" },
     body: JSON.stringify({
-      oncall_id,
+      oncall_ids,
       user_group_id,
     }),
   }).then((res) => res.json());
@@ -250,7 +338,7 @@ export function AddNotification(
   oncall_id: string,
   slack_channel_id: string
 ): Promise<AddNotificationResponse> {
-  return fetch("/api/notifications/add", {
+  return apiFetch("/api/notifications/add", {
     method: "POST",
     headers: { "Content-Type": "application/json" },
     body: JSON.stringify({
@@ -261,13 +349,13 @@ export function AddNotification(
 }
 
 export function ListUserGroups(): Promise<ListUserGroupsResponse> {
-  return fetch("/api/list_user_groups").then((res) => res.json());
+  return apiFetch("/api/list_user_groups").then((res) => res.json());
 }
 
 export function RemoveUserMap(
   user_mapping_id: number
 ): Promise<RemoveUserMapResponse> {
-  return fetch("/api/remove_user_map", {
+  return apiFetch("/api/remove_user_map", {
     method: "POST",
     headers: { "Content-Type": "application/json" },
     body: JSON.stringify({
@@ -279,7 +367,7 @@ export function RemoveUserMap(
 export function RemoveSync(
   oncall_sync_id: number
 ): Promise<RemoveSyncResponse> {
-  return fetch("/api/remove_sync", {
+  return apiFetch("/api/remove_sync", {
     method: "POST",
     headers: { "Content-Type": "application/json" },
     body: JSON.stringify({
@@ -291,7 +379,7 @@ export function RemoveSync(
 export function RemoveNotification(
   notification_id: number
 ): Promise<RemoveNotificationResponse> {
-  return fetch("/api/notifications/remove", {
+  return apiFetch("/api/notifications/remove", {
     method: "POST",
     headers: { "Content-Type": "application/json" },
     body: JSON.stringify({
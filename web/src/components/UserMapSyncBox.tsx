@@ -171,7 +171,7 @@ export default function UserMapSyncBox(props: UserMapSyncBoxProps) {
       let oncall_id = props.oncall.id;
       setOncallCardAdding(true);
 
-      AddSync(props.oncall.id, selectedId)
+      AddSync([props.oncall.id], selectedId)
         .then(
           (result) => {
             if (result.error !== undefined && result.error !== null) {